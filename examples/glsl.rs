@@ -1,10 +1,8 @@
 extern crate bullet;
 use bullet::builder::Builder;
+use bullet::vm::glsl::glsl;
 
-#[cfg(feature="glsl")]
 fn main() {
-    use bullet::vm::glsl::glsl;
-
     let expr = "(x+y)/2";
     println!("Expr: {}\n", expr);
     let builder = Builder::new();
@@ -17,6 +15,3 @@ fn main() {
     }
 }
 
-
-#[cfg(not(feature="glsl"))]
-fn main() {}
@@ -3,7 +3,7 @@ extern crate bullet;
 
 
 fn main() {
-    #[cfg(feature="ptx")]
+    #[cfg(feature="nvidia")]
     {
     use std::env;
     use bullet::builder::Builder;
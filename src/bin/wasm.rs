@@ -1,10 +1,9 @@
 #[cfg(feature="wasm")]
 fn main() {
     use std::fs;
-    use itertools::Itertools;
     use bullet::builder::Builder;
-    use bullet::vm::wasm::Wasm;
-    use bullet::compiler::Compiler;
+    use bullet::vm::wasm::{Wasm, Lanes};
+    use bullet::compiler::variables;
 
     env_logger::init();
     let mut args = std::env::args().skip(1);
@@ -13,7 +12,16 @@ fn main() {
     let builder = Builder::new();
     let root = builder.parse(&input).expect("can't parse expr");
     println!("{}", root);
-    let data = Wasm::compile(&root, &["x", "y"]).expect("can't compile");
+
+    // inputs given on the command line, or all variables in alphabetical order
+    let mut inputs: Vec<String> = args.collect();
+    if inputs.len() == 0 {
+        inputs = variables(&[root.clone()]);
+    }
+    println!("inputs: {}", inputs.join(", "));
+    let inputs: Vec<&str> = inputs.iter().map(|s| s.as_str()).collect();
+
+    let data = Wasm::module(&[("f", root)], &inputs, Lanes::Scalar).expect("can't compile");
 
     fs::write(&output_file, &data).expect("can't write output");
 }
@@ -21,4 +29,4 @@ fn main() {
 #[cfg(not(feature="wasm"))]
 fn main() {
 
-}
\ No newline at end of file
+}
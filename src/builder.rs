@@ -1,11 +1,15 @@
 use crate::prelude::*;
 use std::cell::RefCell;
-use crate::func::Func;
+use crate::func::{Func, Transient};
 use crate::func::Transient::*;
 use crate::poly::Poly;
+use crate::groebner::Order;
+use crate::simplify::Assumptions;
+use crate::rules::Rules;
 use crate::lang::ExprParser;
 use std::collections::HashMap;
 use std::iter::once;
+use std::slice;
 use crate::diff::diff;
 
 pub type NodeResult = Result<NodeRc, Error>;
@@ -22,6 +26,30 @@ fn poly(node: NodeRc) -> Poly {
     Poly::from_node(node)
 }
 
+/// the arguments of a call: the parts of a tuple or the node itself
+fn args(node: &NodeRc) -> &[NodeRc] {
+    match **node {
+        Node::Tuple(ref parts) => &parts[..],
+        _ => slice::from_ref(node)
+    }
+}
+fn name_arg(node: &NodeRc) -> Result<&str, Error> {
+    match **node {
+        Node::Var(ref name) => Ok(name.as_str()),
+        _ => Err(Error::Other(format!("expected a name, found {}", node)))
+    }
+}
+fn names_arg(node: &NodeRc) -> Result<Vec<&str>, Error> {
+    args(node).iter().map(name_arg).collect()
+}
+fn int_arg(node: &NodeRc) -> Result<u32, Error> {
+    let n = match **node {
+        Node::Poly(ref p) => p.to_int().and_then(|i| i.as_i64()),
+        _ => None
+    };
+    n.filter(|&i| i >= 0).map(|i| i as u32).ok_or(Error::IntegerError)
+}
+
 impl Builder {
     pub fn new() -> Builder {
         let mut b = Builder {
@@ -33,10 +61,20 @@ impl Builder {
     }
     fn init(&mut self) {
         let x = self.var("x");
-        for &(n, f) in [("sin", Sin), ("cos", Cos), ("exp", Exp), ("log", Log), ("ln", Log)].iter() {
-            let f = self.func(Func::Transient(f), x.clone()).unwrap();
-            self.define(n, &["x"], f);
+        for &f in Transient::ALL.iter().filter(|&&f| f != Gamma) {
+            let (args, g): (&[&str], _) = match f.arity() {
+                1 => (&["x"], x.clone()),
+                _ => (&["a", "b"], self.tuple(vec![Ok(self.var("a")), Ok(self.var("b"))]).unwrap())
+            };
+            let node = self.func(f.into(), g).unwrap();
+            self.define(f.name(), args, node);
         }
+        let ln = self.func(Log.into(), x.clone()).unwrap();
+        self.define("ln", &["x"], ln);
+        let sqrt = self.sqrt(x.clone()).unwrap();
+        self.define("sqrt", &["x"], sqrt);
+        let cbrt = self.cbrt(x.clone()).unwrap();
+        self.define("cbrt", &["x"], cbrt);
     }
     pub fn define(&mut self, name: &str, args: &[&str], node: NodeRc) {
         let def = Node::Op(Func::Definition(
@@ -111,7 +149,10 @@ impl Builder {
 
     /// a / b
     pub fn div(&self, a: NodeRc, b: NodeRc) -> NodeResult {
-        self.uniform(a, b, |a, b| Ok(self.poly(poly(a) * poly(b).pow_i(self, -1)?)))
+        self.uniform(a, b, |a, b| {
+            let (n, d) = poly(a).cancel(poly(b));
+            Ok(self.poly(n * d.pow_i(self, -1)?))
+        })
     }
 
     /// - a
@@ -123,11 +164,11 @@ impl Builder {
     pub fn pow(&self, a: NodeRc, b: NodeRc) -> NodeResult {
         self.uniform(a, b, |a, b| {
             if let Node::Poly(ref p) = *b {
-                if let Some(i) = p.to_int() {          
-                    return self.pow_i(a, i);
+                if let Some(r) = p.as_rational() {
+                    return self.pow_r(a, r);
                 }
             }
-            
+
             let g = self.func(Log.into(), a)?;
             self.func(Exp.into(), self.mul(g, b)?)
         })
@@ -137,9 +178,145 @@ impl Builder {
         where I: Into<Int>
     {
         let i: Int = i.into();
-        self.uniform_one(a, i, |a, i| Ok(self.poly(poly(a).pow(self, i)?)))
+        self.pow_r(a, i.into())
+    }
+    /// a ^ r
+    pub fn pow_r(&self, a: NodeRc, r: Rational) -> NodeResult {
+        self.uniform_one(a, r, |a, r| Ok(self.poly(poly(a).pow(self, r)?)))
+    }
+    /// √a, written as a^(1/2)
+    pub fn sqrt(&self, a: NodeRc) -> NodeResult {
+        self.pow_r(a, Rational::new(1.into(), 2.into()))
+    }
+    /// ∛a, written as a^(1/3)
+    pub fn cbrt(&self, a: NodeRc) -> NodeResult {
+        self.pow_r(a, Rational::new(1.into(), 3.into()))
     }
     
+    /// rewrite `node` into an equivalent form that needs fewer operations to evaluate
+    pub fn optimize_for_eval(&self, node: NodeRc) -> NodeResult {
+        crate::cost::optimize(self, &node)
+    }
+    /// rewrite all polynomials in `node` into nested Horner form
+    pub fn horner(&self, node: NodeRc) -> NodeResult {
+        crate::horner::rewrite(self, &node, crate::horner::Scheme::Horner)
+    }
+    /// rewrite all polynomials in `node` using Estrin's scheme
+    pub fn estrin(&self, node: NodeRc) -> NodeResult {
+        crate::horner::rewrite(self, &node, crate::horner::Scheme::Estrin)
+    }
+    /// factor all polynomials in `node` over the rationals
+    pub fn factor(&self, node: NodeRc) -> NodeResult {
+        crate::factor::factor_node(self, &node)
+    }
+    /// combine the rational functions in `node` over a common, factored denominator
+    pub fn together(&self, node: NodeRc) -> NodeResult {
+        crate::ratfunc::together(self, &node, false)
+    }
+    /// like `together`, but with the denominator multiplied out
+    pub fn cancel(&self, node: NodeRc) -> NodeResult {
+        crate::ratfunc::together(self, &node, true)
+    }
+    /// apply the identities of the elementary functions that hold for all real arguments
+    pub fn simplify(&self, node: NodeRc) -> NodeResult {
+        crate::simplify::simplify(self, &node, &Assumptions::new())
+    }
+    /// like `simplify`, but also split logarithms of factors that `assume` proves positive
+    pub fn simplify_assuming(&self, node: NodeRc, assume: &Assumptions) -> NodeResult {
+        crate::simplify::simplify(self, &node, assume)
+    }
+    /// expand sin, cos, sinh and cosh of sums
+    pub fn expand_trig(&self, node: NodeRc) -> NodeResult {
+        crate::simplify::expand_trig(self, &node)
+    }
+    /// apply `rules` until none of them matches anymore
+    pub fn rewrite(&self, node: NodeRc, rules: &Rules) -> NodeResult {
+        rules.apply(self, &node)
+    }
+    /// partial fraction decomposition in `var`
+    pub fn apart(&self, node: NodeRc, var: &str) -> NodeResult {
+        crate::ratfunc::apart(self, &node, &self.var(var))
+    }
+    /// multiply out all products and positive powers of polynomials in `node`
+    pub fn expand(&self, node: NodeRc) -> NodeResult {
+        Ok(match *node {
+            Node::Poly(ref p) => {
+                let mut sum = Poly::zero();
+                for (base, fac) in p.factors() {
+                    let mut term = Poly::rational(fac.clone());
+                    for &(ref v, ref n) in base.iter() {
+                        let v = self.expand(v.clone())?;
+                        term = term * match (&*v, n.as_i32()) {
+                            (&Node::Poly(ref q), Some(n)) if n > 0 => q.clone().pow_n(n as u32),
+                            _ => Poly::product(vec![(v.clone(), n.clone())], 1.into())
+                        };
+                    }
+                    sum = sum + term;
+                }
+                self.poly(sum)
+            },
+            Node::Apply(ref f, ref g) => self.intern(Node::Apply(f.clone(), self.expand(g.clone())?)),
+            Node::Tuple(ref parts) => self.tuple(parts.iter().map(|p| self.expand(p.clone())))?,
+            Node::Var(_) | Node::Op(_) => node.clone()
+        })
+    }
+    /// `Σ cₖ varᵏ` with each coefficient `cₖ` kept together
+    pub fn collect(&self, node: NodeRc, var: &str) -> NodeResult {
+        let v = self.var(var);
+        self.uniform_one(self.expand(node)?, v, |a, v| {
+            let mut sum = Poly::zero();
+            for (k, c) in poly(a).coefficients(&v) {
+                let power = Poly::product(vec![(v.clone(), Rational::from(k as i64))], 1.into());
+                sum = sum + match c.factors().count() {
+                    1 => c * power,
+                    _ => Poly::product(vec![(self.poly(c), 1.into())], 1.into()) * power
+                };
+            }
+            Ok(self.poly(sum))
+        })
+    }
+    /// the coefficient of `varⁿ` in the expanded `node`
+    pub fn coeff(&self, node: NodeRc, var: &str, n: u32) -> NodeResult {
+        let v = self.var(var);
+        self.uniform_one(self.expand(node)?, v, |a, v| {
+            Ok(self.poly(poly(a).coefficients(&v).remove(&n).unwrap_or_else(Poly::zero)))
+        })
+    }
+    /// the highest power of `var` in the expanded `node`
+    pub fn degree(&self, node: NodeRc, var: &str) -> NodeResult {
+        let v = self.var(var);
+        self.uniform_one(self.expand(node)?, v, |a, v| Ok(self.int(poly(a).degree_in(&v) as i64)))
+    }
+    /// the greatest term of the expanded `node` in graded reverse lexicographic order
+    pub fn leading_term(&self, node: NodeRc) -> NodeResult {
+        self.uniform_one(self.expand(node)?, (), |a, ()| {
+            Ok(self.poly(match poly(a).leading_term() {
+                Some((base, fac)) => Poly::product(base.clone(), fac.clone()),
+                None => Poly::zero()
+            }))
+        })
+    }
+    /// the real roots of the polynomial `node` in `var` as a tuple
+    pub fn solve(&self, node: NodeRc, var: &str) -> NodeResult {
+        crate::solve::solve(self, &node, var)
+    }
+    /// the real solutions of the system `eqs = 0` (a tuple of polynomials in `vars`)
+    pub fn solve_system(&self, eqs: NodeRc, vars: &[&str]) -> NodeResult {
+        crate::solve::solve_system(self, &eqs, vars)
+    }
+    /// the reduced Gröbner basis of the ideal generated by the tuple `ps`
+    pub fn groebner(&self, ps: NodeRc, vars: &[&str], order: Order) -> NodeResult {
+        let parts = match *ps {
+            Node::Tuple(ref parts) => parts.clone(),
+            _ => vec![ps.clone()]
+        };
+        let polys = parts.into_iter()
+            .map(|n| Ok(Poly::from_node(self.expand(n)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let vars: Vec<NodeRc> = vars.iter().map(|v| self.var(v)).collect();
+        let basis = crate::groebner::groebner(&polys, &vars, order)?;
+        self.tuple(basis.into_iter().map(|p| Ok(self.poly(p))))
+    }
     /// a!
     pub fn factorial(&self, _a: NodeRc) -> NodeResult {
         todo!("factorial")
@@ -159,6 +336,10 @@ impl Builder {
     pub fn var(&self, name: &str) -> NodeRc {
         self.intern(Node::Var(name.into()))
     }
+    /// π, as the name `π`, which evaluates and compiles to the constant unless it is set
+    pub fn pi(&self) -> NodeRc {
+        self.var("π")
+    }
     pub fn named(&self, name: &str) -> NodeRc {
         match self.defs.get(name) {
             Some(n) => n.clone(),
@@ -166,6 +347,33 @@ impl Builder {
         }
     }
 
+    /// the builder functions callable by name, e.g. `collect(f, x)`
+    pub fn call(&self, name: &str, args: Vec<NodeResult>) -> NodeResult {
+        let args = args.into_iter().collect::<Result<Vec<_>, _>>()?;
+        match (name, &args[..]) {
+            ("expand", &[ref e]) => self.expand(e.clone()),
+            ("simplify", &[ref e]) => self.simplify(e.clone()),
+            ("factor", &[ref e]) => self.factor(e.clone()),
+            ("collect", &[ref e, ref v]) => name_arg(v).and_then(|v| self.collect(e.clone(), v)),
+            ("coeff", &[ref e, ref v, ref n]) => name_arg(v).and_then(|v| self.coeff(e.clone(), v, int_arg(n)?)),
+            ("degree", &[ref e, ref v]) => name_arg(v).and_then(|v| self.degree(e.clone(), v)),
+            ("solve", &[ref e, ref v]) => match **v {
+                Node::Tuple(_) => names_arg(v).and_then(|v| self.solve_system(e.clone(), &v)),
+                _ => name_arg(v).and_then(|v| self.solve(e.clone(), v))
+            },
+            ("groebner", &[ref e, ref v]) => names_arg(v).and_then(|v| self.groebner(e.clone(), &v, Order::Lex)),
+            ("groebner", &[ref e, ref v, ref o]) => name_arg(o).and_then(|o| match Order::from_name(o) {
+                Some(order) => self.groebner(e.clone(), &names_arg(v)?, order),
+                None => Err(Error::Other(format!("unknown monomial order {}", o)))
+            }),
+            ("expand", a) | ("simplify", a) | ("factor", a) => Err(Error::ShapeMismatch(1, a.len())),
+            ("collect", a) | ("degree", a) | ("solve", a) => Err(Error::ShapeMismatch(2, a.len())),
+            ("coeff", a) => Err(Error::ShapeMismatch(3, a.len())),
+            ("groebner", a) => Err(Error::ShapeMismatch(2, a.len())),
+            _ => Err(Error::Other(format!("unknown function {}", name)))
+        }
+    }
+
     /// magic 'apply' function
     pub fn apply(&self, left: NodeRc, right: NodeRc) -> NodeResult {
         match *left {
@@ -198,7 +406,7 @@ impl Builder {
         self.mul(left, right)
     }
 
-    fn substitute(&self, node: &NodeRc, map: &HashMap<&str, NodeRc>) -> NodeResult {
+    pub(crate) fn substitute(&self, node: &NodeRc, map: &HashMap<&str, NodeRc>) -> NodeResult {
         match **node {
             Node::Var(ref name) => match map.get(&**name) {
                 Some(node) => Ok(node.clone()),
@@ -210,7 +418,7 @@ impl Builder {
                     self.product(
                         once(Ok(self.rational(fac.clone())))
                             .chain(
-                                base.iter().map(|&(ref v, ref p)| self.pow_i(
+                                base.iter().map(|&(ref v, ref p)| self.pow_r(
                                     self.substitute(v, map)?,
                                     p.clone()
                                 ))
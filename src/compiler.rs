@@ -1,13 +1,74 @@
 use crate::prelude::*;
 use std::collections::hash_map::{HashMap, Entry};
+use std::collections::HashSet;
 use crate::node::{NodeRc, Node};
 use crate::func::{Func, Transient};
-use crate::vm::Vm;
+use crate::vm::{Vm, Round};
+
+/// the names of all variables used in `nodes`, sorted. π is a constant, not a variable.
+pub fn variables(nodes: &[NodeRc]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut vars = vec![];
+    let mut queue: Vec<&Node> = nodes.iter().map(|n| &**n).collect();
+    while let Some(node) = queue.pop() {
+        if !seen.insert(node) {
+            continue;
+        }
+        match *node {
+            Node::Poly(ref p) => {
+                for (base, _) in p.factors() {
+                    queue.extend(base.iter().map(|b| &*b.0));
+                }
+            },
+            Node::Apply(ref f, ref g) => {
+                queue.push(f);
+                queue.push(g);
+            },
+            Node::Var(ref name) if name == "π" => {},
+            Node::Var(ref name) => vars.push(name.clone()),
+            Node::Tuple(ref parts) => queue.extend(parts.iter().map(|n| &**n)),
+            Node::Op(_) => {}
+        }
+    }
+    vars.sort();
+    vars
+}
+
+type Monomial<'a> = &'a [(NodeRc, Rational)];
+
+/// how to compute x^n
+#[derive(Copy, Clone, Debug)]
+enum Step {
+    Direct,            // pow_n(x, n)
+    Mul(u32, u32),     // x^p * x^q
+    MulPow(u32, u32),  // x^p * pow_n(x, q)
+}
+
+/// number of multiplications `Vm::pow_n` needs
+fn pow_cost(n: u32) -> u32 {
+    match n {
+        0 | 1 => 0,
+        n => 31 - n.leading_zeros() + n.count_ones() - 1
+    }
+}
+
+/// a monomial that is just a node
+fn is_trivial(m: Monomial) -> bool {
+    m.len() == 1 && m[0].1 == 1
+}
 
 pub struct Compiler<'a, V: Vm + 'a> {
     uses: HashMap<&'a Node, usize>,
     storage: HashMap<&'a Node, V::Storage>,
     sources: HashMap<&'a str, V::Var>,
+
+    // common subexpressions inside polynomials
+    monomials: HashMap<Monomial<'a>, usize>,
+    monomial_storage: HashMap<Monomial<'a>, V::Storage>,
+    powers: HashMap<&'a Node, HashMap<u32, usize>>, // exponent (≥ 2) → number of monomials using it
+    chains: HashMap<(&'a Node, u32), (Step, usize)>,
+    power_storage: HashMap<(&'a Node, u32), V::Storage>,
+
     vm: &'a mut V
 }
 impl<'a, V: Vm + 'a> Compiler<'a, V> {
@@ -21,13 +82,46 @@ impl<'a, V: Vm + 'a> Compiler<'a, V> {
                     match *node {
                         Node::Poly(ref p) => {
                             for (base, _) in p.factors() {
-                                queue.extend(base.iter().map(|b| &*b.0));
+                                if base.is_empty() {
+                                    continue;
+                                }
+                                if is_trivial(base) {
+                                    queue.push(&*base[0].0);
+                                    continue;
+                                }
+                                // equal monomials are only computed once
+                                match self.monomials.entry(&base[..]) {
+                                    Entry::Occupied(mut o) => {
+                                        *o.get_mut() += 1;
+                                        continue;
+                                    },
+                                    Entry::Vacant(v) => {
+                                        v.insert(1);
+                                    }
+                                }
+                                for &(ref v, ref n) in base.iter() {
+                                    // roots are computed from v itself
+                                    let n = match n.to_int() {
+                                        Some(n) => n.as_i32().ok_or(Error::Overflow)?.abs() as u32,
+                                        None => 1
+                                    };
+                                    if n >= 2 {
+                                        *self.powers.entry(&**v).or_insert_with(HashMap::new)
+                                            .entry(n).or_insert(0) += 1;
+                                    }
+                                    queue.push(&**v);
+                                }
                             }
                         },
                         Node::Apply(ref f, ref g) => match **f {
-                            Node::Op(Func::Transient(_)) => queue.push(g),
+                            // the arguments of binary functions are used directly, not as a tuple
+                            Node::Op(Func::Transient(_)) => match **g {
+                                Node::Tuple(ref parts) => queue.extend(parts.iter().map(|n| &**n)),
+                                _ => queue.push(g)
+                            },
                             _ => bug!("only transients are allowed as left argument of apply"),
                         }
+                        Node::Var(ref name) if name == "π" => {},
                         Node::Var(ref name) => vars.push(name.as_str()),
                         Node::Tuple(ref parts) => queue.extend(parts.iter().map(|n| &**n)),
                         Node::Op(_) => bug!("no transients allowd outside of apply")
@@ -39,11 +133,59 @@ impl<'a, V: Vm + 'a> Compiler<'a, V> {
         Ok(vars)
     }
 
+    /// decide how the powers of every node are computed, so that they can build on each other
+    /// (x³ = x² · x, x⁵ = x³ · x²), and fix up the use counts accordingly
+    fn plan(&mut self) {
+        for (&v, occurrences) in self.powers.iter() {
+            let mut exponents: Vec<u32> = occurrences.keys().cloned().collect();
+            exponents.sort();
+
+            let mut avail = vec![1];
+            let mut chain_uses: HashMap<u32, usize> = HashMap::new();
+            let mut steps = Vec::with_capacity(exponents.len());
+            for &e in exponents.iter() {
+                // reuse the largest power we already have
+                let step = match avail.iter().rev().find(|&&p| p > 1 && p < e && avail.contains(&(e - p))) {
+                    Some(&p) => Step::Mul(p, e - p),
+                    None => match avail.iter().rev().find(|&&p| p > 1 && p < e) {
+                        Some(&p) if 1 + pow_cost(e - p) <= pow_cost(e) => Step::MulPow(p, e - p),
+                        _ => Step::Direct
+                    }
+                };
+                let used: &[u32] = match step {
+                    Step::Direct => &[1],
+                    Step::Mul(p, q) => &[p, q],
+                    Step::MulPow(p, _) => &[p, 1]
+                };
+                for &k in used {
+                    *chain_uses.entry(k).or_insert(0) += 1;
+                }
+                steps.push((e, step));
+                avail.push(e);
+            }
+
+            for (e, step) in steps {
+                let uses = occurrences[&e] + chain_uses.get(&e).cloned().unwrap_or(0);
+                self.chains.insert((v, e), (step, uses));
+            }
+
+            // v itself is now only used by the chains instead of by every monomial with a power of it
+            let replaced: usize = occurrences.values().sum();
+            let uses = self.uses.get_mut(v).expect("power of an unvisited node");
+            *uses = *uses + chain_uses[&1] - replaced;
+        }
+    }
+
     pub fn new(vm: &'a mut V) -> Compiler<'a, V> {
         Compiler {
             uses: HashMap::new(),
             storage: HashMap::new(),
             sources: HashMap::new(),
+            monomials: HashMap::new(),
+            monomial_storage: HashMap::new(),
+            powers: HashMap::new(),
+            chains: HashMap::new(),
+            power_storage: HashMap::new(),
             vm: vm
         }
     }
@@ -52,6 +194,7 @@ impl<'a, V: Vm + 'a> Compiler<'a, V> {
         let mut comp = Compiler::new(vm);
         let mut vars = comp.visit(root)?;
         vars.sort();
+        comp.plan();
 
         for name in vars {
             let var = comp.vm.make_source(name);
@@ -70,16 +213,14 @@ impl<'a, V: Vm + 'a> Compiler<'a, V> {
         for n in nodes.iter() {
             comp.visit(&**n)?;
         }
+        comp.plan();
         
         for &name in vars.iter() {
             let var = comp.vm.make_source(name);
-	    println!("source {} @ {:?}", name, var);
+            debug!("source {} @ {:?}", name, var);
             comp.sources.insert(name, var);
         }
 
-        for (n, u) in comp.uses.iter() {
-            println!("{}: {}", u, n);
-        }
         // build it
         let mut vars = Vec::with_capacity(nodes.len());
         for n in nodes.iter() {
@@ -92,12 +233,14 @@ impl<'a, V: Vm + 'a> Compiler<'a, V> {
         if let Some(stored) = self.storage.get(node) {
             return Ok(self.vm.load(stored)); // already computed
         }
-        println!("{}", node);
+        if let Node::Poly(ref poly) = *node {
+            if let Some(i) = poly.to_int() {
+                return Ok(self.vm.make_int(i.as_i64().ok_or(Error::Overflow)?));
+            }
+        }
+        self.vm.enter(node);
         let mut var = match *node {
             Node::Poly(ref poly) => {
-                if let Some(i) = poly.to_int() {
-                    return Ok(self.vm.make_int(i.as_i64().ok_or(Error::Overflow)?));
-                }
                 let mut sum = Vec::new();
                 for (base, fac) in poly.factors() {
                     let fac = match fac.as_i64() {
@@ -107,24 +250,8 @@ impl<'a, V: Vm + 'a> Compiler<'a, V> {
                     };
                     let base = match base.len() {
                         0 => None,
-                        _ => {
-                            let mut prod = Vec::with_capacity(base.len());
-                            for &(ref v, ref n) in base.iter() {
-                                let v = self.generate(v)?;
-                                let n = n.as_i32().ok_or(Error::Overflow)?;
-                                prod.push(match n {
-                                    0 => continue, // skip it
-                                    1 => v,
-                                    i if i > 0 => self.vm.pow_n(v, i as u32),
-                                    i => {
-                                        let p = self.vm.pow_n(v, -i as u32);
-                                        self.vm.inv(p)
-                                    }
-                                });
-                            }
-                            Some(self.vm.make_product(prod))
-                        }
-                    };   
+                        _ => Some(self.monomial(base)?)
+                    };
                     sum.push(match (fac, base) {
                         (None, None) => self.vm.make_int(1),
                         (Some(f), None) => f,
@@ -139,17 +266,48 @@ impl<'a, V: Vm + 'a> Compiler<'a, V> {
                     _ => self.vm.make_sum(sum)
                 }
             },
-            Node::Var(ref name) => {
-	        println!("use {}", name);
-	        self.sources.remove(name.as_str()).ok_or(Error::Undefined(name.clone()))?
-	    },
+            Node::Var(ref name) => match self.sources.remove(name.as_str()) {
+                Some(var) => var,
+                None if name == "π" => self.vm.make_const(::std::f64::consts::PI),
+                None => return Err(Error::Undefined(name.clone()))
+            },
             Node::Apply(ref f, ref g) => match **f {
+                Node::Op(Func::Transient(f)) if f.arity() == 2 => {
+                    use self::Transient::*;
+                    let (a, b) = match **g {
+                        Node::Tuple(ref parts) if parts.len() == 2 => (self.generate(&parts[0])?, self.generate(&parts[1])?),
+                        _ => return Err(Error::ShapeMismatch(2, 1))
+                    };
+                    match f {
+                        Atan2 => self.vm.atan2(a, b),
+                        Min => self.vm.min(a, b),
+                        Max => self.vm.max(a, b),
+                        f => return Err(Error::MissingFunction(f.name().into()))
+                    }
+                },
                 Node::Op(Func::Transient(f)) => { 
                     use self::Transient::*;
                     let x = self.generate(g)?;
                     match f {
                         Sin => self.vm.sin(x),
                         Cos => self.vm.cos(x),
+                        Tan => self.vm.tan(x),
+                        Asin => self.vm.asin(x),
+                        Acos => self.vm.acos(x),
+                        Atan => self.vm.atan(x),
+                        Sinh => self.vm.sinh(x),
+                        Cosh => self.vm.cosh(x),
+                        Tanh => self.vm.tanh(x),
+                        Asinh => self.vm.asinh(x),
+                        Acosh => self.vm.acosh(x),
+                        Atanh => self.vm.atanh(x),
+                        Log => self.vm.log(x),
+                        Exp => self.vm.exp(x),
+                        Abs => self.vm.abs(x),
+                        Sign => self.vm.sign(x),
+                        Floor => self.vm.round(x, Round::Down),
+                        Ceil => self.vm.round(x, Round::Up),
+                        Erf => self.vm.erf(x),
                         _ => todo!("implement all functions for avx")
                     }
                 },
@@ -158,7 +316,8 @@ impl<'a, V: Vm + 'a> Compiler<'a, V> {
             Node::Op(_) => bug!("operators are not allowed outside apply"),
             Node::Tuple(_) => todo!("implement tuples")
         };
-        println!("{} uses for {} (stored in {:?})", self.uses[node], node, var);
+        self.vm.leave(node);
+        debug!("{} uses for {} (stored in {:?})", self.uses[node], node, var);
         match self.uses[node] {
             0 => unreachable!(),
             1 => {},
@@ -168,4 +327,96 @@ impl<'a, V: Vm + 'a> Compiler<'a, V> {
         }
         Ok(var)
     }
+
+    fn monomial(&mut self, base: Monomial<'a>) -> Result<V::Var, Error> {
+        if is_trivial(base) {
+            return self.generate(&base[0].0);
+        }
+        if let Some(stored) = self.monomial_storage.get(base) {
+            return Ok(self.vm.load(stored));
+        }
+
+        let mut prod = Vec::with_capacity(base.len());
+        for &(ref v, ref n) in base.iter() {
+            if n.to_int().is_none() {
+                prod.push(self.root(v, n)?);
+                continue;
+            }
+            let n = n.as_i32().ok_or(Error::Overflow)?;
+            prod.push(match n {
+                0 => continue, // skip it
+                i if i > 0 => self.power(v, i as u32)?,
+                i => {
+                    let p = self.power(v, -i as u32)?;
+                    self.vm.inv(p)
+                }
+            });
+        }
+        let mut var = self.vm.make_product(prod);
+        match self.monomials[base] {
+            1 => {},
+            n => {
+                let stored = self.vm.store(&mut var, n-1);
+                self.monomial_storage.insert(base, stored);
+            }
+        }
+        Ok(var)
+    }
+
+    /// v^(p/q) with q = 2ᵏ m and m odd, as p-th power of k nested square roots of the real m-th root
+    fn root(&mut self, v: &'a Node, n: &Rational) -> Result<V::Var, Error> {
+        let (p, q) = n.frac();
+        let p = p.as_i32().ok_or(Error::Overflow)?;
+        let q = q.as_i32().ok_or(Error::Overflow)?;
+        let k = q.trailing_zeros();
+        let m = q >> k;
+        let mut x = self.generate(v)?;
+        if m > 1 {
+            x = self.vm.odd_root(x, m as u32);
+        }
+        for _ in 0 .. k {
+            x = self.vm.sqrt(x);
+        }
+        if p.abs() > 1 {
+            x = self.vm.pow_n(x, p.abs() as u32);
+        }
+        if p < 0 {
+            x = self.vm.inv(x);
+        }
+        Ok(x)
+    }
+
+    /// v^n, following the plan
+    fn power(&mut self, v: &'a Node, n: u32) -> Result<V::Var, Error> {
+        if n == 1 {
+            return self.generate(v);
+        }
+        if let Some(stored) = self.power_storage.get(&(v, n)) {
+            return Ok(self.vm.load(stored));
+        }
+
+        let (step, uses) = self.chains[&(v, n)];
+        let mut var = match step {
+            Step::Direct => {
+                let x = self.generate(v)?;
+                self.vm.pow_n(x, n)
+            },
+            Step::Mul(p, q) => {
+                let a = self.power(v, p)?;
+                let b = self.power(v, q)?;
+                self.vm.mul(a, b)
+            },
+            Step::MulPow(p, q) => {
+                let a = self.power(v, p)?;
+                let x = self.generate(v)?;
+                let b = self.vm.pow_n(x, q);
+                self.vm.mul(a, b)
+            }
+        };
+        if uses > 1 {
+            let stored = self.vm.store(&mut var, uses-1);
+            self.power_storage.insert((v, n), stored);
+        }
+        Ok(var)
+    }
 }
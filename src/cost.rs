@@ -0,0 +1,229 @@
+//! How expensive is it to evaluate an expression, and how to make it cheaper.
+
+use crate::prelude::*;
+use crate::poly::{Poly, Base};
+use crate::func::Func;
+use crate::horner::{rewrite_poly, Scheme};
+use std::collections::{HashMap, HashSet};
+use std::ops::Add;
+use std::fmt;
+
+/// Operations the `Compiler` emits for an expression
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Cost {
+    pub add: usize,
+    pub mul: usize,
+    pub div: usize,
+    pub transcendental: usize
+}
+impl Cost {
+    /// a single number to compare costs. divisions and transcendental functions are weighted
+    /// by their rough latency relative to a multiplication.
+    pub fn total(&self) -> usize {
+        self.add + self.mul + 4 * self.div + 20 * self.transcendental
+    }
+}
+impl Add for Cost {
+    type Output = Cost;
+    fn add(self, rhs: Cost) -> Cost {
+        Cost {
+            add: self.add + rhs.add,
+            mul: self.mul + rhs.mul,
+            div: self.div + rhs.div,
+            transcendental: self.transcendental + rhs.transcendental
+        }
+    }
+}
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} add, {} mul, {} div, {} transcendental", self.add, self.mul, self.div, self.transcendental)
+    }
+}
+
+/// number of multiplications `Vm::pow_n` needs for x^n
+pub fn pow_cost(n: u32) -> usize {
+    match n {
+        0 | 1 => 0,
+        n => (31 - n.leading_zeros() + n.count_ones() - 1) as usize
+    }
+}
+
+/// Count the operations needed to compute all `nodes`.
+///
+/// Like the `Compiler`, every node and every monomial is only computed once
+/// and powers of the same node build on each other.
+pub fn cost(nodes: &[NodeRc]) -> Cost {
+    let mut counter = Counter {
+        seen: HashSet::new(),
+        monomials: HashSet::new(),
+        powers: HashMap::new(),
+        cost: Cost::default()
+    };
+    for n in nodes {
+        counter.node(n);
+    }
+    counter.cost
+}
+
+struct Counter<'a> {
+    seen: HashSet<&'a Node>,
+    monomials: HashSet<&'a [(NodeRc, Rational)]>,
+    powers: HashMap<&'a Node, Vec<u32>>,
+    cost: Cost
+}
+impl<'a> Counter<'a> {
+    fn node(&mut self, node: &'a Node) {
+        if !self.seen.insert(node) {
+            return;
+        }
+        match *node {
+            Node::Poly(ref p) => {
+                let mut terms = 0;
+                for (base, fac) in p.factors() {
+                    terms += 1;
+                    if base.is_empty() {
+                        continue;
+                    }
+                    self.monomial(base);
+                    if *fac != Rational::from(1i64) {
+                        self.cost.mul += 1;
+                    }
+                }
+                if terms > 1 {
+                    self.cost.add += terms - 1;
+                }
+            },
+            Node::Apply(ref f, ref g) => {
+                self.node(g);
+                if let Node::Op(Func::Transient(_)) = **f {
+                    self.cost.transcendental += 1;
+                }
+            },
+            Node::Tuple(ref parts) => {
+                for p in parts {
+                    self.node(p);
+                }
+            },
+            Node::Var(_) | Node::Op(_) => {}
+        }
+    }
+    fn monomial(&mut self, base: &'a [(NodeRc, Rational)]) {
+        if base.len() == 1 && base[0].1 == 1 {
+            return self.node(&base[0].0);
+        }
+        if !self.monomials.insert(base) {
+            return;
+        }
+        for &(ref v, ref n) in base {
+            self.node(v);
+            if n.to_int().is_none() {
+                // roots cost about as much as a transcendental function
+                self.cost.transcendental += 1;
+                continue;
+            }
+            let n = n.as_i32().unwrap_or(i32::max_value());
+            if n.abs() >= 2 {
+                self.power(v, n.abs() as u32);
+            }
+            if n < 0 {
+                self.cost.div += 1;
+            }
+        }
+        self.cost.mul += base.len() - 1;
+    }
+    fn power(&mut self, v: &'a Node, n: u32) {
+        let avail = self.powers.entry(v).or_insert_with(|| vec![1]);
+        if avail.contains(&n) {
+            return;
+        }
+        let reuse = avail.iter().any(|&p| p > 1 && p < n && avail.contains(&(n - p)));
+        self.cost.mul += match avail.iter().filter(|&&p| p < n).max() {
+            _ if reuse => 1,
+            Some(&p) if p > 1 => pow_cost(n).min(1 + pow_cost(n - p)),
+            _ => pow_cost(n)
+        };
+        avail.push(n);
+    }
+}
+
+/// Rewrite `node` into an equivalent form that is cheaper to evaluate according to `cost`.
+///
+/// For every polynomial the expanded, factored and Horner form are compared, and powers are split into cheaper chains (x¹⁵ = (x³)⁵).
+pub fn optimize(builder: &Builder, node: &NodeRc) -> Result<NodeRc, Error> {
+    Optimizer { builder, done: HashMap::new() }.node(node)
+}
+
+struct Optimizer<'b> {
+    builder: &'b Builder,
+    done: HashMap<NodeRc, NodeRc>
+}
+impl<'b> Optimizer<'b> {
+    fn node(&mut self, node: &NodeRc) -> Result<NodeRc, Error> {
+        if let Some(n) = self.done.get(node) {
+            return Ok(n.clone());
+        }
+        let out = match **node {
+            Node::Poly(ref p) => self.poly(p)?,
+            Node::Apply(ref f, ref g) => {
+                let g = self.node(g)?;
+                self.builder.intern(Node::Apply(f.clone(), g))
+            },
+            Node::Tuple(ref parts) => {
+                let parts = parts.iter().map(|p| self.node(p)).collect::<Result<Vec<_>, _>>()?;
+                self.builder.intern(Node::Tuple(parts))
+            },
+            Node::Var(_) | Node::Op(_) => node.clone()
+        };
+        self.done.insert(node.clone(), out.clone());
+        Ok(out)
+    }
+
+    /// cheapest way to compute v^n, as factors
+    fn power(&mut self, v: NodeRc, n: &Rational) -> Base {
+        let m = match n.as_i32() {
+            Some(m) => m,
+            None => return vec![(v, n.clone())]
+        };
+        let (sign, m) = (m.signum(), m.abs() as u32);
+        let best = (2 .. m).filter(|a| m % a == 0)
+            .map(|a| (a, pow_cost(a) + pow_cost(m / a)))
+            .min_by_key(|&(_, c)| c);
+        match best {
+            Some((a, c)) if c < pow_cost(m) => {
+                let inner = self.builder.poly(Poly::product(vec![(v, Rational::from(a as i64))], 1.into()));
+                self.power(inner, &Rational::from((sign * (m / a) as i32) as i64))
+            },
+            _ => vec![(v, n.clone())]
+        }
+    }
+
+    fn poly(&mut self, p: &Poly) -> Result<NodeRc, Error> {
+        let mut expanded = Poly::zero();
+        for (base, fac) in p.factors() {
+            let mut factors = vec![];
+            for &(ref v, ref n) in base.iter() {
+                let v = self.node(v)?;
+                factors.extend(self.power(v, n));
+            }
+            expanded = expanded + Poly::product(factors, fac.clone());
+        }
+
+        let mut candidates = vec![self.builder.poly(expanded.clone())];
+        if let Some((common, rest)) = expanded.factorize() {
+            let rest = self.poly(&rest)?;
+            let (base, _) = common.factors().next().unwrap();
+            let mut factors = base.clone();
+            factors.push((rest, 1.into()));
+            candidates.push(self.builder.poly(Poly::product(factors, 1.into())));
+        }
+        let horner = rewrite_poly(self.builder, &expanded, Scheme::Horner);
+        if horner != expanded {
+            candidates.push(self.builder.poly(horner));
+        }
+
+        // min_by_key prefers the earlier candidate on ties
+        let best = candidates.into_iter().min_by_key(|n| cost(&[n.clone()]).total()).unwrap();
+        self.done.insert(best.clone(), best.clone());
+        Ok(best)
+    }
+}
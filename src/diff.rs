@@ -7,18 +7,73 @@ pub fn diff(builder: &Builder, node: &NodeRc, var: &str) -> Result<NodeRc, Error
     match **node {
         Node::Apply(ref f, ref g) => {
             match **f {
+                Node::Op(Func::Transient(f)) if f.arity() == 2 => {
+                    let (a, b) = match **g {
+                        Node::Tuple(ref parts) if parts.len() == 2 => (&parts[0], &parts[1]),
+                        _ => return Err(Error::ShapeMismatch(2, 1))
+                    };
+                    let (da, db) = (diff(builder, a, var)?, diff(builder, b, var)?);
+                    match f {
+                        Atan2 => // d/dx atan2(a, b) = (b a' - a b') / (a² + b²)
+                            builder.div(
+                                builder.sub(builder.mul(b.clone(), da)?, builder.mul(a.clone(), db)?)?,
+                                builder.add(builder.pow_i(a.clone(), 2)?, builder.pow_i(b.clone(), 2)?)?
+                            ),
+                        Min | Max => { // min(a, b) = (a + b - |a - b|) / 2, max(a, b) = (a + b + |a - b|) / 2
+                            let s = builder.func(Sign.into(), builder.sub(a.clone(), b.clone())?)?;
+                            let s = if f == Min { builder.neg(s)? } else { s };
+                            builder.div(
+                                builder.add(builder.add(da.clone(), db.clone())?, builder.mul(s, builder.sub(da, db)?)?)?,
+                                builder.int(2)
+                            )
+                        },
+                        f => Err(Error::MissingFunction(f.name().into()))
+                    }
+                },
                 Node::Op(Func::Transient(f)) => {
                     let dg = diff(builder, g, var)?;
+                    let one = builder.int(1);
+                    let g2 = builder.pow_i(g.clone(), 2)?;
+                    let minus_half = Rational::new((-1).into(), 2.into());
                     builder.mul(
                         match f {
                             Sin => // d/dx sin(g(x)) = cos(g(x)) g'(x)
                                 builder.func(Cos.into(), g.clone())?, 
                             Cos => // d/dx cos(g(x)) = - sin(g(x)) g'(x)
                                 builder.neg(builder.func(Sin.into(), g.clone())?)?,
+                            Tan => // d/dx tan(g(x)) = g'(x) / cos(g(x))²
+                                builder.pow_i(builder.func(Cos.into(), g.clone())?, -2)?,
+                            Asin => // d/dx asin(g(x)) = g'(x) / √(1 - g(x)²)
+                                builder.pow_r(builder.sub(one, g2)?, minus_half)?,
+                            Acos => // d/dx acos(g(x)) = - g'(x) / √(1 - g(x)²)
+                                builder.neg(builder.pow_r(builder.sub(one, g2)?, minus_half)?)?,
+                            Atan => // d/dx atan(g(x)) = g'(x) / (1 + g(x)²)
+                                builder.pow_i(builder.add(one, g2)?, -1)?,
+                            Sinh => // d/dx sinh(g(x)) = cosh(g(x)) g'(x)
+                                builder.func(Cosh.into(), g.clone())?,
+                            Cosh => // d/dx cosh(g(x)) = sinh(g(x)) g'(x)
+                                builder.func(Sinh.into(), g.clone())?,
+                            Tanh => // d/dx tanh(g(x)) = (1 - tanh(g(x))²) g'(x)
+                                builder.sub(one, builder.pow_i(builder.func(Tanh.into(), g.clone())?, 2)?)?,
+                            Asinh => // d/dx asinh(g(x)) = g'(x) / √(g(x)² + 1)
+                                builder.pow_r(builder.add(g2, one)?, minus_half)?,
+                            Acosh => // d/dx acosh(g(x)) = g'(x) / √(g(x)² - 1)
+                                builder.pow_r(builder.sub(g2, one)?, minus_half)?,
+                            Atanh => // d/dx atanh(g(x)) = g'(x) / (1 - g(x)²)
+                                builder.pow_i(builder.sub(one, g2)?, -1)?,
                             Log => // d/dx log(g(x)) = g'(x) / g(x)
                                 builder.pow_i(g.clone(), -1)?,
                             Exp => // d/dx exp(g(x)) = exp(g(x)) g'(x)
                                 builder.func(Exp.into(), g.clone())?,
+                            Abs => // d/dx |g(x)| = sign(g(x)) g'(x)
+                                builder.func(Sign.into(), g.clone())?,
+                            Sign | Floor | Ceil => // piecewise constant
+                                builder.int(0),
+                            Erf => // d/dx erf(g(x)) = 2 / √π exp(-g(x)²) g'(x)
+                                builder.div(
+                                    builder.mul(builder.int(2), builder.func(Exp.into(), builder.neg(g2)?)?)?,
+                                    builder.sqrt(builder.pi())?
+                                )?,
                             _ => todo!("missing functions")
                         },
                         dg       
@@ -41,8 +96,8 @@ pub fn diff_poly(builder: &Builder, poly: &Poly, var: &str) -> Result<Poly, Erro
             Poly::from_node(f.clone()).pow(builder, n.clone())
         }).collect(); // [f₀, f₁, f₂, ...]
         let df: Result<Vec<Poly>, Error> = base.iter().map(|&(ref f, ref n)| Ok(
-            Poly::from_node(f.clone()).pow(builder, n.clone() - 1)?
-            * n.clone() * Poly::from_node(diff(builder, f, var)?) // ∂ₓ f(x)ⁿ = n f(x)ⁿ⁻¹ ∂ₓ f(x)
+            Poly::from_node(f.clone()).pow(builder, n.clone() - Rational::from(1))?
+            * Poly::rational(n.clone()) * Poly::from_node(diff(builder, f, var)?) // ∂ₓ f(x)ⁿ = n f(x)ⁿ⁻¹ ∂ₓ f(x)
         )).collect(); // [∂ₓ f₀, ∂ₓ f₁, ∂ₓ f₂, ...]
 
         let (f, df) = (f?, df?);
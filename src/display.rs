@@ -1,5 +1,5 @@
 use crate::prelude::*;
-use crate::poly::{Poly, cmp_base};
+use crate::poly::Poly;
 use itertools::Itertools;
 use std::fmt::{self, Display};
 
@@ -45,6 +45,14 @@ impl Display for Tokens {
         Ok(())
     }
 }
+/// roots already group their argument
+fn strip_parens(s: &str) -> &str {
+    if s.starts_with("\\left( ") && s.ends_with(" \\right)") {
+        &s[7 .. s.len() - 8]
+    } else {
+        s
+    }
+}
 fn wrap_poly(p: &Poly, mode: &Mode) -> String {
     let tokens = Tokens::poly(p, mode);
     if tokens.len() > 1 {
@@ -78,27 +86,41 @@ impl Tokens {
             LaTeX => self.push(format!("\\frac{{{}}}{{{}}}", nom, denom))
         }
     }
+    /// the product of `factors`, each with its exponent
+    fn monomial(factors: &[(NodeRc, Rational)], mode: &Mode) -> Tokens {
+        let mut mid = Tokens::new();
+        for (i, &(ref v, ref n)) in factors.iter().enumerate() {
+            match *mode {
+                LaTeX if i > 0 => mid.push("\\,"),
+                _ => {}
+            }
+            let base = match **v {
+                Node::Poly(ref p) => wrap_poly(p, mode),
+                ref v => format!("{}", Tokens::node(v, mode))
+            };
+            let (p, q) = n.frac();
+            mid.push(match (*mode, q.as_i64()) {
+                _ if *n == 1 => base,
+                (Text, Some(1)) => format!("{}{}", base, int_super(&p)),
+                (LaTeX, Some(1)) => format!("{{{}}}^{{{}}}", base, p),
+                (Text, Some(2)) if p == 1 => format!("√{}", base),
+                (Text, Some(3)) if p == 1 => format!("∛{}", base),
+                (Text, _) => format!("{}^({})", base, n),
+                (LaTeX, Some(2)) if p == 1 => format!("\\sqrt{{{}}}", strip_parens(&base)),
+                (LaTeX, _) if p == 1 => format!("\\sqrt[{}]{{{}}}", q, strip_parens(&base)),
+                (LaTeX, _) => format!("{{{}}}^{{\\frac{{{}}}{{{}}}}}", base, p, q)
+            });
+        }
+        mid
+    }
     pub fn poly(p: &Poly, mode: &Mode) -> Tokens {
         let mut tokens = Tokens::new();
-        let mut elements: Vec<_> = p.factors().collect();
-        elements.sort_by(|a, b| cmp_base(&a.0, &b.0));
+        for (n, (base, fac)) in p.factors().enumerate() {
+            // negative powers go into the denominator
+            let (over, under): (Vec<_>, Vec<_>) = base.iter().cloned().partition(|&(_, ref n)| !n.is_negative());
+            let under: Vec<_> = under.into_iter().map(|(v, n)| (v, n * -1)).collect();
+            let mid = Tokens::monomial(&over, mode);
 
-        for (n, &(base, fac)) in elements.iter().enumerate() {
-            let mut mid = Tokens::new();
-            for (i, &(ref v, ref n)) in base.iter().enumerate() {
-                match *mode {
-                    LaTeX if i > 0 => mid.push("\\,"),
-                    _ => {}
-                }
-                mid.push(match (&**v, *mode) {
-                    (v, _) if *n == 1 => format!("{}", Tokens::node(v, mode)),
-                    (&Node::Poly(ref p), Text) => format!("{}{}", wrap_poly(p, mode), int_super(n)),
-                    (&Node::Poly(ref p), LaTeX) => format!("{{{}}}^{{{}}}", wrap_poly(p, mode), n),
-                    (v, Text) => format!("{}{}", Tokens::node(v, mode), int_super(n)),
-                    (v, LaTeX) => format!("{{{}}}^{{{}}}", Tokens::node(v, mode), n)
-                });
-            }
-            
             let (nom, denom) = fac.frac();
 
             if nom.is_negative() {
@@ -110,6 +132,28 @@ impl Tokens {
             let nom = nom.abs();
             let nom_is_one = nom == 1;
             let denom_is_one = denom == 1;
+
+            if under.len() > 0 {
+                let mut top = Tokens::new();
+                if !nom_is_one || mid.len() == 0 {
+                    top.push(nom);
+                }
+                if mid.len() > 0 {
+                    top.push(mid);
+                }
+                let mut bottom = Tokens::new();
+                if !denom_is_one {
+                    bottom.push(denom);
+                }
+                bottom.push(Tokens::monomial(&under, mode));
+                let bottom = match *mode {
+                    Text if !denom_is_one || under.len() > 1 => format!("({})", bottom),
+                    _ => bottom.to_string()
+                };
+                tokens.push_frac(top, bottom, mode);
+                continue;
+            }
+
             match (nom_is_one, denom_is_one, mid.len(), *mode) {
                 (_,    true, 0, _) => tokens.push(nom),
                 (true, true, _, _) => tokens.push(mid),
@@ -134,10 +178,15 @@ impl Tokens {
         let mut tokens = Tokens::new();
         match (&*n, *mode) {
             (&Node::Op(ref f), _) => tokens.push(f),
-            (&Node::Apply(ref f, ref g), Text) => tokens.push(format!("{}({})", Tokens::node(f, mode), Tokens::node(g, mode))),
+            (&Node::Apply(ref f, ref g), Text) => match **g {
+                // the tuple already has its parentheses
+                Node::Tuple(_) => tokens.push(format!("{}{}", Tokens::node(f, mode), Tokens::node(g, mode))),
+                _ => tokens.push(format!("{}({})", Tokens::node(f, mode), Tokens::node(g, mode)))
+            },
             (&Node::Apply(ref f, ref g), LaTeX) => tokens.push(format!("{} \\left( {} \\right)", Tokens::node(f, mode), Tokens::node(g, mode))),
             (&Node::Poly(ref p), _) => {
-                match p.factorize() {
+                // common denominators are shown by the fractions of each term
+                match p.factorize().filter(|_| p.is_polynomial()) {
                     Some((p, q)) => {
                         tokens.push(wrap_poly(&p, mode));
                         tokens.push(wrap_poly(&q, mode));
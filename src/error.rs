@@ -1,6 +1,7 @@
 use crate::prelude::*;
 use crate::poly::PolyError;
 use std::fmt::{self, Debug, Display};
+use std::io;
 
 #[derive(Debug)]
 pub enum Error {
@@ -17,6 +18,7 @@ pub enum Error {
     Todo(&'static str),
     Bug(&'static str),
     Other(String),
+    Io(io::Error),
     Overflow
 }
 impl Display for Error {
@@ -29,11 +31,13 @@ impl Display for Error {
                 write!(f, "expected {}: {}\u{32d}{}", expected.iter().join(" ,"), &input[..pos+1], &input[pos+1..]),
             IntegerError => write!(f, "not an integer"),
             Poly(PolyError::DivZero) => write!(f, "division by zero"),
+            Poly(PolyError::NotPolynomial) => write!(f, "not a polynomial (negative exponent)"),
             Undefined(ref name) => write!(f, "'{}' is not defined", name),
             ShapeMismatch(a, b) => write!(f, "shapes do not match ({} vs. {})", a, b),
             Todo(what) => write!(f, "{} is not implemented yet", what),
             Bug(what) => write!(f, "BUG: {}", what),
             Other(ref msg) => write!(f, "{}", msg),
+            Io(ref e) => write!(f, "I/O error: {}", e),
             Overflow => write!(f, "out of bits!")
         }       
     }
@@ -41,6 +45,9 @@ impl Display for Error {
 impl From<PolyError> for Error {
     fn from(e: PolyError) -> Error { Error::Poly(e) }
 }
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error { Error::Io(e) }
+}
 impl Error {
     pub fn parse_error<T: Debug>(e: lalrpop_util::ParseError<usize, T, &str>, input: &str) -> Error {
         use lalrpop_util::ParseError::UnrecognizedToken;
@@ -8,7 +8,60 @@ pub enum Command<'a> {
     Define(&'a str, Vec<&'a str>, NodeRc),
     Expr(NodeRc),
     Eval(NodeRc),
-    Bench(NodeRc)
+    Bench(NodeRc),
+    Factor(NodeRc)
+}
+
+/// xⁿ, where odd roots of negative numbers are real
+fn pow(x: f64, n: &Rational) -> f64 {
+    let (p, q) = n.frac();
+    if x < 0.0 && q.is_odd() && q != 1 {
+        let y = (-x).powf(n.as_f64());
+        return if p.is_odd() { -y } else { y };
+    }
+    x.powf(n.as_f64())
+}
+
+/// the error function, from its Taylor series for small |x| and the continued fraction of erfc otherwise
+fn erf(x: f64) -> f64 {
+    use std::f64::consts::PI;
+    if x.abs() < 3.0 {
+        // 2/√π ∑ (-1)ⁿ x²ⁿ⁺¹ / (n! (2n + 1))
+        let (mut sum, mut term, x2) = (0.0, x, x * x);
+        for n in 0 .. 100 {
+            let t = term / (2 * n + 1) as f64;
+            sum += t;
+            if t.abs() < 1e-17 * sum.abs() {
+                break;
+            }
+            term *= -x2 / (n + 1) as f64;
+        }
+        return sum * 2.0 / PI.sqrt();
+    }
+    // erfc x = e^(-x²) / √π · 1 / (x + ½ / (x + 1 / (x + ³⁄₂ / (x + …))))
+    let y = x.abs();
+    let mut k = y;
+    for n in (1 .. 60).rev() {
+        k = y + 0.5 * n as f64 / k;
+    }
+    let erfc = (-y * y).exp() / (PI.sqrt() * k);
+    (1.0 - erfc).copysign(x)
+}
+
+/// the argument of the `factor` command, i.e. of `factor` multiplied by juxtaposition.
+///
+/// `factor` is not a keyword, so `factor + 1` and `factor(…)` are expressions.
+fn factor_arg(input: &str) -> Option<&str> {
+    let s = input.trim_start();
+    if !s.starts_with("factor") {
+        return None;
+    }
+    let rest = &s["factor".len() ..];
+    let arg = rest.trim_start();
+    match arg.chars().next() {
+        Some(c) if rest.len() > arg.len() && (c.is_alphanumeric() || c == '(' || c == '[') => Some(arg),
+        _ => None
+    }
 }
 
 pub struct EvalContext {
@@ -30,27 +83,59 @@ impl EvalContext {
                 for (base, r) in p.factors() {
                     let mut prod = r.as_f64();
                     for &(ref f, ref n) in base.iter() {
-                        prod *= self.eval(f)?.powf(n.as_f64());
+                        prod *= pow(self.eval(f)?, n);
                     }
                     sum += prod;
                 }
                 Ok(sum)
             }
-            Node::Apply(ref f, ref g) => {
-                let x = self.eval(g)?;
-                match **f {
-                    Node::Op(Func::Transient(f)) => Ok(match f {    
+            Node::Apply(ref f, ref g) => match **f {
+                Node::Op(Func::Transient(f)) if f.arity() == 2 => {
+                    let (a, b) = match **g {
+                        Node::Tuple(ref parts) if parts.len() == 2 => (self.eval(&parts[0])?, self.eval(&parts[1])?),
+                        _ => return Err(Error::ShapeMismatch(2, 1))
+                    };
+                    Ok(match f {
+                        Atan2 => a.atan2(b),
+                        Min => a.min(b),
+                        Max => a.max(b),
+                        f   => return Err(Error::MissingFunction(f.name().into()))
+                    })
+                },
+                Node::Op(Func::Transient(f)) => {
+                    let x = self.eval(g)?;
+                    Ok(match f {
                         Sin => x.sin(),
                         Cos => x.cos(),
+                        Tan => x.tan(),
+                        Asin => x.asin(),
+                        Acos => x.acos(),
+                        Atan => x.atan(),
+                        Sinh => x.sinh(),
+                        Cosh => x.cosh(),
+                        Tanh => x.tanh(),
+                        Asinh => x.asinh(),
+                        Acosh => x.acosh(),
+                        Atanh => x.atanh(),
                         Log => x.ln(),
                         Exp => x.exp(),
+                        Abs => x.abs(),
+                        Sign if x == 0.0 => 0.0,
+                        Sign => x.signum(),
+                        Floor => x.floor(),
+                        Ceil => x.ceil(),
+                        Erf => erf(x),
                         _   => todo!("missing function")
-                    }),
-                    _ => todo!("apply non transients")
-                }
+                    })
+                },
+                _ => todo!("apply non transients")
             },
             Node::Op(_) => todo!("?"),
-            Node::Var(ref s) => self.defines.get(s).cloned().ok_or(Error::Undefined(s.clone())),
+            Node::Var(ref s) => match self.defines.get(s) {
+                Some(&x) => Ok(x),
+                None if s == "π" => Ok(::std::f64::consts::PI),
+                None => Err(Error::Undefined(s.clone()))
+            },
             _ => unimplemented!()
         }
     }
@@ -86,7 +171,10 @@ impl EvalContext {
         use self::Command::*;
         
         let cmd = match CommandParser::new().parse(&self.builder, input) {
-            Ok(r) => r?,
+            Ok(r) => match (r?, factor_arg(input)) {
+                (Expr(_), Some(arg)) => Factor(self.builder.parse(arg)?),
+                (cmd, _) => cmd
+            },
             Err(e) => return Err(Error::parse_error(e, input))
         };
 
@@ -98,6 +186,7 @@ impl EvalContext {
             },
             Expr(e) => Some(e.to_string()),
             Eval(e) => Some(self.eval(&e)?.to_string()),
+            Factor(e) => Some(self.builder.factor(e)?.to_string()),
             #[cfg(all(target_feature = "avx", feature="jit"))]
             Bench(e) => Some(self.bench(e)?),
             _ => None
@@ -0,0 +1,597 @@
+//! Factorization of polynomials with rational coefficients.
+//!
+//! A polynomial is split into its content and primitive part, the primitive part into
+//! square-free factors (Yun), and those into irreducible factors. Univariate ones are
+//! factored modulo a small prime (Berlekamp), lifted (Hensel) and recombined (Zassenhaus).
+//! Multivariate ones are mapped to a univariate polynomial by Kronecker substitution.
+//!
+//! The modular arithmetic uses `i128`, so factors whose coefficients could exceed 2⁶²
+//! are not split.
+
+use crate::prelude::*;
+use crate::poly::{Poly, PolyError};
+use std::collections::BTreeSet;
+
+/// `unit · Π fᵢ^kᵢ`
+#[derive(Debug, Clone)]
+pub struct Factors {
+    pub unit: Rational,
+    /// irreducible, with coprime integer coefficients and a positive leading coefficient
+    pub factors: Vec<(Poly, u32)>
+}
+
+/// the largest univariate image of a multivariate polynomial that is factored
+const MAX_IMAGE_DEGREE: usize = 256;
+
+/// Factor `p` into irreducible polynomials over the rationals.
+///
+/// The nodes in the bases are treated as independent variables.
+pub fn factor(p: &Poly) -> Result<Factors, Error> {
+    if !p.is_polynomial() {
+        return Err(PolyError::NotPolynomial.into());
+    }
+    let lc = match p.leading_term() {
+        Some((_, lc)) => lc.clone(),
+        None => return Ok(Factors { unit: 0.into(), factors: vec![] })
+    };
+
+    let mut factors = vec![];
+    let mut q = integral(p.clone());
+    for v in variables(&q) {
+        // the smallest power of v in all terms
+        let k = *q.coefficients(&v).keys().next().unwrap();
+        if k > 0 {
+            q = q.div_rem(&power(&v, k))?.0;
+            factors.push((Poly::from_node(v), k));
+        }
+    }
+    split(q, &mut factors)?;
+    factors.sort();
+
+    let mut unit = lc;
+    for &(ref f, k) in factors.iter() {
+        unit /= f.leading_term().unwrap().1.pow(k as i32);
+    }
+    Ok(Factors { unit, factors })
+}
+
+/// Rewrite every polynomial in `node` as a product of irreducible factors.
+pub fn factor_node(builder: &Builder, node: &NodeRc) -> Result<NodeRc, Error> {
+    Ok(match **node {
+        Node::Poly(ref p) => {
+            let mut inner = Poly::zero();
+            for (base, fac) in p.factors() {
+                let base = base.iter()
+                    .map(|&(ref v, ref n)| Ok((factor_node(builder, v)?, n.clone())))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                inner = inner + Poly::product(base, fac.clone());
+            }
+            if !inner.is_polynomial() {
+                return Ok(builder.poly(inner));
+            }
+            product(builder, factor(&inner)?)
+        },
+        Node::Apply(ref f, ref g) => builder.intern(Node::Apply(f.clone(), factor_node(builder, g)?)),
+        Node::Tuple(ref parts) => builder.tuple(parts.iter().map(|p| factor_node(builder, p)))?,
+        Node::Var(_) | Node::Op(_) => node.clone()
+    })
+}
+
+/// the factors as a node, without expanding them
+fn product(builder: &Builder, f: Factors) -> NodeRc {
+    if f.factors.len() == 1 && f.factors[0].1 == 1 && f.unit == Rational::from(1) {
+        return builder.poly(f.factors[0].0.clone());
+    }
+    let mut base = vec![];
+    for (p, k) in f.factors {
+        let k = Rational::from(k as i64);
+        let monomial = match p.factors().next() {
+            Some((b, fac)) if p.factors().count() == 1 && *fac == Rational::from(1) => Some(b.clone()),
+            _ => None
+        };
+        match monomial {
+            Some(b) => base.extend(b.into_iter().map(|(v, n)| (v, n * k.clone()))),
+            None => base.push((builder.poly(p), k))
+        }
+    }
+    builder.poly(Poly::product(base, f.unit))
+}
+
+/// factor the primitive, integral `q` into `out`
+fn split(q: Poly, out: &mut Vec<(Poly, u32)>) -> Result<(), Error> {
+    let v = match variables(&q).into_iter().next() {
+        Some(v) => v,
+        None => return Ok(()) // a constant
+    };
+
+    // the content in v does not contain v
+    let mut content = Poly::zero();
+    for (_, c) in q.coefficients(&v) {
+        content = content.gcd(&c)?;
+    }
+    let pp = integral(q.div_rem(&content)?.0);
+    split(integral(content), out)?;
+
+    for (s, i) in square_free(pp, &v)? {
+        let parts = match variables(&s).len() {
+            1 => univariate(&s, &v),
+            _ => kronecker(&s)?
+        };
+        out.extend(parts.into_iter().map(|f| (f, i)));
+    }
+    Ok(())
+}
+
+/// square-free decomposition in `v` (Yun): `p = Π sᵢ^i`
+fn square_free(p: Poly, v: &NodeRc) -> Result<Vec<(Poly, u32)>, Error> {
+    let dp = derivative(&p, v);
+    let c = p.gcd(&dp)?;
+    let mut w = p.div_rem(&c)?.0;
+    let y = dp.div_rem(&c)?.0;
+    let mut z = y + derivative(&w, v) * (-1);
+
+    let mut out = vec![];
+    let mut i = 1;
+    while w.degree_in(v) > 0 {
+        let g = w.gcd(&z)?;
+        w = w.div_rem(&g)?.0;
+        let y = z.div_rem(&g)?.0;
+        z = y + derivative(&w, v) * (-1);
+        if g.degree_in(v) > 0 {
+            out.push((integral(g), i));
+        }
+        i += 1;
+    }
+    Ok(out)
+}
+
+/// irreducible factors of a square-free, primitive polynomial in `v` alone
+fn univariate(p: &Poly, v: &NodeRc) -> Vec<Poly> {
+    let mut dense = vec![0; p.degree_in(v) as usize + 1];
+    for (k, c) in p.coefficients(v) {
+        match c.as_rational().and_then(|r| r.as_i64()) {
+            Some(c) => dense[k as usize] = c as i128,
+            None => return vec![p.clone()]
+        }
+    }
+    zassenhaus(&dense).into_iter().map(|f| {
+        let terms = f.iter().enumerate()
+            .filter(|&(_, &c)| c != 0)
+            .map(|(k, &c)| power(v, k as u32) * Int::from(c as i64));
+        integral(terms.fold(Poly::zero(), |a, b| a + b))
+    }).collect()
+}
+
+/// irreducible factors of a square-free, primitive polynomial in several nodes
+fn kronecker(p: &Poly) -> Result<Vec<Poly>, Error> {
+    let vars = variables(p);
+    let d = vars.iter().map(|v| p.degree_in(v)).max().unwrap() as usize + 1;
+    match d.checked_pow(vars.len() as u32) {
+        Some(n) if n <= MAX_IMAGE_DEGREE => {},
+        _ => return Ok(vec![p.clone()])
+    }
+
+    // p(x, y, z, …) → p(x, x^d, x^(d²), …)
+    let x = &vars[0];
+    let mut image = Poly::zero();
+    for (base, fac) in p.factors() {
+        let (e, _) = vars.iter().fold((0, 1), |(e, scale), v| {
+            let n = base.iter().find(|t| t.0 == *v).map_or(0, |t| t.1.as_i32().unwrap() as usize);
+            (e + n * scale, scale * d)
+        });
+        image = image + power(x, e as u32) * Poly::rational(fac.clone());
+    }
+
+    // the image may have repeated factors even though p has none
+    let mut parts = vec![];
+    for (s, i) in square_free(integral(image), x)? {
+        for f in univariate(&s, x) {
+            parts.extend((0 .. i).map(|_| f.clone()));
+        }
+    }
+
+    // every factor of p is the preimage of a product of parts
+    let preimage = |image: Poly| {
+        let mut out = Poly::zero();
+        for (base, fac) in image.factors() {
+            let mut e = base.first().map_or(0, |t| t.1.as_i32().unwrap() as usize);
+            let mut base = vec![];
+            for v in vars.iter() {
+                base.push((v.clone(), Rational::from((e % d) as i64)));
+                e /= d;
+            }
+            out = out + Poly::product(base, fac.clone());
+        }
+        integral(out)
+    };
+    let mut rest = p.clone();
+    let mut out = vec![];
+    let mut size = 1;
+    while 2 * size <= parts.len() {
+        let found = (0 .. parts.len()).combinations(size).filter_map(|subset| {
+            let g = preimage(subset.iter().fold(Poly::int(1), |acc, &i| acc * parts[i].clone()));
+            if g.as_rational().is_some() {
+                return None;
+            }
+            match rest.div_rem(&g) {
+                Ok((q, r)) if r.is_zero() => Some((subset, g, q)),
+                _ => None
+            }
+        }).next();
+        match found {
+            Some((subset, g, q)) => {
+                out.push(g);
+                rest = integral(q);
+                for &i in subset.iter().rev() {
+                    parts.remove(i);
+                }
+            },
+            None => size += 1
+        }
+    }
+    out.push(rest);
+    Ok(out)
+}
+
+/// `p` scaled to coprime integer coefficients and a positive leading coefficient
+fn integral(p: Poly) -> Poly {
+    p.split_content().1
+}
+
+fn variables(p: &Poly) -> Vec<NodeRc> {
+    let vars: BTreeSet<&NodeRc> = p.factors().flat_map(|(b, _)| b.iter().map(|t| &t.0)).collect();
+    vars.into_iter().cloned().collect()
+}
+
+fn power(v: &NodeRc, k: u32) -> Poly {
+    Poly::product(vec![(v.clone(), Rational::from(k as i64))], 1.into())
+}
+
+/// d/dv p
+fn derivative(p: &Poly, v: &NodeRc) -> Poly {
+    let mut d = Poly::zero();
+    for (k, c) in p.coefficients(v) {
+        if k > 0 {
+            d = d + c * power(v, k - 1) * (k as i32);
+        }
+    }
+    d
+}
+
+const PRIMES: &[i128] = &[
+    3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199
+];
+
+/// irreducible factors over ℤ of a square-free, primitive `f` with a positive leading coefficient
+fn zassenhaus(f: &[i128]) -> Vec<Vec<i128>> {
+    use self::zp::*;
+
+    let n = f.len() - 1;
+    if n <= 1 {
+        return vec![f.to_vec()];
+    }
+    let lc = f[n];
+
+    // a prime that keeps the degree and f square-free
+    let p = match PRIMES.iter().cloned().find(|&p| lc % p != 0 && {
+        let fp = reduce(f, p);
+        gcd(&fp, &zp::derivative(&fp, p), p).len() == 1
+    }) {
+        Some(p) => p,
+        None => return vec![f.to_vec()]
+    };
+    let factors = berlekamp(&monic(&reduce(f, p), p), p);
+    if factors.len() == 1 {
+        return vec![f.to_vec()];
+    }
+
+    // lc times the coefficients of any factor is at most `bound` (Mignotte)
+    let norm = f.iter().map(|&c| (c as f64).powi(2)).sum::<f64>().sqrt();
+    let bound = 2.0 * (lc as f64) * 2f64.powi(n as i32) * norm;
+    let (mut m, mut k) = (p, 1);
+    while m as f64 <= bound {
+        if m > (1 << 62) / p {
+            return vec![f.to_vec()];
+        }
+        m *= p;
+        k += 1;
+    }
+    let mut lifted = hensel(&scale(f, inv(lc, m).unwrap(), m), &factors, p, k);
+
+    // combine the lifted factors into true ones, smallest subsets first
+    let mut f = f.to_vec();
+    let mut out = vec![];
+    let mut size = 1;
+    while 2 * size <= lifted.len() {
+        let found = (0 .. lifted.len()).combinations(size).filter_map(|subset| {
+            let lc = *f.last().unwrap();
+            let g = subset.iter().fold(vec![lc.rem_euclid(m)], |acc, &i| mul(&acc, &lifted[i], m));
+            let g = primitive(g.into_iter().map(|c| if c > m / 2 { c - m } else { c }).collect());
+            exact_div(&f, &g).map(|q| (subset, g, q))
+        }).next();
+        match found {
+            Some((subset, g, q)) => {
+                out.push(g);
+                f = q;
+                for &i in subset.iter().rev() {
+                    lifted.remove(i);
+                }
+            },
+            None => size += 1
+        }
+    }
+    out.push(f);
+    out
+}
+
+/// divide by the gcd of the coefficients and make the leading coefficient positive
+fn primitive(f: Vec<i128>) -> Vec<i128> {
+    let g = f.iter().fold(0, |a, &b| {
+        let (mut a, mut b) = (a, b.abs());
+        while b != 0 {
+            let r = a % b;
+            a = b;
+            b = r;
+        }
+        a
+    });
+    let g = if *f.last().unwrap() < 0 { -g } else { g };
+    f.into_iter().map(|c| c / g).collect()
+}
+
+/// `f / g` over ℤ, if it is exact
+fn exact_div(f: &[i128], g: &[i128]) -> Option<Vec<i128>> {
+    if g.len() > f.len() {
+        return None;
+    }
+    let mut r = f.to_vec();
+    let mut q = vec![0; f.len() - g.len() + 1];
+    let lg = *g.last().unwrap();
+    for k in (0 .. q.len()).rev() {
+        let top = r[k + g.len() - 1];
+        if top % lg != 0 {
+            return None;
+        }
+        q[k] = top / lg;
+        for (i, &gi) in g.iter().enumerate() {
+            r[k + i] = r[k + i].checked_sub(q[k].checked_mul(gi)?)?;
+        }
+    }
+    if r.iter().all(|&c| c == 0) {
+        Some(q)
+    } else {
+        None
+    }
+}
+
+/// dense polynomials modulo `m`: coefficients in `0 .. m`, lowest first, no trailing zeros
+mod zp {
+    pub type Dense = Vec<i128>;
+
+    fn trim(mut a: Dense) -> Dense {
+        while a.last() == Some(&0) {
+            a.pop();
+        }
+        a
+    }
+    pub fn reduce(a: &[i128], m: i128) -> Dense {
+        trim(a.iter().map(|&c| c.rem_euclid(m)).collect())
+    }
+    pub fn add(a: &[i128], b: &[i128], m: i128) -> Dense {
+        let n = a.len().max(b.len());
+        trim((0 .. n).map(|i| (a.get(i).unwrap_or(&0) + b.get(i).unwrap_or(&0)).rem_euclid(m)).collect())
+    }
+    pub fn sub(a: &[i128], b: &[i128], m: i128) -> Dense {
+        let n = a.len().max(b.len());
+        trim((0 .. n).map(|i| (a.get(i).unwrap_or(&0) - b.get(i).unwrap_or(&0)).rem_euclid(m)).collect())
+    }
+    pub fn mul(a: &[i128], b: &[i128], m: i128) -> Dense {
+        if a.is_empty() || b.is_empty() {
+            return vec![];
+        }
+        let mut out = vec![0; a.len() + b.len() - 1];
+        for (i, &x) in a.iter().enumerate() {
+            for (j, &y) in b.iter().enumerate() {
+                out[i + j] = (out[i + j] + x * y % m) % m;
+            }
+        }
+        trim(out)
+    }
+    pub fn scale(a: &[i128], c: i128, m: i128) -> Dense {
+        trim(a.iter().map(|&x| (x * c).rem_euclid(m)).collect())
+    }
+    /// 1 / a mod m
+    pub fn inv(a: i128, m: i128) -> Option<i128> {
+        let (mut r0, mut r1) = (a.rem_euclid(m), m);
+        let (mut s0, mut s1) = (1, 0);
+        while r1 != 0 {
+            let q = r0 / r1;
+            let r = r0 - q * r1;
+            r0 = r1;
+            r1 = r;
+            let s = s0 - q * s1;
+            s0 = s1;
+            s1 = s;
+        }
+        if r0 == 1 { Some(s0.rem_euclid(m)) } else { None }
+    }
+    /// the leading coefficient of `b` needs to be invertible
+    pub fn div_rem(a: &[i128], b: &[i128], m: i128) -> (Dense, Dense) {
+        let inv_lc = inv(*b.last().unwrap(), m).unwrap();
+        let mut r = a.to_vec();
+        let mut q = vec![0; (a.len() + 1).saturating_sub(b.len())];
+        while r.len() >= b.len() {
+            let k = r.len() - b.len();
+            let c = r.last().unwrap() * inv_lc % m;
+            q[k] = c;
+            for (i, &x) in b.iter().enumerate() {
+                r[i + k] = (r[i + k] - c * x % m).rem_euclid(m);
+            }
+            r = trim(r);
+        }
+        (trim(q), r)
+    }
+    pub fn monic(a: &[i128], m: i128) -> Dense {
+        match a.last() {
+            Some(&lc) => scale(a, inv(lc, m).unwrap(), m),
+            None => vec![]
+        }
+    }
+    pub fn derivative(a: &[i128], m: i128) -> Dense {
+        trim(a.iter().enumerate().skip(1).map(|(i, &c)| (c * i as i128).rem_euclid(m)).collect())
+    }
+    /// monic gcd modulo the prime p
+    pub fn gcd(a: &[i128], b: &[i128], p: i128) -> Dense {
+        let (mut a, mut b) = (a.to_vec(), b.to_vec());
+        while !b.is_empty() {
+            let r = div_rem(&a, &b, p).1;
+            a = b;
+            b = r;
+        }
+        monic(&a, p)
+    }
+    /// `(g, s, t)` with `s a + t b = g = gcd(a, b)` modulo the prime p
+    pub fn ext_gcd(a: &[i128], b: &[i128], p: i128) -> (Dense, Dense, Dense) {
+        let (mut r0, mut r1) = (a.to_vec(), b.to_vec());
+        let (mut s0, mut s1) = (vec![1], vec![]);
+        let (mut t0, mut t1) = (vec![], vec![1]);
+        while !r1.is_empty() {
+            let (q, r) = div_rem(&r0, &r1, p);
+            r0 = r1;
+            r1 = r;
+            let s = sub(&s0, &mul(&q, &s1, p), p);
+            s0 = s1;
+            s1 = s;
+            let t = sub(&t0, &mul(&q, &t1, p), p);
+            t0 = t1;
+            t1 = t;
+        }
+        let c = inv(*r0.last().unwrap(), p).unwrap();
+        (scale(&r0, c, p), scale(&s0, c, p), scale(&t0, c, p))
+    }
+    /// aᵉ mod f
+    fn pow_mod(a: &[i128], mut e: u64, f: &[i128], p: i128) -> Dense {
+        let mut result = vec![1];
+        let mut base = div_rem(a, f, p).1;
+        while e > 0 {
+            if e & 1 == 1 {
+                result = div_rem(&mul(&result, &base, p), f, p).1;
+            }
+            base = div_rem(&mul(&base, &base, p), f, p).1;
+            e >>= 1;
+        }
+        result
+    }
+
+    /// irreducible factors of a square-free, monic `f` modulo the prime `p`
+    pub fn berlekamp(f: &[i128], p: i128) -> Vec<Dense> {
+        let n = f.len() - 1;
+
+        // rows of Q: x^(i p) mod f
+        let xp = pow_mod(&[0, 1], p as u64, f, p);
+        let mut rows = Vec::with_capacity(n);
+        let mut row = vec![1];
+        for _ in 0 .. n {
+            let next = div_rem(&mul(&row, &xp, p), f, p).1;
+            rows.push(row);
+            row = next;
+        }
+
+        // v Q = v: the kernel of Qᵀ - I
+        let a = (0 .. n).map(|j| (0 .. n).map(|i| {
+            let q = rows[i].get(j).cloned().unwrap_or(0);
+            (q - (i == j) as i128).rem_euclid(p)
+        }).collect::<Vec<_>>()).collect();
+        let basis = kernel(a, p);
+
+        // every basis vector separates some of the factors
+        let mut factors = vec![f.to_vec()];
+        for v in basis.iter() {
+            if factors.len() >= basis.len() {
+                break;
+            }
+            let mut next = vec![];
+            for mut g in factors {
+                for s in 0 .. p {
+                    if g.len() <= 2 {
+                        break;
+                    }
+                    let h = gcd(&g, &sub(v, &[s], p), p);
+                    if h.len() > 1 && h.len() < g.len() {
+                        g = div_rem(&g, &h, p).0;
+                        next.push(h);
+                    }
+                }
+                next.push(g);
+            }
+            factors = next;
+        }
+        factors
+    }
+
+    /// basis of `{ v | a v = 0 }` for a square matrix `a`
+    fn kernel(mut a: Vec<Vec<i128>>, p: i128) -> Vec<Dense> {
+        let n = a.len();
+        let mut pivots = vec![];
+        for c in 0 .. n {
+            let r = pivots.len();
+            let pr = match (r .. n).find(|&i| a[i][c] != 0) {
+                Some(i) => i,
+                None => continue
+            };
+            a.swap(r, pr);
+            let inv_p = inv(a[r][c], p).unwrap();
+            let pivot: Vec<i128> = a[r].iter().map(|&x| x * inv_p % p).collect();
+            for (i, row) in a.iter_mut().enumerate() {
+                let f = row[c];
+                if i != r && f != 0 {
+                    for (x, &y) in row.iter_mut().zip(pivot.iter()) {
+                        *x = (*x - f * y).rem_euclid(p);
+                    }
+                }
+            }
+            a[r] = pivot;
+            pivots.push(c);
+        }
+
+        // one vector for every column without a pivot
+        (0 .. n).filter(|c| !pivots.contains(c)).map(|free| {
+            let mut v = vec![0; n];
+            v[free] = 1;
+            for (row, &c) in pivots.iter().enumerate() {
+                v[c] = (-a[row][free]).rem_euclid(p);
+            }
+            trim(v)
+        }).collect()
+    }
+
+    /// lift the monic factors of `f mod p` to factors of `f mod pᵏ`. `f` is monic.
+    pub fn hensel(f: &[i128], factors: &[Dense], p: i128, k: u32) -> Vec<Dense> {
+        if factors.len() == 1 {
+            return vec![reduce(f, p.pow(k))];
+        }
+        let mut g = factors[0].clone();
+        let mut h = factors[1 ..].iter().fold(vec![1], |acc, u| mul(&acc, u, p));
+        let (_, s, t) = ext_gcd(&g, &h, p);
+
+        let mut pj = p;
+        for _ in 1 .. k {
+            let pj1 = pj * p;
+            // f = g h + pʲ e (mod pʲ⁺¹)
+            let e = sub(&reduce(f, pj1), &mul(&g, &h, pj1), pj1);
+            let e: Dense = trim(e.iter().map(|&c| (c / pj) % p).collect());
+
+            // σ g + τ h = e with deg σ < deg h
+            let (q, sigma) = div_rem(&mul(&s, &e, p), &h, p);
+            let tau = add(&mul(&t, &e, p), &mul(&q, &g, p), p);
+            g = add(&g, &scale(&tau, pj, pj1), pj1);
+            h = add(&h, &scale(&sigma, pj, pj1), pj1);
+            pj = pj1;
+        }
+
+        let mut out = vec![g];
+        out.extend(hensel(&h, &factors[1 ..], p, k));
+        out
+    }
+}
@@ -5,12 +5,74 @@ use crate::prelude::*;
 pub enum Transient {
     Sin,
     Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Sinh,
+    Cosh,
+    Tanh,
+    Asinh,
+    Acosh,
+    Atanh,
     Log,
     Exp,
-    Gamma
+    Gamma,
+    Abs,
+    Sign,
+    Floor,
+    Ceil,
+    Min,
+    Max,
+    Erf
 }
 use self::Transient::*;
 
+impl Transient {
+    /// all functions, in the order of declaration
+    pub const ALL: [Transient; 23] = [
+        Sin, Cos, Tan, Asin, Acos, Atan, Atan2, Sinh, Cosh, Tanh, Asinh, Acosh, Atanh,
+        Log, Exp, Gamma, Abs, Sign, Floor, Ceil, Min, Max, Erf
+    ];
+
+    /// number of arguments, passed as a `Node::Tuple` if more than one
+    pub fn arity(self) -> usize {
+        match self {
+            Atan2 | Min | Max => 2,
+            _ => 1
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sin => "sin",
+            Cos => "cos",
+            Tan => "tan",
+            Asin => "asin",
+            Acos => "acos",
+            Atan => "atan",
+            Atan2 => "atan2",
+            Sinh => "sinh",
+            Cosh => "cosh",
+            Tanh => "tanh",
+            Asinh => "asinh",
+            Acosh => "acosh",
+            Atanh => "atanh",
+            Log => "log",
+            Exp => "exp",
+            Gamma => "Γ",
+            Abs => "abs",
+            Sign => "sign",
+            Floor => "floor",
+            Ceil => "ceil",
+            Min => "min",
+            Max => "max",
+            Erf => "erf",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub enum Func {
     Transient(Transient),
@@ -28,16 +90,7 @@ impl fmt::Display for Func {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::Func::*;
         match *self {
-            Transient(t) => {
-                let name = match t {
-                    Sin => "sin",
-                    Cos => "cos",
-                    Log => "log",
-                    Exp => "exp",
-                    Gamma => "Γ",
-                };
-                f.write_str(name)
-            },
+            Transient(t) => f.write_str(t.name()),
             Diff(ref var) => write!(f, "d/d{}", var),
             Definition(ref args, ref expr) => match args.len() {
                 0 => expr.fmt(f),
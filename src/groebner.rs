@@ -0,0 +1,207 @@
+//! Gröbner bases of polynomial ideals (Buchberger's algorithm).
+//!
+//! Each term is stored as a vector of exponents of the given variables, and the terms
+//! are kept sorted by the chosen monomial order, greatest first.
+
+use crate::prelude::*;
+use crate::poly::Poly;
+use std::cmp::Ordering;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Order {
+    /// lexicographic, the first variable is the greatest
+    Lex,
+    /// graded reverse lexicographic
+    GrevLex
+}
+impl Order {
+    pub fn from_name(name: &str) -> Option<Order> {
+        match name {
+            "lex" => Some(Order::Lex),
+            "grevlex" => Some(Order::GrevLex),
+            _ => None
+        }
+    }
+    fn cmp(self, a: &[u32], b: &[u32]) -> Ordering {
+        match self {
+            Order::Lex => a.cmp(b),
+            Order::GrevLex => degree(a).cmp(&degree(b)).then_with(|| {
+                // the smaller exponent of the last variable where they differ is greater
+                for (x, y) in a.iter().zip(b.iter()).rev() {
+                    if x != y {
+                        return y.cmp(x);
+                    }
+                }
+                Ordering::Equal
+            })
+        }
+    }
+}
+
+type Monomial = Vec<u32>;
+
+/// sorted by the order, greatest term first, without zero coefficients
+type Terms = Vec<(Monomial, Rational)>;
+
+fn degree(m: &[u32]) -> u32 {
+    m.iter().sum()
+}
+fn divides(a: &[u32], b: &[u32]) -> bool {
+    a.iter().zip(b.iter()).all(|(x, y)| x <= y)
+}
+fn lcm(a: &[u32], b: &[u32]) -> Monomial {
+    a.iter().zip(b.iter()).map(|(&x, &y)| x.max(y)).collect()
+}
+fn coprime(a: &[u32], b: &[u32]) -> bool {
+    a.iter().zip(b.iter()).all(|(&x, &y)| x == 0 || y == 0)
+}
+
+struct Ring {
+    order: Order
+}
+impl Ring {
+    /// `a + c m b`, where multiplying by the monomial `m` keeps the order of `b`
+    fn add_mul(&self, a: Terms, c: &Rational, m: &[u32], b: &[(Monomial, Rational)]) -> Terms {
+        let mut out = Vec::with_capacity(a.len() + b.len());
+        let mut a = a.into_iter().peekable();
+        let mut b = b.iter()
+            .map(|&(ref n, ref d)| (n.iter().zip(m.iter()).map(|(x, y)| x + y).collect::<Monomial>(), c * d))
+            .peekable();
+        loop {
+            let order = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) => self.order.cmp(&x.0, &y.0),
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (None, None) => break
+            };
+            match order {
+                Ordering::Greater => out.push(a.next().unwrap()),
+                Ordering::Less => out.push(b.next().unwrap()),
+                Ordering::Equal => {
+                    let (n, mut x) = a.next().unwrap();
+                    x += b.next().unwrap().1;
+                    if !x.is_zero() {
+                        out.push((n, x));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// remainder of `f` divided by `basis`, where no term is divisible by a leading term
+    fn reduce(&self, mut f: Terms, basis: &[Terms]) -> Terms {
+        let mut r = vec![];
+        while !f.is_empty() {
+            let (m, c) = f[0].clone();
+            match basis.iter().find(|g| divides(&g[0].0, &m)) {
+                Some(g) => {
+                    let q: Monomial = m.iter().zip(g[0].0.iter()).map(|(x, y)| x - y).collect();
+                    let k = c / g[0].1.clone() * -1;
+                    f = self.add_mul(f, &k, &q, g);
+                },
+                None => r.push(f.remove(0))
+            }
+        }
+        r
+    }
+
+    fn s_poly(&self, f: &[(Monomial, Rational)], g: &[(Monomial, Rational)]) -> Terms {
+        let l = lcm(&f[0].0, &g[0].0);
+        let shift = |m: &[u32]| -> Monomial { l.iter().zip(m.iter()).map(|(x, y)| x - y).collect() };
+        let a = self.add_mul(vec![], &(Rational::from(1) / f[0].1.clone()), &shift(&f[0].0), f);
+        self.add_mul(a, &(Rational::from(-1) / g[0].1.clone()), &shift(&g[0].0), g)
+    }
+
+    fn buchberger(&self, polys: Vec<Terms>) -> Vec<Terms> {
+        let mut basis: Vec<Terms> = polys.into_iter().filter(|p| !p.is_empty()).map(monic).collect();
+        let mut pairs: Vec<(usize, usize)> = (0 .. basis.len())
+            .flat_map(|j| (0 .. j).map(move |i| (i, j)))
+            .collect();
+
+        while !pairs.is_empty() {
+            // the pair with the smallest lcm of the leading terms first
+            let lcm_of = |&(i, j): &(usize, usize)| lcm(&basis[i][0].0, &basis[j][0].0);
+            let next = (0 .. pairs.len())
+                .min_by(|&a, &b| self.order.cmp(&lcm_of(&pairs[a]), &lcm_of(&pairs[b])))
+                .unwrap();
+            let (i, j) = pairs.swap_remove(next);
+
+            // the S-polynomial of coprime leading terms always reduces to zero
+            if coprime(&basis[i][0].0, &basis[j][0].0) {
+                continue;
+            }
+            let s = self.reduce(self.s_poly(&basis[i], &basis[j]), &basis);
+            if !s.is_empty() {
+                pairs.extend((0 .. basis.len()).map(|k| (k, basis.len())));
+                basis.push(monic(s));
+            }
+        }
+
+        // drop elements whose leading term is divisible by another one, then reduce the rest
+        let mut minimal: Vec<Terms> = vec![];
+        for (i, g) in basis.iter().enumerate() {
+            let redundant = basis.iter().enumerate().any(|(j, h)| {
+                j != i && divides(&h[0].0, &g[0].0) && (h[0].0 != g[0].0 || j < i)
+            });
+            if !redundant {
+                minimal.push(g.clone());
+            }
+        }
+        let mut reduced: Vec<Terms> = (0 .. minimal.len()).map(|i| {
+            let others: Vec<Terms> = minimal.iter().enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, g)| g.clone())
+                .collect();
+            let (lead, rest) = minimal[i].split_at(1);
+            let mut g = lead.to_vec();
+            g.extend(self.reduce(rest.to_vec(), &others));
+            g
+        }).collect();
+        reduced.sort_by(|a, b| self.order.cmp(&b[0].0, &a[0].0));
+        reduced
+    }
+}
+
+fn monic(mut p: Terms) -> Terms {
+    let lc = Rational::from(1) / p[0].1.clone();
+    for &mut (_, ref mut c) in p.iter_mut() {
+        *c *= &lc;
+    }
+    p
+}
+
+fn from_poly(p: &Poly, vars: &[NodeRc], order: Order) -> Option<Terms> {
+    let mut terms = vec![];
+    for (base, fac) in p.factors() {
+        let mut m = vec![0; vars.len()];
+        for &(ref v, ref n) in base.iter() {
+            let i = vars.iter().position(|w| w == v)?;
+            m[i] = n.as_i32().filter(|&n| n > 0)? as u32;
+        }
+        terms.push((m, fac.clone()));
+    }
+    terms.sort_by(|a, b| order.cmp(&b.0, &a.0));
+    Some(terms)
+}
+
+fn to_poly(p: &[(Monomial, Rational)], vars: &[NodeRc]) -> Poly {
+    p.iter().fold(Poly::zero(), |sum, &(ref m, ref c)| {
+        let base = vars.iter().zip(m.iter())
+            .map(|(v, &n)| (v.clone(), Rational::from(n as i64)))
+            .collect();
+        sum + Poly::product(base, c.clone())
+    })
+}
+
+/// The reduced Gröbner basis of the ideal generated by `polys`, whose terms may only
+/// contain `vars`.
+///
+/// The elements are sorted by their leading terms, greatest first.
+pub fn groebner(polys: &[Poly], vars: &[NodeRc], order: Order) -> Result<Vec<Poly>, Error> {
+    let terms = polys.iter().map(|p| from_poly(p, vars, order).ok_or_else(|| {
+        Error::Other(format!("{} is not a polynomial in {}", p, vars.iter().join(", ")))
+    })).collect::<Result<Vec<_>, Error>>()?;
+    let ring = Ring { order };
+    Ok(ring.buchberger(terms).iter().map(|g| to_poly(g, vars)).collect())
+}
@@ -0,0 +1,158 @@
+//! Horner and Estrin forms of multivariate polynomials.
+//!
+//! A polynomial is split by the node that occurs in the most terms,
+//! `p = c₀ + c₁ x + c₂ x² + …`, and the coefficients `cₖ` are split again recursively.
+
+use crate::prelude::*;
+use crate::poly::{Poly, Base};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Scheme {
+    /// c₀ + x (c₁ + x (c₂ + x c₃)): fewest multiplications
+    Horner,
+    /// (c₀ + c₁ x) + (c₂ + c₃ x) x²: independent parts, which suits SIMD and pipelining
+    Estrin
+}
+
+/// Rewrite every polynomial in `node` using `scheme`.
+pub fn rewrite(builder: &Builder, node: &NodeRc, scheme: Scheme) -> Result<NodeRc, Error> {
+    Rewriter { builder, scheme, done: HashMap::new() }.node(node)
+}
+
+/// Rewrite the single polynomial `p` (but not the nodes in it) using `scheme`.
+pub fn rewrite_poly(builder: &Builder, p: &Poly, scheme: Scheme) -> Poly {
+    Rewriter { builder, scheme, done: HashMap::new() }.poly(p)
+}
+
+struct Rewriter<'b> {
+    builder: &'b Builder,
+    scheme: Scheme,
+    done: HashMap<NodeRc, NodeRc>
+}
+impl<'b> Rewriter<'b> {
+    fn node(&mut self, node: &NodeRc) -> Result<NodeRc, Error> {
+        if let Some(n) = self.done.get(node) {
+            return Ok(n.clone());
+        }
+        let out = match **node {
+            Node::Poly(ref p) => {
+                let mut inner = Poly::zero();
+                for (base, fac) in p.factors() {
+                    let base = base.iter()
+                        .map(|&(ref v, ref n)| Ok((self.node(v)?, n.clone())))
+                        .collect::<Result<Base, Error>>()?;
+                    inner = inner + Poly::product(base, fac.clone());
+                }
+                let p = self.poly(&inner);
+                self.builder.poly(p)
+            },
+            Node::Apply(ref f, ref g) => {
+                let g = self.node(g)?;
+                self.builder.intern(Node::Apply(f.clone(), g))
+            },
+            Node::Tuple(ref parts) => {
+                let parts = parts.iter().map(|p| self.node(p)).collect::<Result<Vec<_>, _>>()?;
+                self.builder.intern(Node::Tuple(parts))
+            },
+            Node::Var(_) | Node::Op(_) => node.clone()
+        };
+        self.done.insert(node.clone(), out.clone());
+        Ok(out)
+    }
+
+    fn poly(&mut self, p: &Poly) -> Poly {
+        // the node with a positive integer power in the most terms
+        let mut counts: BTreeMap<&NodeRc, usize> = BTreeMap::new();
+        for (base, _) in p.factors() {
+            for &(ref v, ref n) in base.iter() {
+                if positive(n).is_some() {
+                    *counts.entry(v).or_insert(0) += 1;
+                }
+            }
+        }
+        let v = match counts.into_iter().max_by_key(|&(_, c)| c) {
+            Some((v, c)) if c >= 2 => v.clone(),
+            _ => return p.clone() // nothing to share
+        };
+
+        // p = Σ cₖ vᵏ
+        let mut coeffs: BTreeMap<u32, Poly> = BTreeMap::new();
+        for (base, fac) in p.factors() {
+            let k = base.iter().find(|&&(ref w, _)| *w == v).and_then(|&(_, ref n)| positive(n)).unwrap_or(0);
+            let rest: Base = base.iter().filter(|&&(ref w, _)| k == 0 || *w != v).cloned().collect();
+            let c = coeffs.remove(&k).unwrap_or_else(Poly::zero) + Poly::product(rest, fac.clone());
+            coeffs.insert(k, c);
+        }
+        let coeffs: BTreeMap<u32, Poly> = coeffs.into_iter().map(|(k, c)| (k, self.poly(&c))).collect();
+
+        match self.scheme {
+            Scheme::Horner => self.horner(coeffs, &v),
+            Scheme::Estrin => {
+                let n = *coeffs.keys().next_back().unwrap() as usize;
+                let mut dense = vec![Poly::zero(); n + 1];
+                for (k, c) in coeffs {
+                    dense[k as usize] = c;
+                }
+                self.estrin(dense, v)
+            }
+        }
+    }
+
+    fn horner(&mut self, coeffs: BTreeMap<u32, Poly>, v: &NodeRc) -> Poly {
+        let mut terms = coeffs.into_iter().rev();
+        let (mut last, mut acc) = terms.next().unwrap();
+        for (k, c) in terms {
+            acc = self.times_power(acc, v, last - k) + c;
+            last = k;
+        }
+        self.times_power(acc, v, last)
+    }
+
+    /// Σ cs[k] xᵏ
+    fn estrin(&mut self, mut cs: Vec<Poly>, x: NodeRc) -> Poly {
+        if cs.len() == 1 {
+            return cs.pop().unwrap();
+        }
+        let mut pairs = Vec::with_capacity((cs.len() + 1) / 2);
+        let mut iter = cs.into_iter();
+        while let Some(c0) = iter.next() {
+            pairs.push(match iter.next() {
+                Some(c1) => c0 + self.times_power(c1, &x, 1),
+                None => c0
+            });
+        }
+        let x2 = self.builder.poly(Poly::product(vec![(x, 2.into())], 1.into()));
+        self.estrin(pairs, x2)
+    }
+
+    /// acc · vᵏ without expanding acc
+    fn times_power(&mut self, acc: Poly, v: &NodeRc, k: u32) -> Poly {
+        if k == 0 || acc.is_zero() {
+            return acc;
+        }
+        let power = (v.clone(), Rational::from(k as i64));
+        let single = {
+            let mut terms = acc.factors();
+            match (terms.next(), terms.next()) {
+                (Some((base, fac)), None) if base.iter().all(|&(ref w, _)| w != v) => Some((base.clone(), fac.clone())),
+                _ => None
+            }
+        };
+        match single {
+            // a single term takes the power into its monomial
+            Some((mut base, fac)) => {
+                base.push(power);
+                Poly::product(base, fac)
+            },
+            None => Poly::product(vec![power, (self.builder.poly(acc), 1.into())], 1.into())
+        }
+    }
+}
+
+fn positive(n: &Rational) -> Option<u32> {
+    match n.as_i32() {
+        Some(n) if n > 0 => Some(n as u32),
+        _ => None
+    }
+}
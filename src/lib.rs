@@ -6,7 +6,7 @@
 #![feature(asm)]
 #![feature(try_trait)]
 #![feature(proc_macro_hygiene)]
-#![cfg_attr(feature="jit", feature(stdsimd, llvm_asm))]
+#![cfg_attr(feature="jit", feature(stdsimd))]
 #![cfg_attr(feature="nvidia", feature(ptr_internals))]
 
 #[macro_use] extern crate log;
@@ -43,8 +43,16 @@ pub mod poly;      // polynomial representation
 pub mod builder;   // helps you crate function graphs
 pub mod eval;      // enables to actually get "values"
 pub mod integrate; // numerical integration
+pub mod cost;      // operation counts and optimization for evaluation
+pub mod horner;    // Horner and Estrin forms of polynomials
+pub mod factor;    // factorization of polynomials
+pub mod ratfunc;   // rational functions
+pub mod solve;     // real roots of polynomials
+pub mod groebner;  // Gröbner bases of polynomial ideals
+pub mod simplify;  // identities of the elementary functions
+pub mod rules;     // pattern-matching rewrite rules
 pub mod numbers;
-#[cfg(feature="jit")]
+#[cfg(any(feature="jit", feature="simd", feature="nvidia", feature="cranelift"))]
 pub mod rt;        // runtime (various jit compilers, gpu integration)
 pub mod data;
 
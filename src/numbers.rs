@@ -1,4 +1,4 @@
-use std::ops::{MulAssign, AddAssign, DivAssign, Add, Sub, Mul, Div};
+use std::ops::{MulAssign, AddAssign, DivAssign, Add, Sub, Mul, Div, Rem};
 use std::cmp::{Ordering, Ord, PartialEq};
 use std::fmt;
 use num_rational::{BigRational};
@@ -24,6 +24,9 @@ impl Int {
     pub fn is_negative(&self) -> bool {
         self.0.sign() == Sign::Minus
     }
+    pub fn is_odd(&self) -> bool {
+        !(&self.0 % BigInt::from(2)).is_zero()
+    }
     pub fn parse(s: &str, radix: u32) -> Result<Int, Error> {
         match BigInt::parse_bytes(s.as_bytes(), radix) {
             Some(i) => Ok(Int(i)),
@@ -67,6 +70,46 @@ impl Int {
     pub fn abs(self) -> Int {
         Int(self.0.abs())
     }
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+    /// the `n`-th root if `self` is a perfect power
+    pub fn exact_root(&self, n: u32) -> Option<Int> {
+        if self.is_negative() {
+            if n % 2 == 0 {
+                return None;
+            }
+            return Int(-&self.0).exact_root(n).map(|r| Int(-r.0));
+        }
+        if self.0.is_zero() || n == 1 {
+            return Some(self.clone());
+        }
+        // Newton's method from above
+        let (m, m1) = (BigInt::from(n), BigInt::from(n - 1));
+        let mut x = BigInt::one() << (self.0.bits() / n as usize + 1);
+        loop {
+            let y = (&m1 * &x + &self.0 / num_traits::pow(x.clone(), n as usize - 1)) / &m;
+            if y >= x {
+                break;
+            }
+            x = y;
+        }
+        if num_traits::pow(x.clone(), n as usize) == self.0 {
+            Some(Int(x))
+        } else {
+            None
+        }
+    }
+    /// greatest common divisor, never negative
+    pub fn gcd(&self, other: &Int) -> Int {
+        let (mut a, mut b) = (self.0.abs(), other.0.abs());
+        while !b.is_zero() {
+            let r = &a % &b;
+            a = b;
+            b = r;
+        }
+        Int(a)
+    }
 }
 impl From<i32> for Int {
     fn from(i: i32) -> Int {
@@ -125,6 +168,18 @@ impl<'a, 'b> Mul<&'a Int> for &'b Int {
         Int(&self.0 * &rhs.0)
     }
 }
+impl<'a> Div<&'a Int> for Int {
+    type Output = Int;
+    fn div(self, rhs: &'a Int) -> Int {
+        Int(self.0 / &rhs.0)
+    }
+}
+impl<'a> Rem<&'a Int> for Int {
+    type Output = Int;
+    fn rem(self, rhs: &'a Int) -> Int {
+        Int(self.0 % &rhs.0)
+    }
+}
 impl<T> Mul<T> for Int where T: Into<Int> {
     type Output = Int;
     fn mul(self, rhs: T) -> Int {
@@ -201,6 +256,18 @@ impl<'a, 'b> Mul<&'b Rational> for &'a Rational {
         Rational(&self.0 * &rhs.0)
     }
 }
+impl Add for Rational {
+    type Output = Rational;
+    fn add(self, rhs: Rational) -> Rational {
+        Rational(self.0 + rhs.0)
+    }
+}
+impl Sub for Rational {
+    type Output = Rational;
+    fn sub(self, rhs: Rational) -> Rational {
+        Rational(self.0 - rhs.0)
+    }
+}
 impl DivAssign for Rational {
     fn div_assign(&mut self, rhs: Rational) {
         self.0 = &self.0 / rhs.0;
@@ -232,6 +299,13 @@ impl Rational {
             None
         }
     }
+    pub fn as_i32(&self) -> Option<i32> {
+        if self.0.denom().is_one() {
+            self.0.numer().to_i32()
+        } else {
+            None
+        }
+    }
     pub fn as_i64(&self) -> Option<i64> {
         if self.0.denom().is_one() {
             self.0.numer().to_i64()
@@ -239,6 +313,10 @@ impl Rational {
             None
         }
     }
+    /// the largest integer not greater than `self`
+    pub fn floor(&self) -> Int {
+        Int(self.0.floor().to_integer())
+    }
     pub fn as_f64(&self) -> f64 {
         match (self.0.numer().to_f64(), self.0.denom().to_f64()) {
             (Some(n), Some(d)) => n / d,
@@ -252,6 +330,14 @@ impl Rational {
     pub fn is_negative(&self) -> bool {
         (self.0.numer().sign() == Sign::Minus) ^ (self.0.denom().sign() == Sign::Minus)
     }
+    pub fn abs(&self) -> Rational {
+        Rational(self.0.abs())
+    }
+    /// the `n`-th root if numerator and denominator are perfect powers
+    pub fn exact_root(&self, n: u32) -> Option<Rational> {
+        let (num, denom) = self.frac();
+        Some(Rational::new(num.exact_root(n)?, denom.exact_root(n)?))
+    }
     pub fn pow(&self, i: i32) -> Rational {
         match i.cmp(&0) {
             Ordering::Greater => {
@@ -268,6 +354,14 @@ impl Rational {
         }
     }
 }
+impl PartialEq<i64> for Rational {
+    fn eq(&self, other: &i64) -> bool {
+        match self.as_i64() {
+            Some(i) => i.eq(other),
+            None => false
+        }
+    }
+}
 impl From<i64> for Rational {
     fn from(i: i64) -> Rational {
         Rational::new(i.into(), 1.into())
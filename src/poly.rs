@@ -1,57 +1,148 @@
 use crate::prelude::*;
-use std::iter::once;
-use std::collections::hash_map::{HashMap, Entry, Iter};
 use std::ops::{Add, Mul, MulAssign};
-use std::cmp::{min, max, PartialEq, Eq, PartialOrd, Ord, Ordering};
+use std::cmp::{min, PartialEq, Eq, PartialOrd, Ord, Ordering};
+use std::collections::BTreeMap;
 use std::hash::{Hash, Hasher};
 use std::{fmt};
 
-pub type Base = Vec<(NodeRc, Int)>;
+/// nodes with their exponents
+pub type Base = Vec<(NodeRc, Rational)>;
+
+/// A sum of monomials.
+///
+/// The terms are sorted by `grevlex`, leading term first, so iterating over them
+/// does not depend on hashing.
 #[derive(Debug, Clone)]
 pub struct Poly {
-    // never contains a zero
-    elements: HashMap<Base, Rational>,
+    // never contains a zero and every base at most once
+    elements: Vec<(Base, Rational)>,
 }
 
 #[derive(Debug, Clone)]
 pub enum PolyError {
-    DivZero
+    DivZero,
+    NotPolynomial
 }
-fn add_to<'a>(e: Entry<'a, Base, Rational>, r: Rational) {
-    if r.is_zero() {
-        return;
-    }
-    match e {
-        Entry::Vacant(v) => {
-            v.insert(r);
-        },
-        Entry::Occupied(mut o) => {
-            if {
-                let q = o.get_mut();
-                *q += r;
-                q.is_zero()
-            } {
-                o.remove();
-            }
+/// sort `terms` and merge equal bases
+fn normalize(terms: Vec<(Base, Rational)>) -> Vec<(Base, Rational)> {
+    let mut terms: Vec<_> = terms.into_iter().map(|(b, f)| fold_constants(b, f)).collect();
+    terms.sort_by(|a, b| grevlex(&b.0, &a.0));
+    let mut out: Vec<(Base, Rational)> = Vec::with_capacity(terms.len());
+    for (base, fac) in terms {
+        match out.last_mut() {
+            Some(&mut (ref b, ref mut f)) if *b == base => {
+                *f += fac;
+                continue;
+            },
+            _ => {}
         }
+        out.push((base, fac));
     }
+    out.retain(|&(_, ref f)| !f.is_zero());
+    out
 }
 
-fn base<I>(bv: I) -> Base where I: IntoIterator<Item=(NodeRc, Int)>
+fn base<I>(bv: I) -> Base where I: IntoIterator<Item=(NodeRc, Rational)>
 {
     let mut base: Vec<_> = bv.into_iter()
-        .filter(|&(_, ref n)| *n != 0)
+        .filter(|&(_, ref n)| !n.is_zero())
         .collect();
     base.sort();
     base
 }
 
+/// the largest coefficient, in bits, `fold_constants` creates
+const FOLD_BITS: usize = 256;
+
+/// Move the integer part of powers of constants into the coefficient, so that
+/// √2 √2 = 2 and √2⁵ = 4 √2.
+///
+/// Powers whose coefficient would grow beyond `FOLD_BITS` are left alone.
+fn fold_constants(base: Base, mut fac: Rational) -> (Base, Rational) {
+    let mut out = Vec::with_capacity(base.len());
+    for (v, n) in base {
+        if let Node::Poly(ref p) = *v {
+            if let Some(c) = p.as_rational().filter(|c| !c.is_zero()) {
+                let (num, den) = c.frac();
+                let bits = num.bits().max(den.bits());
+                if let Some(k) = n.floor().as_i32().filter(|k| k.abs() as usize * bits <= FOLD_BITS) {
+                    fac *= c.pow(k);
+                    let rest = n - Rational::from(k as i64);
+                    if !rest.is_zero() {
+                        out.push((v.clone(), rest));
+                    }
+                    continue;
+                }
+            }
+        }
+        out.push((v, n));
+    }
+    (out, fac)
+}
+
+/// `c^r` for `r = p/q` with `q > 1`, with the rational part pulled out of the root:
+/// 8^(1/2) = 2 · 2^(1/2) and (1/2)^(1/2) = 2^(1/2) / 2
+fn rational_pow(builder: &Builder, c: Rational, r: Rational) -> Result<Poly, Error> {
+    let (p, q) = r.frac();
+    if c.is_zero() {
+        return match p.is_negative() {
+            true => Err(PolyError::DivZero.into()),
+            false => Ok(Poly::zero())
+        };
+    }
+    let (p, q) = match (p.as_i32(), q.as_i32()) {
+        (Some(p), Some(q)) => (p, q as u32),
+        _ => return Ok(Poly::one(vec![(builder.poly(Poly::rational(c)), r)], 1.into()))
+    };
+    if c.is_negative() {
+        // odd roots of negative numbers are real
+        return match q % 2 {
+            1 => Ok(rational_pow(builder, c * -1, r)? * (if p % 2 == 0 { 1 } else { -1 })),
+            _ => Ok(Poly::one(vec![(builder.poly(Poly::rational(c)), r)], 1.into()))
+        };
+    }
+
+    // (a/b)^(1/q) = (a b^(q-1))^(1/q) / b
+    let (a, b) = c.pow(p).frac();
+    let n = a * &b.pow(q - 1);
+    let (s, rest) = split_power(n, q);
+    let root = match rest == 1 {
+        true => vec![],
+        false => vec![(builder.int(rest), Rational::new(1.into(), Int::from(q as i32)))]
+    };
+    Ok(Poly::one(root, Rational::new(s, b)))
+}
+
+/// trial division stops at this prime candidate
+const MAX_ROOT_DIVISOR: i64 = 1 << 16;
+
+/// `(s, m)` with `n = sᵠ m`, where `m` has no q-th powers of primes below `MAX_ROOT_DIVISOR`
+fn split_power(mut n: Int, q: u32) -> (Int, Int) {
+    let mut s = Int::from(1);
+    if let Some(r) = n.exact_root(q) {
+        return (r, Int::from(1));
+    }
+    let mut d = 2i64;
+    while d < MAX_ROOT_DIVISOR && Int::from(d).pow(q) <= n {
+        let dq = Int::from(d).pow(q);
+        while (n.clone() % &dq).is_zero() {
+            n = n / &dq;
+            s = s * d;
+        }
+        d += 1;
+    }
+    match n.exact_root(q) {
+        Some(r) => (s * &r, Int::from(1)),
+        None => (s, n)
+    }
+}
+
 impl Poly {
     fn one(bv: Base, fac: Rational) -> Poly {
-        Poly { elements: once((base(bv), fac)).collect() }
+        Poly { elements: normalize(vec![(base(bv), fac)]) }
     }
     pub fn zero() -> Poly {
-        Poly { elements: HashMap::new() }
+        Poly { elements: vec![] }
     }
     pub fn rational(r: Rational) -> Poly {
         if r.is_zero() {
@@ -64,6 +155,13 @@ impl Poly {
         let i: Int = i.into();
         Poly::rational(i.into())
     }
+    /// `fac` times the product of `factors`, without expanding them
+    pub fn product(factors: Base, fac: Rational) -> Poly {
+        if fac.is_zero() {
+            return Poly::zero();
+        }
+        Poly::one(factors, fac)
+    }
     pub fn from_node(node: NodeRc) -> Poly {
         if let Node::Poly(ref p) = *node {
             return p.clone();
@@ -71,11 +169,27 @@ impl Poly {
                 
         Poly::one(vec![(node, 1.into())], 1.into())
     }
-    pub fn pow(self, builder: &Builder, i: Int) -> Result<Poly, Error> {
-        if let Some(i) = i.as_i32() {
-            self.pow_i(builder, i)
-        } else {
-            Ok(Poly::one(vec![(builder.poly(self), i)], 1.into()))
+    pub fn pow(self, builder: &Builder, r: Rational) -> Result<Poly, Error> {
+        if let Some(i) = r.as_i32() {
+            return self.pow_i(builder, i);
+        }
+        if r.to_int().is_some() {
+            return Ok(Poly::one(vec![(builder.poly(self), r)], 1.into()));
+        }
+
+        // (c vᵉ)^r = c^r v^(e r) holds for c > 0 if e has an odd numerator, but not for (x²)^(1/2) = |x|
+        let single = match self.elements.as_slice() {
+            [(base, fac)] if !fac.is_negative() && base.len() <= 1 && base.iter().all(|t| t.1.frac().0.is_odd()) => {
+                Some((base.clone(), fac.clone()))
+            },
+            _ => None
+        };
+        match single {
+            Some((base, fac)) => {
+                let base = base.into_iter().map(|(v, e)| (v, e * r.clone())).collect();
+                Ok(rational_pow(builder, fac, r)? * Poly::one(base, 1.into()))
+            },
+            None => Ok(Poly::one(vec![(builder.poly(self), r)], 1.into()))
         }
     }
     pub fn pow_i(self, builder: &Builder, i: i32) -> Result<Poly, Error> {
@@ -104,7 +218,7 @@ impl Poly {
                 return Ok(self.pow_n(i as u32));
             }
         }
-        Ok(Poly::one(vec![(builder.poly(self), i.into())], 1.into()))
+        Ok(Poly::one(vec![(builder.poly(self), Rational::from(i as i64))], 1.into()))
     }
     pub fn pow_n(mut self, mut n: u32) -> Poly {
         let mut p = Poly::int(1);
@@ -123,13 +237,14 @@ impl Poly {
     pub fn is_zero(&self) -> bool {
         self.elements.len() == 0
     }
-    pub fn factors(&self) -> Iter<Base, Rational> {
-        self.elements.iter()
+    /// the terms, leading term first
+    pub fn factors(&self) -> impl Iterator<Item=(&Base, &Rational)> {
+        self.elements.iter().map(|&(ref base, ref fac)| (base, fac))
     }
     pub fn as_rational(&self) -> Option<Rational> {
         match self.elements.len() {
             0 => Some(0.into()),
-            1 => self.elements.get(&vec![]).cloned(),
+            1 if self.elements[0].0.is_empty() => Some(self.elements[0].1.clone()),
             _ => None
         }
     }
@@ -166,46 +281,252 @@ impl Poly {
         }
 
         // common now contains the common factor
-        let elements = self.factors().map(|(bv, rat)| {
+        let elements = normalize(self.factors().map(|(bv, rat)| {
             (
                 base(bv.iter().map(|&(ref v, ref n)| {
                     match common.iter().find(|&&(ref w, _)| w == v) {
-                        Some(&(_, ref m)) => (v.clone(), n.clone() - m),
+                        Some(&(_, ref m)) => (v.clone(), n.clone() - m.clone()),
                         None => (v.clone(), n.clone())
                     }
                 })),
                 rat.clone()
             )
-        }).collect();
+        }).collect());
         
         let poly = Poly { elements };
         let common_poly = Poly::one(common.into_iter().collect(), 1.into());
         Some((common_poly, poly))
     }
+
+    /// the greatest term in grevlex order
+    pub fn leading_term(&self) -> Option<(&Base, &Rational)> {
+        self.elements.first().map(|&(ref base, ref fac)| (base, fac))
+    }
+    /// true if all exponents are non-negative
+    pub fn is_polynomial(&self) -> bool {
+        self.factors().all(|(base, _)| base.iter().all(|&(_, ref n)| n.as_i32().map_or(false, |n| n >= 0)))
+    }
+    /// scaled so that the leading coefficient is 1
+    pub fn monic(mut self) -> Poly {
+        if let Some(lc) = self.elements.first().map(|t| t.1.clone()) {
+            self *= Rational::from(1) / lc;
+        }
+        self
+    }
+
+    /// `(c, q)` with `self = c q`, where `q` has coprime integer coefficients and a positive
+    /// leading coefficient
+    pub fn split_content(mut self) -> (Rational, Poly) {
+        let (mut num, mut den) = (Int::from(0), Int::from(1));
+        for (_, fac) in self.factors() {
+            let (n, d) = fac.frac();
+            num = num.gcd(&n);
+            den = den.clone() * &d / &den.gcd(&d);
+        }
+        if num.is_zero() {
+            return (1.into(), self);
+        }
+        let mut scale = Rational::new(den, num);
+        if self.leading_term().map_or(false, |(_, lc)| lc.is_negative()) {
+            scale = scale * -1;
+        }
+        self *= scale.clone();
+        (Rational::from(1) / scale, self)
+    }
+
+    /// `(q, r)` with `self = q d + r`, where no term of `r` is divisible by the leading term of `d`
+    pub fn div_rem(&self, d: &Poly) -> Result<(Poly, Poly), Error> {
+        if d.is_zero() {
+            return Err(PolyError::DivZero.into());
+        }
+        if !self.is_polynomial() || !d.is_polynomial() {
+            return Err(PolyError::NotPolynomial.into());
+        }
+        Ok(self.divide(d))
+    }
+    fn divide(&self, d: &Poly) -> (Poly, Poly) {
+        let (d_base, d_fac) = d.leading_term().expect("division by zero");
+        let mut p = self.clone();
+        // the leading terms of p only decrease, so q and r are built in order
+        let mut q = vec![];
+        let mut r = vec![];
+        while !p.is_zero() {
+            let (base, fac) = p.elements[0].clone();
+            match divide_base(&base, d_base) {
+                Some(t) => {
+                    let t = Poly::one(t, fac / d_fac.clone());
+                    p = p + t.clone() * d.clone() * (-1);
+                    q.extend(t.elements);
+                },
+                None => r.push(p.elements.remove(0))
+            }
+        }
+        (Poly { elements: q }, Poly { elements: r })
+    }
+
+    /// Greatest common divisor of two polynomials with leading coefficient 1.
+    ///
+    /// The nodes in the bases are treated as independent variables.
+    pub fn gcd(&self, other: &Poly) -> Result<Poly, Error> {
+        if !self.is_polynomial() || !other.is_polynomial() {
+            return Err(PolyError::NotPolynomial.into());
+        }
+        Ok(gcd(self.clone(), other.clone()))
+    }
+
+    /// divide `self` and `denom` by their greatest common divisor
+    pub fn cancel(self, denom: Poly) -> (Poly, Poly) {
+        // monomials in the denominator are already cancelled by their negative powers
+        if denom.elements.len() < 2 || self.as_rational().is_some() || !self.is_polynomial() || !denom.is_polynomial() {
+            return (self, denom);
+        }
+        let g = gcd(self.clone(), denom.clone());
+        if g.as_rational().is_some() {
+            return (self, denom);
+        }
+        (self.divide(&g).0, denom.divide(&g).0)
+    }
+
+    /// `self = Σ cₖ vᵏ`, where no `cₖ` contains a positive power of `v`
+    pub fn coefficients(&self, v: &NodeRc) -> BTreeMap<u32, Poly> {
+        let mut coeffs: BTreeMap<u32, Vec<(Base, Rational)>> = BTreeMap::new();
+        for &(ref b, ref fac) in self.elements.iter() {
+            let k = exponent(b, v);
+            let rest = b.iter().filter(|&&(ref w, _)| k == 0 || w != v).cloned().collect();
+            coeffs.entry(k).or_insert_with(Vec::new).push((rest, fac.clone()));
+        }
+        coeffs.into_iter().map(|(k, terms)| (k, Poly { elements: normalize(terms) })).collect()
+    }
+    /// the highest positive power of `v`, or 0
+    pub fn degree_in(&self, v: &NodeRc) -> u32 {
+        self.factors().map(|(b, _)| exponent(b, v)).max().unwrap_or(0)
+    }
 }
         
+/// the exponent of `v` in `b` if it is positive, otherwise 0
+fn exponent(b: &[(NodeRc, Rational)], v: &NodeRc) -> u32 {
+    b.iter().find(|&&(ref w, _)| w == v)
+        .and_then(|&(_, ref n)| n.as_i32())
+        .map_or(0, |n| n.max(0) as u32)
+}
+
+/// `a / b` if no exponent in `b` is larger than in `a`
+fn divide_base(a: &[(NodeRc, Rational)], b: &[(NodeRc, Rational)]) -> Option<Base> {
+    let mut out = a.to_vec();
+    for &(ref v, ref n) in b {
+        let i = out.iter().position(|&(ref w, _)| w == v)?;
+        if out[i].1 < *n {
+            return None;
+        }
+        out[i].1 = out[i].1.clone() - n.clone();
+    }
+    Some(base(out))
+}
+
+/// gcd of polynomials, recursive in the greatest node
+fn gcd(a: Poly, b: Poly) -> Poly {
+    if a.is_zero() {
+        return b.monic();
+    }
+    if b.is_zero() {
+        return a.monic();
+    }
+    let v = match a.elements.iter().chain(b.elements.iter()).flat_map(|t| t.0.iter()).map(|t| &t.0).max() {
+        Some(v) => v.clone(),
+        None => return Poly::int(1) // both are constants
+    };
+
+    // gcd(a, b) = gcd(content(a), content(b)) · gcd(pp(a), pp(b))
+    let (ca, pa) = primitive(a, &v);
+    let (cb, pb) = primitive(b, &v);
+    let c = gcd(ca, cb);
+
+    // primitive remainder sequence
+    let (mut f, mut g) = if pa.degree_in(&v) >= pb.degree_in(&v) { (pa, pb) } else { (pb, pa) };
+    let h = loop {
+        if g.is_zero() {
+            break f;
+        }
+        if g.degree_in(&v) == 0 {
+            break Poly::int(1);
+        }
+        let r = pseudo_rem(f, &g, &v);
+        f = g;
+        g = primitive(r, &v).1;
+    };
+    (c * h).monic()
+}
+
+/// content (the gcd of the coefficients in `v`) and primitive part of `p`
+fn primitive(p: Poly, v: &NodeRc) -> (Poly, Poly) {
+    if p.is_zero() {
+        return (Poly::zero(), Poly::zero());
+    }
+    let content = p.coefficients(v).into_iter().fold(Poly::zero(), |c, (_, k)| gcd(c, k));
+    let pp = p.divide(&content).0.monic();
+    (content, pp)
+}
+
+/// `r` with `lc(g)ᵏ f = q g + r` and a lower degree in `v` than `g`
+fn pseudo_rem(f: Poly, g: &Poly, v: &NodeRc) -> Poly {
+    let n = g.degree_in(v);
+    let lc_g = g.coefficients(v).remove(&n).unwrap();
+    let mut r = f;
+    while !r.is_zero() {
+        let m = r.degree_in(v);
+        if m < n {
+            break;
+        }
+        // cancel the leading coefficient in v
+        let lc_r = r.coefficients(v).remove(&m).unwrap();
+        let shift = Poly::one(vec![(v.clone(), Rational::from((m - n) as i64))], 1.into());
+        r = r * lc_g.clone() + lc_r * shift * g.clone() * (-1);
+    }
+    r
+}
+
 impl Add for Poly {
     type Output = Poly;
-    fn add(mut self, rhs: Poly) -> Poly {
-        for (base, fac) in rhs.elements.into_iter() {
-            add_to(self.elements.entry(base), fac);
+    fn add(self, rhs: Poly) -> Poly {
+        // merge the sorted terms
+        let mut elements = Vec::with_capacity(self.elements.len() + rhs.elements.len());
+        let mut a = self.elements.into_iter().peekable();
+        let mut b = rhs.elements.into_iter().peekable();
+        loop {
+            let order = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) => grevlex(&x.0, &y.0),
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (None, None) => break
+            };
+            match order {
+                Ordering::Greater => elements.push(a.next().unwrap()),
+                Ordering::Less => elements.push(b.next().unwrap()),
+                Ordering::Equal => {
+                    let (base, mut fac) = a.next().unwrap();
+                    fac += b.next().unwrap().1;
+                    if !fac.is_zero() {
+                        elements.push((base, fac));
+                    }
+                }
+            }
         }
-        self
+        Poly { elements }
     }
 }
 impl Mul for Poly {
     type Output = Poly;
     fn mul(self, rhs: Poly) -> Poly {
         debug!("Poly::mul({}, {}", self, rhs);
-        let mut elements = HashMap::with_capacity(max(self.elements.len(), rhs.elements.len()));
-        for ((a_base, a_fac), (b_base, b_fac)) in self.elements.iter().cartesian_product(rhs.elements.iter()) {
+        let mut elements = Vec::with_capacity(self.elements.len() * rhs.elements.len());
+        for (&(ref a_base, ref a_fac), &(ref b_base, ref b_fac)) in self.elements.iter().cartesian_product(rhs.elements.iter()) {
             // multiply base vector by adding powers
             let mut base = a_base.clone();
             for &(ref v, ref n) in b_base.iter() {
                 match base.iter().position(|b| *v == b.0) {
                     Some(i) => {
-                        base[i].1 += &n;
-                        if base[i].1 == 0 {
+                        base[i].1 += n.clone();
+                        if base[i].1.is_zero() {
                             base.swap_remove(i);
                         }
                     }
@@ -216,9 +537,9 @@ impl Mul for Poly {
                 Ordering::Equal => a.1.cmp(&b.1),
                 o => o
             });
-            add_to(elements.entry(base), a_fac * b_fac);
+            elements.push((base, a_fac * b_fac));
         }
-        Poly { elements }
+        Poly { elements: normalize(elements) }
     }
 }
 
@@ -236,7 +557,10 @@ impl MulAssign<Int> for Poly {
 }
 impl MulAssign<Rational> for Poly {
     fn mul_assign(&mut self, rhs: Rational) {
-        for fac in self.elements.values_mut() {
+        if rhs.is_zero() {
+            self.elements.clear();
+        }
+        for &mut (_, ref mut fac) in self.elements.iter_mut() {
             *fac *= &rhs;
         }
     }
@@ -244,7 +568,7 @@ impl MulAssign<Rational> for Poly {
 
 impl PartialEq for Poly {
     fn eq(&self, rhs: &Poly) -> bool {
-        (self.elements.len() == rhs.elements.len()) && self.elements.iter().all(|(k, v)| rhs.elements.get(k) == Some(v))
+        self.elements == rhs.elements
     }
 }
 impl Eq for Poly {}
@@ -261,39 +585,46 @@ impl Ord for Poly {
 }
 
 fn cmp_poly(a: &Poly, b: &Poly) -> Ordering {
-    match a.elements.len().cmp(&b.elements.len()) {
-        Ordering::Equal => {
-            let mut e_a: Vec<_> = a.elements.iter().collect();
-            let mut e_b: Vec<_> = b.elements.iter().collect();
-            e_a.sort();
-            e_b.sort();
-            for (a, b) in e_a.iter().zip(e_b.iter()) {
-                match cmp_base(a.0, b.0) {
-                    Ordering::Equal => continue,
-                    o => return o
-                }
+    a.elements.len().cmp(&b.elements.len()).then_with(|| {
+        for (&(ref a_base, ref a_fac), &(ref b_base, ref b_fac)) in a.elements.iter().zip(b.elements.iter()) {
+            match grevlex(a_base, b_base).then_with(|| a_fac.cmp(b_fac)) {
+                Ordering::Equal => continue,
+                o => return o
             }
-            Ordering::Equal
-        },
-        o => o
-    }
+        }
+        Ordering::Equal
+    })
 }
 
-pub fn cmp_base(a: &[(NodeRc, Int)], b: &[(NodeRc, Int)]) -> Ordering {
-    match a.len().cmp(&b.len()) {
+/// Graded reverse lexicographic order of two sorted bases.
+///
+/// The higher total degree is greater. On equal degree the base with the smaller
+/// exponent of the last (greatest) node where they differ is greater, so
+/// x² > x y > y² > x z > y z > z².
+pub fn grevlex(a: &[(NodeRc, Rational)], b: &[(NodeRc, Rational)]) -> Ordering {
+    let degree = |m: &[(NodeRc, Rational)]| m.iter().fold(Rational::from(0), |d, &(_, ref n)| d + n.clone());
+    match degree(a).cmp(&degree(b)) {
         Ordering::Equal => {},
         o => return o
     }
-    for (a, b) in a.iter().zip(b.iter()) {
-        match a.0.cmp(&b.0) {
-            Ordering::Equal => continue,
-            o => return o
-        }
-    }
-    for (a, b) in a.iter().zip(b.iter()) {
-        match a.1.cmp(&b.1) {
+
+    // walk both from the greatest node down
+    let zero = Rational::from(0);
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 || j > 0 {
+        let (n, m) = match (a[.. i].last(), b[.. j].last()) {
+            (Some(x), Some(y)) => match x.0.cmp(&y.0) {
+                Ordering::Equal => { i -= 1; j -= 1; (&x.1, &y.1) },
+                Ordering::Greater => { i -= 1; (&x.1, &zero) },
+                Ordering::Less => { j -= 1; (&zero, &y.1) }
+            },
+            (Some(x), None) => { i -= 1; (&x.1, &zero) },
+            (None, Some(y)) => { j -= 1; (&zero, &y.1) },
+            (None, None) => unreachable!()
+        };
+        match n.cmp(m) {
             Ordering::Equal => continue,
-            o => return o
+            o => return o.reverse()
         }
     }
     Ordering::Equal
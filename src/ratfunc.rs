@@ -0,0 +1,297 @@
+//! Rational functions: a numerator over a product of coprime denominator factors.
+//!
+//! Division in `Poly` leaves denominators as negative powers inside the bases, so
+//! `1/(x+1) + 1/(x-1)` stays a sum of two terms. Here both are brought over a common
+//! denominator and common factors of numerator and denominator are cancelled.
+
+use crate::prelude::*;
+use crate::poly::{Poly, PolyError};
+use crate::factor::factor;
+
+/// `num / Π fᵢ^kᵢ`
+#[derive(Debug, Clone)]
+pub struct RatFunc {
+    pub num: Poly,
+    /// distinct, non-constant and coprime to `num`, each with coprime integer
+    /// coefficients and a positive leading coefficient
+    pub den: Vec<(Poly, u32)>
+}
+
+impl RatFunc {
+    pub fn poly(p: Poly) -> RatFunc {
+        RatFunc { num: p, den: vec![] }
+    }
+
+    /// the expanded denominator
+    pub fn denominator(&self) -> Poly {
+        self.den.iter().fold(Poly::int(1), |d, &(ref f, k)| d * f.clone().pow_n(k))
+    }
+
+    pub fn mul(self, rhs: RatFunc) -> Result<RatFunc, Error> {
+        let mut den = self.den;
+        den.extend(rhs.den);
+        RatFunc { num: self.num * rhs.num, den: merge(den) }.reduce()
+    }
+
+    pub fn add(self, rhs: RatFunc) -> Result<RatFunc, Error> {
+        // every factor with the larger of both exponents
+        let mut den = self.den.clone();
+        for &(ref f, k) in rhs.den.iter() {
+            match den.iter_mut().find(|t| t.0 == *f) {
+                Some(t) => t.1 = t.1.max(k),
+                None => den.push((f.clone(), k))
+            }
+        }
+        let num = self.num * cofactor(&den, &self.den) + rhs.num * cofactor(&den, &rhs.den);
+        RatFunc { num, den: merge(den) }.reduce()
+    }
+
+    pub fn inv(self) -> Result<RatFunc, Error> {
+        if self.num.is_zero() {
+            return Err(PolyError::DivZero.into());
+        }
+        let (c, q) = self.num.split_content();
+        let mut num = self.denominator();
+        num *= Rational::from(1) / c;
+        let mut den = vec![];
+        push_factor(&mut den, q, 1);
+        Ok(RatFunc { num, den: merge(den) })
+    }
+
+    pub fn pow(self, n: i32) -> Result<RatFunc, Error> {
+        if n < 0 {
+            return self.inv()?.pow(-n);
+        }
+        let n = n as u32;
+        Ok(RatFunc {
+            num: self.num.pow_n(n),
+            den: if n == 0 { vec![] } else { self.den.into_iter().map(|(f, k)| (f, k * n)).collect() }
+        })
+    }
+
+    /// cancel the common factors of numerator and denominator
+    fn reduce(self) -> Result<RatFunc, Error> {
+        let mut num = self.num;
+        if num.is_zero() {
+            return Ok(RatFunc::poly(num));
+        }
+        let mut work = self.den;
+        let mut den = vec![];
+        // every step lowers the degree of num
+        while let Some((f, k)) = work.pop() {
+            let g = num.gcd(&f)?;
+            if g.as_rational().is_some() {
+                den.push((f, k));
+                continue;
+            }
+            num = num.div_rem(&g)?.0;
+            let (c, h) = f.div_rem(&g)?.0.split_content();
+            num *= Rational::from(1) / c;
+            if k > 1 {
+                work.push((f, k - 1));
+            }
+            push_factor(&mut work, h, 1);
+        }
+        Ok(RatFunc { num, den: merge(den) })
+    }
+
+    /// Partial fraction decomposition in `v`.
+    ///
+    /// Numerator and denominator must not contain other nodes than `v`.
+    fn apart(&self, builder: &Builder) -> Result<Poly, Error> {
+        let d = self.denominator();
+        let (mut out, mut r) = self.num.div_rem(&d)?;
+        let f = factor(&d)?;
+        r *= Rational::from(1) / f.unit;
+
+        let powers: Vec<Poly> = f.factors.iter().map(|&(ref p, k)| p.clone().pow_n(k)).collect();
+        for (i, &(ref p, k)) in f.factors.iter().enumerate() {
+            let others = powers.iter().enumerate()
+                .filter(|&(j, _)| j != i)
+                .fold(Poly::int(1), |a, (_, b)| a * b.clone());
+
+            // r / d = Σ aᵢ / pᵢ^kᵢ with aᵢ = r · others⁻¹ mod pᵢ^kᵢ
+            let mut a = (r.clone() * inverse(&others, &powers[i])?).div_rem(&powers[i])?.1;
+
+            // aᵢ = Σ cⱼ pᵢʲ
+            for j in 0 .. k {
+                let (q, c) = a.div_rem(p)?;
+                out = out + fraction(builder, c, &[(p.clone(), k - j)]);
+                a = q;
+            }
+        }
+        Ok(out)
+    }
+
+    /// `expand`: multiply out the denominator instead of keeping its factors
+    pub fn to_node(&self, builder: &Builder, expand: bool) -> NodeRc {
+        let p = match expand {
+            true if self.den.len() > 0 => fraction(builder, self.num.clone(), &[(self.denominator(), 1)]),
+            _ => fraction(builder, self.num.clone(), &self.den)
+        };
+        builder.poly(unwrap_roots(p))
+    }
+}
+
+/// sort the factors and add the exponents of equal ones
+fn merge(mut den: Vec<(Poly, u32)>) -> Vec<(Poly, u32)> {
+    den.sort();
+    let mut out: Vec<(Poly, u32)> = Vec::with_capacity(den.len());
+    for (f, k) in den {
+        match out.last_mut() {
+            Some(&mut (ref g, ref mut n)) if *g == f => {
+                *n += k;
+                continue;
+            },
+            _ => {}
+        }
+        out.push((f, k));
+    }
+    out
+}
+
+/// add the integral `p` to the factors, split into its monomial part and the rest
+fn push_factor(den: &mut Vec<(Poly, u32)>, p: Poly, k: u32) {
+    if p.as_rational().is_some() {
+        return;
+    }
+    let (common, rest) = match p.factorize() {
+        Some(parts) => parts,
+        None => (Poly::int(1), p)
+    };
+    for q in vec![common, rest] {
+        match q.factors().count() {
+            1 => for (base, _) in q.factors() {
+                for &(ref v, ref n) in base.iter() {
+                    let n = n.as_i32().unwrap() as u32;
+                    den.push((Poly::from_node(v.clone()), n * k));
+                }
+            },
+            _ => den.push((q, k))
+        }
+    }
+}
+
+/// `Π fᵢ^(kᵢ - mᵢ)` where the `fᵢ^mᵢ` are the factors in `part`
+fn cofactor(den: &[(Poly, u32)], part: &[(Poly, u32)]) -> Poly {
+    den.iter().fold(Poly::int(1), |p, &(ref f, k)| {
+        let m = part.iter().find(|t| t.0 == *f).map_or(0, |t| t.1);
+        p * f.clone().pow_n(k - m)
+    })
+}
+
+/// `num / Π fᵢ^kᵢ` as a single term, with multi-term polynomials kept as nodes
+fn fraction(builder: &Builder, num: Poly, den: &[(Poly, u32)]) -> Poly {
+    if num.is_zero() {
+        return num;
+    }
+    let (c, num) = num.split_content();
+    let factor = |p: &Poly, k: i32| match p.factors().count() {
+        1 => {
+            let (base, fac) = p.leading_term().unwrap();
+            let base = base.iter().map(|&(ref v, ref n)| (v.clone(), n.clone() * k)).collect();
+            Poly::product(base, fac.pow(k))
+        },
+        _ => Poly::product(vec![(builder.poly(p.clone()), Rational::from(k as i64))], 1.into())
+    };
+    den.iter().fold(
+        Poly::rational(c) * factor(&num, 1),
+        |p, &(ref f, k)| p * factor(f, -(k as i32))
+    )
+}
+
+/// merge the fractional powers wrapped by `from_poly` back into the terms
+fn unwrap_roots(p: Poly) -> Poly {
+    p.factors().fold(Poly::zero(), |sum, (base, fac)| {
+        let mut out = vec![];
+        for &(ref v, ref n) in base.iter() {
+            let root = match **v {
+                Node::Poly(ref q) if q.factors().count() == 1 && n.to_int().is_some() => q.leading_term()
+                    .filter(|&(b, c)| *c == 1 && b.iter().any(|t| t.1.to_int().is_none())),
+                _ => None
+            };
+            match root {
+                Some((b, _)) => out.extend(b.iter().map(|&(ref w, ref m)| (w.clone(), m.clone() * n.clone()))),
+                None => out.push((v.clone(), n.clone()))
+            }
+        }
+        sum + Poly::product(out, fac.clone())
+    })
+}
+
+/// `s` with `s b ≡ 1 mod m` for coprime univariate `b` and `m`
+fn inverse(b: &Poly, m: &Poly) -> Result<Poly, Error> {
+    // sᵢ b ≡ rᵢ mod m
+    let (mut r0, mut r1) = (m.clone(), b.div_rem(m)?.1);
+    let (mut s0, mut s1) = (Poly::zero(), Poly::int(1));
+    while !r1.is_zero() {
+        let (q, r) = r0.div_rem(&r1)?;
+        let s = s0 + q * s1.clone() * (-1);
+        r0 = r1;
+        r1 = r;
+        s0 = s1;
+        s1 = s;
+    }
+    match r0.as_rational() {
+        Some(c) => {
+            s0 *= Rational::from(1) / c;
+            Ok(s0)
+        },
+        None => bug!("partial fractions of factors that are not coprime")
+    }
+}
+
+/// `p` as a rational function, with the polynomials inside it combined as well
+pub fn from_poly(builder: &Builder, p: &Poly, expand: bool) -> Result<RatFunc, Error> {
+    let mut sum = RatFunc::poly(Poly::zero());
+    for (base, fac) in p.factors() {
+        let mut term = RatFunc::poly(Poly::rational(fac.clone()));
+        for &(ref v, ref n) in base.iter() {
+            if n.to_int().is_none() {
+                // a fractional power is an independent variable, wrapped in a node of its own
+                let root = Poly::product(vec![(together(builder, v, expand)?, n.clone())], 1.into());
+                let atom = Poly::product(vec![(builder.poly(root), 1.into())], 1.into());
+                term = term.mul(RatFunc::poly(atom))?;
+                continue;
+            }
+            let n = n.as_i32().ok_or(Error::Overflow)?;
+            let atom = match **v {
+                Node::Poly(ref q) => from_poly(builder, q, expand)?,
+                _ => RatFunc::poly(Poly::from_node(together(builder, v, expand)?))
+            };
+            term = term.mul(atom.pow(n)?)?;
+        }
+        sum = sum.add(term)?;
+    }
+    Ok(sum)
+}
+
+/// Write every polynomial in `node` over a common denominator without common factors.
+///
+/// `expand`: multiply out the denominator.
+pub fn together(builder: &Builder, node: &NodeRc, expand: bool) -> Result<NodeRc, Error> {
+    Ok(match **node {
+        Node::Poly(ref p) => from_poly(builder, p, expand)?.to_node(builder, expand),
+        Node::Apply(ref f, ref g) => builder.intern(Node::Apply(f.clone(), together(builder, g, expand)?)),
+        Node::Tuple(ref parts) => builder.tuple(parts.iter().map(|p| together(builder, p, expand)))?,
+        Node::Var(_) | Node::Op(_) => node.clone()
+    })
+}
+
+/// Partial fraction decomposition of every rational function in `v` within `node`.
+///
+/// Polynomials that also contain other nodes are only cancelled.
+pub fn apart(builder: &Builder, node: &NodeRc, v: &NodeRc) -> Result<NodeRc, Error> {
+    Ok(match **node {
+        Node::Poly(ref p) => {
+            let f = from_poly(builder, p, true)?;
+            let only_v = |p: &Poly| p.factors().all(|(b, _)| b.iter().all(|t| t.0 == *v));
+            if f.den.is_empty() || !only_v(&f.num) || !f.den.iter().all(|t| only_v(&t.0)) {
+                return Ok(f.to_node(builder, true));
+            }
+            builder.poly(f.apart(builder)?)
+        },
+        Node::Tuple(ref parts) => builder.tuple(parts.iter().map(|p| apart(builder, p, v)))?,
+        Node::Var(_) | Node::Op(_) | Node::Apply(..) => node.clone()
+    })
+}
@@ -0,0 +1,307 @@
+//! AArch64 NEON encoder.
+//!
+//! All vector operations work on the `4S` arrangement (f32x4) of the `v` registers.
+
+use crate::vm::simd::{SimdAsm, Source, Instr, Reg as SimdReg};
+use crate::vm::{Round, Cmp};
+
+/// general purpose register x0-x30
+#[derive(Copy, Clone)]
+pub struct XReg(pub u8);
+
+/// the fixed bits of an instruction. registers are or'ed in.
+pub type Opcode = u32;
+
+pub mod op {
+    use super::Opcode;
+
+    // Vd, Vn, Vm
+    pub const FADD: Opcode   = 0x4e20d400;
+    pub const FSUB: Opcode   = 0x4ea0d400;
+    pub const FMUL: Opcode   = 0x6e20dc00;
+    pub const FDIV: Opcode   = 0x6e20fc00;
+    pub const FMLA: Opcode   = 0x4e20cc00; // Vd += Vn * Vm
+    pub const FRECPS: Opcode = 0x4e20fc00; // 2 - Vn * Vm
+    pub const FCMGE: Opcode  = 0x6e20e400;
+    pub const FCMGT: Opcode  = 0x6ea0e400;
+    pub const FCMEQ: Opcode  = 0x4e20e400;
+    pub const BSL: Opcode    = 0x6e601c00; // Vd = Vd ? Vn : Vm (bitwise)
+    pub const ORR: Opcode    = 0x4ea01c00;
+
+    // Vd, Vn
+    pub const FRINTM: Opcode = 0x4e219800;
+    pub const FRINTP: Opcode = 0x4ea18800;
+    pub const FRECPE: Opcode = 0x4ea1d800;
+    pub const FSQRT: Opcode  = 0x6ea1f800;
+    pub const MVN: Opcode    = 0x6e205800;
+
+    // Qt, [Xn, #imm]
+    pub const LDR: Opcode    = 0x3dc00000;
+    pub const STR: Opcode    = 0x3d800000;
+
+    pub const MOVI_ZERO: Opcode = 0x6f00e400; // movi Vd.2d, #0
+
+    // Xd, #imm16, lsl #(16 * hw)
+    pub const MOVZ: Opcode   = 0xd2800000;
+    pub const MOVK: Opcode   = 0xf2800000;
+    // Xd, Xn, Xm
+    pub const ADD_X: Opcode  = 0x8b000000;
+    pub const RET: Opcode    = 0xd65f03c0;
+}
+
+pub struct Writer {
+    buf: Vec<u8>
+}
+impl Writer {
+    pub fn new() -> Writer {
+        Writer { buf: Vec::with_capacity(256) }
+    }
+    pub fn finish(mut self) -> Vec<u8> {
+        self.push(op::RET);
+        self.buf
+    }
+    fn push(&mut self, instr: u32) {
+        self.buf.extend_from_slice(&instr.to_le_bytes());
+    }
+
+    /// three register operation: `op Vd, Vn, Vm`
+    pub fn rrr(&mut self, op: Opcode, d: u8, n: u8, m: u8) {
+        assert!(d < 32 && n < 32 && m < 32);
+        self.push(op | (m as u32) << 16 | (n as u32) << 5 | d as u32);
+    }
+
+    /// two register operation: `op Vd, Vn`
+    pub fn rr(&mut self, op: Opcode, d: u8, n: u8) {
+        self.rrr(op, d, n, 0);
+    }
+
+    /// `mov Vd, Vn`
+    pub fn mov(&mut self, d: u8, n: u8) {
+        self.rrr(op::ORR, d, n, n);
+    }
+
+    /// set all bits of Vd to zero
+    pub fn zero(&mut self, d: u8) {
+        self.rr(op::MOVI_ZERO, d, 0);
+    }
+
+    /// load or store `Qt` at `[base, #offset]`
+    ///
+    /// Offsets that don't fit the immediate are added to `base` in `ADDRESS` first.
+    pub fn mem(&mut self, op: Opcode, t: u8, base: XReg, offset: u32) {
+        assert!(t < 32 && base.0 < 32);
+        if offset % 16 == 0 && offset / 16 < 4096 {
+            self.push(op | (offset / 16) << 10 | (base.0 as u32) << 5 | t as u32);
+        } else {
+            self.push(op::MOVZ | (offset & 0xffff) << 5 | ADDRESS.0 as u32);
+            if offset >> 16 != 0 {
+                self.push(op::MOVK | 1 << 21 | (offset >> 16) << 5 | ADDRESS.0 as u32);
+            }
+            self.push(op::ADD_X | (ADDRESS.0 as u32) << 16 | (base.0 as u32) << 5 | ADDRESS.0 as u32);
+            self.push(op | (ADDRESS.0 as u32) << 5 | t as u32);
+        }
+    }
+}
+
+// arguments in x0-x2 according to AAPCS64
+const CONSTS: XReg = XReg(0);
+const INPUTS: XReg = XReg(1);
+const OUTPUTS: XReg = XReg(2);
+// a temporary register for addresses with large offsets
+const ADDRESS: XReg = XReg(9);
+
+// SimdAsm only uses v0-v15
+const SCRATCH: u8 = 16;
+const SCRATCH2: u8 = 17;
+
+/// Encode the instructions of `asm` as a function
+/// `fn(consts: *const f32x4, inputs: *const f32x4, outputs: *mut f32x4)`.
+///
+/// Every constant and input occupies 16 bytes, so `asm.consts` needs to be splatted to f32x4.
+pub fn assemble(asm: &SimdAsm, outputs: &[Source]) -> Vec<u8> {
+    let mut w = Writer::new();
+    let reg = |r: SimdReg| r.0;
+
+    // memory operands are loaded into `scratch` first
+    let operand = |w: &mut Writer, s: Source, scratch: u8| match s {
+        Source::Reg(r) => reg(r),
+        Source::Const(idx) => {
+            w.mem(op::LDR, scratch, CONSTS, idx as u32 * 16);
+            scratch
+        },
+        Source::Input(idx) => {
+            w.mem(op::LDR, scratch, INPUTS, idx as u32 * 16);
+            scratch
+        }
+    };
+
+    for instr in asm.instr.iter() {
+        match *instr {
+            Instr::Add(r0, r1, s) => {
+                let m = operand(&mut w, s, SCRATCH);
+                w.rrr(op::FADD, reg(r0), reg(r1), m);
+            },
+            Instr::Sub(r0, r1, s) => {
+                let m = operand(&mut w, s, SCRATCH);
+                w.rrr(op::FSUB, reg(r0), reg(r1), m);
+            },
+            Instr::Mul(r0, r1, s) => {
+                let m = operand(&mut w, s, SCRATCH);
+                w.rrr(op::FMUL, reg(r0), reg(r1), m);
+            },
+            Instr::Div(r0, r1, s) => {
+                let m = operand(&mut w, s, SCRATCH);
+                w.rrr(op::FDIV, reg(r0), reg(r1), m);
+            },
+            Instr::Inv(r0, s) => {
+                // estimate and one newton step, which is about as precise as vrcpps
+                let x = operand(&mut w, s, SCRATCH);
+                w.rr(op::FRECPE, SCRATCH2, x);
+                w.rrr(op::FRECPS, reg(r0), x, SCRATCH2);
+                w.rrr(op::FMUL, reg(r0), reg(r0), SCRATCH2);
+            },
+            Instr::Sqrt(r0, s) => {
+                let x = operand(&mut w, s, SCRATCH);
+                w.rr(op::FSQRT, reg(r0), x);
+            },
+            Instr::Round(r0, s, dir) => {
+                let n = operand(&mut w, s, SCRATCH);
+                w.rr(match dir {
+                    Round::Down => op::FRINTM,
+                    Round::Up => op::FRINTP
+                }, reg(r0), n);
+            },
+            Instr::Load(r0, s) => match s {
+                Source::Reg(r) => w.mov(reg(r0), reg(r)),
+                s => { operand(&mut w, s, reg(r0)); }
+            },
+            Instr::Cmp(r0, r1, s, ord) => {
+                let (d, a) = (reg(r0), reg(r1));
+                let b = operand(&mut w, s, SCRATCH);
+                match ord {
+                    Cmp::GE => w.rrr(op::FCMGE, d, a, b),
+                    Cmp::GT => w.rrr(op::FCMGT, d, a, b),
+                    Cmp::LE => w.rrr(op::FCMGE, d, b, a),
+                    Cmp::LT => w.rrr(op::FCMGT, d, b, a),
+                    Cmp::EQ => w.rrr(op::FCMEQ, d, a, b),
+                    Cmp::NE => {
+                        w.rrr(op::FCMEQ, d, a, b);
+                        w.rr(op::MVN, d, d);
+                    }
+                }
+            },
+            Instr::MaskMove(r0, r1, s) => {
+                // r0 = mask ? s : 0
+                let n = operand(&mut w, s, SCRATCH);
+                w.zero(SCRATCH2);
+                w.mov(reg(r0), reg(r1));
+                w.rrr(op::BSL, reg(r0), n, SCRATCH2);
+            }
+        }
+    }
+    for (i, &s) in outputs.iter().enumerate() {
+        let r = operand(&mut w, s, SCRATCH);
+        w.mem(op::STR, r, OUTPUTS, i as u32 * 16);
+    }
+
+    w.finish()
+}
+
+#[test]
+fn test_opcodes() {
+    let mut w = Writer::new();
+    w.rrr(op::FADD, 0, 1, 2);
+    w.rrr(op::FADD, 31, 16, 7);
+    w.rrr(op::FSUB, 3, 4, 5);
+    w.rrr(op::FMUL, 6, 7, 8);
+    w.rrr(op::FDIV, 9, 10, 11);
+    w.rrr(op::FMLA, 12, 13, 14);
+    w.rr(op::FRINTM, 1, 2);
+    w.rr(op::FRINTP, 3, 4);
+    w.rr(op::FRECPE, 5, 6);
+    w.rr(op::FSQRT, 10, 11);
+    w.rrr(op::FRECPS, 7, 8, 9);
+    w.rrr(op::FCMGE, 1, 2, 3);
+    w.rrr(op::FCMGT, 4, 5, 6);
+    w.rrr(op::FCMEQ, 7, 8, 9);
+    w.rr(op::MVN, 1, 2);
+    w.rrr(op::BSL, 1, 2, 3);
+    w.mov(4, 5);
+    w.zero(6);
+    w.mem(op::LDR, 0, XReg(0), 0);
+    w.mem(op::LDR, 17, XReg(1), 32);
+    w.mem(op::STR, 2, XReg(2), 65520);
+    w.mem(op::LDR, 3, XReg(0), 65536);
+    w.mem(op::STR, 2, XReg(1), 0x12340);
+
+    let a = w.finish();
+    let b = vec![
+        0x20, 0xd4, 0x22, 0x4e, // fadd v0.4s, v1.4s, v2.4s
+        0x1f, 0xd6, 0x27, 0x4e, // fadd v31.4s, v16.4s, v7.4s
+        0x83, 0xd4, 0xa5, 0x4e, // fsub v3.4s, v4.4s, v5.4s
+        0xe6, 0xdc, 0x28, 0x6e, // fmul v6.4s, v7.4s, v8.4s
+        0x49, 0xfd, 0x2b, 0x6e, // fdiv v9.4s, v10.4s, v11.4s
+        0xac, 0xcd, 0x2e, 0x4e, // fmla v12.4s, v13.4s, v14.4s
+        0x41, 0x98, 0x21, 0x4e, // frintm v1.4s, v2.4s
+        0x83, 0x88, 0xa1, 0x4e, // frintp v3.4s, v4.4s
+        0xc5, 0xd8, 0xa1, 0x4e, // frecpe v5.4s, v6.4s
+        0x6a, 0xf9, 0xa1, 0x6e, // fsqrt v10.4s, v11.4s
+        0x07, 0xfd, 0x29, 0x4e, // frecps v7.4s, v8.4s, v9.4s
+        0x41, 0xe4, 0x23, 0x6e, // fcmge v1.4s, v2.4s, v3.4s
+        0xa4, 0xe4, 0xa6, 0x6e, // fcmgt v4.4s, v5.4s, v6.4s
+        0x07, 0xe5, 0x29, 0x4e, // fcmeq v7.4s, v8.4s, v9.4s
+        0x41, 0x58, 0x20, 0x6e, // mvn v1.16b, v2.16b
+        0x41, 0x1c, 0x63, 0x6e, // bsl v1.16b, v2.16b, v3.16b
+        0xa4, 0x1c, 0xa5, 0x4e, // mov v4.16b, v5.16b
+        0x06, 0xe4, 0x00, 0x6f, // movi v6.2d, #0
+        0x00, 0x00, 0xc0, 0x3d, // ldr q0, [x0]
+        0x31, 0x08, 0xc0, 0x3d, // ldr q17, [x1, #32]
+        0x42, 0xfc, 0xbf, 0x3d, // str q2, [x2, #65520]
+        0x09, 0x00, 0x80, 0xd2, // mov x9, #0
+        0x29, 0x00, 0xa0, 0xf2, // movk x9, #0x1, lsl #16
+        0x09, 0x00, 0x09, 0x8b, // add x9, x0, x9
+        0x23, 0x01, 0xc0, 0x3d, // ldr q3, [x9]
+        0x09, 0x68, 0x84, 0xd2, // mov x9, #0x2340
+        0x29, 0x00, 0xa0, 0xf2, // movk x9, #0x1, lsl #16
+        0x29, 0x00, 0x09, 0x8b, // add x9, x1, x9
+        0x22, 0x01, 0x80, 0x3d, // str q2, [x9]
+        0xc0, 0x03, 0x5f, 0xd6, // ret
+    ];
+
+    println!("");
+    for (i, (a, b)) in a.chunks(4).zip(b.chunks(4)).enumerate() {
+        println!("{:4x}  {:02x?}  {:02x?}  {}", 4 * i, a, b, if a == b {"✓"} else {""});
+    }
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_assemble() {
+    use crate::compiler::Compiler;
+    use crate::builder::Builder;
+
+    let b = Builder::new();
+    let n = b.parse("x * y + 2").unwrap();
+    let mut asm = SimdAsm::new();
+    let outputs = Compiler::compile(&mut asm, &[n], &["x", "y"]).unwrap();
+    let code = assemble(&asm, &outputs);
+
+    // whole instructions, ending in ret
+    assert_eq!(code.len() % 4, 0);
+    assert_eq!(&code[code.len() - 4 ..], &[0xc0, 0x03, 0x5f, 0xd6]);
+    // the result is stored to [x2]
+    let store = 0x3d800000u32 | (OUTPUTS.0 as u32) << 5;
+    assert!(code.chunks(4).any(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) & !0x1f == store));
+}
+
+#[test]
+fn test_many_consts() {
+    // more constants than fit the immediate offset of ldr
+    let mut asm = SimdAsm::new();
+    asm.consts = vec![1.0; 5000];
+    asm.instr.push(Instr::Load(SimdReg(0), Source::Const(4999)));
+    let code = assemble(&asm, &[Source::Reg(SimdReg(0))]);
+
+    let add = op::ADD_X | (ADDRESS.0 as u32) << 16 | (CONSTS.0 as u32) << 5 | ADDRESS.0 as u32;
+    assert!(code.chunks(4).any(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) == add));
+}
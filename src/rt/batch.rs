@@ -0,0 +1,202 @@
+//! Evaluate compiled code over large arrays on several threads.
+
+use crate::util::duration_as_seconds;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Compiled code that can evaluate a contiguous range of values.
+pub trait Eval: Sync {
+    type Value: Copy + Send + Sync;
+
+    /// how many values are computed at once. chunks are split at multiples of this.
+    const LANES: usize;
+
+    fn num_inputs(&self) -> usize;
+    fn num_outputs(&self) -> usize;
+
+    /// all slices have the same length
+    fn eval_slices(&self, inputs: &[&[Self::Value]], outputs: &mut [&mut [Self::Value]]);
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Throughput {
+    pub values: usize,
+    pub threads: usize,
+    pub elapsed: Duration
+}
+impl Throughput {
+    pub fn per_second(&self) -> f64 {
+        self.values as f64 / duration_as_seconds(self.elapsed)
+    }
+}
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} values/s", self.per_second())
+    }
+}
+
+/// the number of threads to use when the caller does not care
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Evaluate `code` for every index of `inputs`, writing to `outputs`, using up to `threads` threads.
+///
+/// All slices need to have the same length.
+pub fn batch<E: Eval>(code: &E, inputs: &[&[E::Value]], outputs: &mut [&mut [E::Value]], threads: usize) -> Throughput {
+    assert_eq!(code.num_inputs(), inputs.len());
+    assert_eq!(code.num_outputs(), outputs.len());
+    let n = match (inputs.first(), outputs.first()) {
+        (Some(i), _) => i.len(),
+        (None, Some(o)) => o.len(),
+        (None, None) => 0
+    };
+    assert!(inputs.iter().all(|i| i.len() == n), "input lengths differ");
+    assert!(outputs.iter().all(|o| o.len() == n), "output lengths differ");
+
+    // every thread but the last gets a multiple of LANES
+    let threads = threads.max(1);
+    let per_thread = (n + threads - 1) / threads;
+    let chunk = ((per_thread + E::LANES - 1) / E::LANES * E::LANES).max(E::LANES);
+    let num_chunks = (n + chunk - 1) / chunk;
+
+    let mut jobs: Vec<(Vec<&[E::Value]>, Vec<&mut [E::Value]>)> = (0 .. num_chunks)
+        .map(|_| (Vec::with_capacity(inputs.len()), Vec::with_capacity(outputs.len())))
+        .collect();
+    for input in inputs {
+        for (job, part) in jobs.iter_mut().zip(input.chunks(chunk)) {
+            job.0.push(part);
+        }
+    }
+    for output in outputs.iter_mut() {
+        for (job, part) in jobs.iter_mut().zip(output.chunks_mut(chunk)) {
+            job.1.push(part);
+        }
+    }
+
+    let t0 = Instant::now();
+    thread::scope(|s| {
+        for (ins, mut outs) in jobs {
+            s.spawn(move || code.eval_slices(&ins, &mut outs));
+        }
+    });
+
+    Throughput { values: n, threads: num_chunks, elapsed: t0.elapsed() }
+}
+
+#[cfg(all(feature="jit", target_feature="avx"))]
+mod simd_jit {
+    use super::Eval;
+    use crate::rt::simd_jit::Code;
+    use packed_simd::f32x8;
+
+    impl Eval for Code {
+        type Value = f32;
+        const LANES: usize = 8;
+
+        fn num_inputs(&self) -> usize {
+            self.num_inputs
+        }
+        fn num_outputs(&self) -> usize {
+            self.num_outputs
+        }
+        fn eval_slices(&self, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+            let n = inputs.first().map(|i| i.len()).or(outputs.first().map(|o| o.len())).unwrap_or(0);
+            let mut vin = vec![f32x8::splat(0.0); inputs.len()];
+            let mut vout = vec![f32x8::splat(0.0); outputs.len()];
+            let mut buf = [0.0f32; 8];
+
+            for start in (0 .. n).step_by(8) {
+                let len = (n - start).min(8);
+                for (v, input) in vin.iter_mut().zip(inputs) {
+                    *v = if len == 8 {
+                        f32x8::from_slice_unaligned(&input[start .. start + 8])
+                    } else {
+                        // remainder: pad with the last value
+                        for (k, b) in buf.iter_mut().enumerate() {
+                            *b = input[start + k.min(len - 1)];
+                        }
+                        f32x8::from_slice_unaligned(&buf)
+                    };
+                }
+                self.call(&vin, &mut vout);
+                for (v, output) in vout.iter().zip(outputs.iter_mut()) {
+                    v.write_to_slice_unaligned(&mut buf);
+                    output[start .. start + len].copy_from_slice(&buf[.. len]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature="cranelift")]
+mod cranelift {
+    use super::Eval;
+    use crate::rt::cranelift::Code;
+
+    impl Eval for Code {
+        type Value = f64;
+        const LANES: usize = 1;
+
+        fn num_inputs(&self) -> usize {
+            self.num_inputs
+        }
+        fn num_outputs(&self) -> usize {
+            self.num_outputs
+        }
+        fn eval_slices(&self, inputs: &[&[f64]], outputs: &mut [&mut [f64]]) {
+            self.map(inputs, outputs);
+        }
+    }
+}
+
+#[cfg(test)]
+struct Square;
+#[cfg(test)]
+impl Eval for Square {
+    type Value = f64;
+    const LANES: usize = 4;
+
+    fn num_inputs(&self) -> usize { 1 }
+    fn num_outputs(&self) -> usize { 1 }
+    fn eval_slices(&self, inputs: &[&[f64]], outputs: &mut [&mut [f64]]) {
+        for (o, &i) in outputs[0].iter_mut().zip(inputs[0]) {
+            *o = i * i;
+        }
+    }
+}
+
+#[test]
+fn test_batch() {
+    for &n in &[0, 1, 7, 100, 101] {
+        let x: Vec<f64> = (0 .. n).map(|i| i as f64).collect();
+        let mut y = vec![0.0; n];
+        let t = batch(&Square, &[&x], &mut [&mut y], 4);
+        assert_eq!(t.values, n);
+        assert!(t.threads <= 4);
+        assert!(x.iter().zip(&y).all(|(&x, &y)| y == x * x));
+    }
+}
+
+#[cfg(feature="cranelift")]
+#[test]
+fn test_batch_cranelift() {
+    use crate::builder::Builder;
+
+    let b = Builder::new();
+    let f = b.parse("x^2 + 3 y").unwrap();
+    let g = b.parse("x - y").unwrap();
+    let code = crate::rt::cranelift::compile(&[f, g], &["x", "y"]).unwrap();
+    for &n in &[0, 1, 7, 1001] {
+        let x: Vec<f64> = (0 .. n).map(|i| i as f64).collect();
+        let y: Vec<f64> = (0 .. n).map(|i| (n - i) as f64).collect();
+        let (mut f, mut g) = (vec![0.0; n], vec![0.0; n]);
+        let t = batch(&code, &[&x, &y], &mut [&mut f, &mut g], 4);
+        assert_eq!(t.values, n);
+        for i in 0 .. n {
+            assert_eq!(f[i], x[i] * x[i] + 3.0 * y[i]);
+            assert_eq!(g[i], x[i] - y[i]);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::prelude::*;
+use crate::compiler::Compiler;
+use crate::vm::{Vm, Round};
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags, Value, Type, UserFuncName};
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module, FuncId};
+use std::mem;
+
+/// Lowers to cranelift IR. Values are SSA, so storing is free.
+struct Lowering<'a, 'b> {
+    builder: &'b mut FunctionBuilder<'a>,
+    sources: Vec<(String, Value)>
+}
+
+impl<'a, 'b> Vm for Lowering<'a, 'b> {
+    type Var = Value;
+    type Storage = Value;
+
+    fn make_const(&mut self, c: f64) -> Self::Var {
+        self.builder.ins().f64const(c)
+    }
+    fn make_source(&mut self, name: &str) -> Self::Var {
+        self.sources.iter().find(|s| s.0 == name).expect("input is not defined").1
+    }
+    fn store(&mut self, var: &mut Self::Var, _uses: usize) -> Self::Storage {
+        *var
+    }
+    fn load(&mut self, storage: &Self::Storage) -> Self::Var {
+        *storage
+    }
+    fn round(&mut self, a: Self::Var, mode: Round) -> Self::Var {
+        match mode {
+            Round::Down => self.builder.ins().floor(a),
+            Round::Up => self.builder.ins().ceil(a)
+        }
+    }
+    fn add(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        self.builder.ins().fadd(a, b)
+    }
+    fn sub(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        self.builder.ins().fsub(a, b)
+    }
+    fn mul(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        self.builder.ins().fmul(a, b)
+    }
+    fn div(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        self.builder.ins().fdiv(a, b)
+    }
+    fn sqrt(&mut self, a: Self::Var) -> Self::Var {
+        self.builder.ins().sqrt(a)
+    }
+    fn abs(&mut self, a: Self::Var) -> Self::Var {
+        self.builder.ins().fabs(a)
+    }
+    fn min(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        self.builder.ins().fmin(a, b)
+    }
+    fn max(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        self.builder.ins().fmax(a, b)
+    }
+    fn mul_add(&mut self, a: Self::Var, b: Self::Var, c: Self::Var) -> Self::Var {
+        self.builder.ins().fma(a, b, c)
+    }
+    fn step_at(&mut self, at: Self::Var, x: Self::Var) -> Self::Var {
+        let c = self.builder.ins().fcmp(FloatCC::GreaterThanOrEqual, x, at);
+        let one = self.make_const(1.0);
+        let zero = self.make_const(0.0);
+        self.builder.ins().select(c, one, zero)
+    }
+}
+
+type CallFn = unsafe extern "C" fn(*const f64, *mut f64);
+type MapFn = unsafe extern "C" fn(usize, *const *const f64, *const *mut f64);
+
+/// Owns the memory of the finalized functions.
+///
+/// `JITModule` is neither `Send` nor `Sync` because of its symbol table. After `finalize_definitions`
+/// nothing but `drop` touches it, and the functions only read their arguments, so `Code`
+/// can be shared between threads.
+struct Finalized(Option<JITModule>);
+unsafe impl Send for Finalized {}
+unsafe impl Sync for Finalized {}
+impl Drop for Finalized {
+    fn drop(&mut self) {
+        if let Some(module) = self.0.take() {
+            // no function pointer outlives the `Code` that owns self
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+/// Natively compiled code, generated by cranelift for the host.
+///
+/// Works on any architecture cranelift supports. Computes with `f64`.
+pub struct Code {
+    module: Finalized,
+    call_fn: CallFn,
+    map_fn: MapFn,
+    pub num_inputs: usize,
+    pub num_outputs: usize,
+}
+impl Code {
+    /// evaluate once
+    pub fn call(&self, inputs: &[f64], outputs: &mut [f64]) {
+        assert_eq!(self.num_inputs, inputs.len());
+        assert_eq!(self.num_outputs, outputs.len());
+
+        unsafe { (self.call_fn)(inputs.as_ptr(), outputs.as_mut_ptr()) }
+    }
+
+    /// evaluate for every index of the input slices, which all need to have the same length as the outputs
+    pub fn map(&self, inputs: &[&[f64]], outputs: &mut [&mut [f64]]) {
+        assert_eq!(self.num_inputs, inputs.len());
+        assert_eq!(self.num_outputs, outputs.len());
+        let n = match (inputs.first(), outputs.first()) {
+            (Some(i), _) => i.len(),
+            (None, Some(o)) => o.len(),
+            (None, None) => return
+        };
+        assert!(inputs.iter().all(|i| i.len() == n), "input lengths differ");
+        assert!(outputs.iter().all(|o| o.len() == n), "output lengths differ");
+
+        let in_ptrs: Vec<*const f64> = inputs.iter().map(|i| i.as_ptr()).collect();
+        let out_ptrs: Vec<*mut f64> = outputs.iter_mut().map(|o| o.as_mut_ptr()).collect();
+        unsafe { (self.map_fn)(n, in_ptrs.as_ptr(), out_ptrs.as_ptr()) }
+    }
+
+    pub fn bench(&self, inputs: &[f64], outputs: &mut [f64], n: usize) {
+        for _ in 0 .. n {
+            self.call(inputs, outputs);
+        }
+    }
+}
+
+fn cranelift_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::Other(format!("cranelift: {}", e))
+}
+
+/// compile `nodes` into the block the builder is positioned at
+fn lower(builder: &mut FunctionBuilder, nodes: &[NodeRc], vars: &[&str], sources: Vec<Value>) -> Result<Vec<Value>, Error> {
+    let mut lowering = Lowering {
+        builder,
+        sources: vars.iter().map(|&s| s.to_owned()).zip(sources).collect()
+    };
+    Compiler::compile(&mut lowering, nodes, vars)
+}
+
+fn define<F>(module: &mut JITModule, ctx_fn: &mut FunctionBuilderContext, name: &str, params: &[Type], body: F) -> Result<FuncId, Error>
+    where F: FnOnce(&mut FunctionBuilder, &[Value]) -> Result<(), Error>
+{
+    let mut ctx = module.make_context();
+    for &t in params {
+        ctx.func.signature.params.push(AbiParam::new(t));
+    }
+    let id = module.declare_function(name, Linkage::Local, &ctx.func.signature).map_err(cranelift_error)?;
+    ctx.func.name = UserFuncName::user(0, id.as_u32());
+    {
+        let mut builder = FunctionBuilder::new(&mut ctx.func, ctx_fn);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let args = builder.block_params(entry).to_vec();
+        body(&mut builder, &args)?;
+        builder.seal_all_blocks();
+        builder.finalize();
+    }
+    module.define_function(id, &mut ctx).map_err(cranelift_error)?;
+    module.clear_context(&mut ctx);
+    Ok(id)
+}
+
+pub fn compile(nodes: &[NodeRc], vars: &[&str]) -> Result<Code, Error> {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed").map_err(cranelift_error)?;
+    let isa = cranelift_native::builder().map_err(cranelift_error)?
+        .finish(settings::Flags::new(flags)).map_err(cranelift_error)?;
+    let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+    let ptr = module.target_config().pointer_type();
+    let mut ctx_fn = FunctionBuilderContext::new();
+    let flags = MemFlags::trusted();
+
+    // fn(inputs: *const f64, outputs: *mut f64)
+    let call_id = define(&mut module, &mut ctx_fn, "call", &[ptr, ptr], |b, args| {
+        let sources = (0 .. vars.len()).map(|k| b.ins().load(types::F64, flags, args[0], 8 * k as i32)).collect();
+        let outputs = lower(b, nodes, vars, sources)?;
+        for (k, out) in outputs.into_iter().enumerate() {
+            b.ins().store(flags, out, args[1], 8 * k as i32);
+        }
+        b.ins().return_(&[]);
+        Ok(())
+    })?;
+
+    // fn(n: usize, inputs: *const *const f64, outputs: *const *mut f64)
+    let map_id = define(&mut module, &mut ctx_fn, "map", &[ptr, ptr, ptr], |b, args| {
+        let (n, inputs, outputs) = (args[0], args[1], args[2]);
+        let in_ptrs: Vec<_> = (0 .. vars.len()).map(|k| b.ins().load(ptr, flags, inputs, k as i32 * ptr.bytes() as i32)).collect();
+        let out_ptrs: Vec<_> = (0 .. nodes.len()).map(|k| b.ins().load(ptr, flags, outputs, k as i32 * ptr.bytes() as i32)).collect();
+
+        let header = b.create_block();
+        let body = b.create_block();
+        let exit = b.create_block();
+        b.append_block_param(header, ptr);
+        let zero = b.ins().iconst(ptr, 0);
+        b.ins().jump(header, &[zero]);
+
+        // header(i): if i >= n { exit } else { body }
+        b.switch_to_block(header);
+        let i = b.block_params(header)[0];
+        let done = b.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, i, n);
+        b.ins().brif(done, exit, &[], body, &[]);
+
+        b.switch_to_block(body);
+        let offset = b.ins().ishl_imm(i, 3);
+        let sources = in_ptrs.iter().map(|&p| {
+            let addr = b.ins().iadd(p, offset);
+            b.ins().load(types::F64, flags, addr, 0)
+        }).collect();
+        let results = lower(b, nodes, vars, sources)?;
+        for (&p, out) in out_ptrs.iter().zip(results) {
+            let addr = b.ins().iadd(p, offset);
+            b.ins().store(flags, out, addr, 0);
+        }
+        let next = b.ins().iadd_imm(i, 1);
+        b.ins().jump(header, &[next]);
+
+        b.switch_to_block(exit);
+        b.ins().return_(&[]);
+        Ok(())
+    })?;
+
+    module.finalize_definitions().map_err(cranelift_error)?;
+    let (call_fn, map_fn) = unsafe {(
+        mem::transmute::<*const u8, CallFn>(module.get_finalized_function(call_id)),
+        mem::transmute::<*const u8, MapFn>(module.get_finalized_function(map_id))
+    )};
+
+    Ok(Code {
+        module: Finalized(Some(module)),
+        call_fn,
+        map_fn,
+        num_inputs: vars.len(),
+        num_outputs: nodes.len()
+    })
+}
+
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn check<T: Send + Sync>() {}
+    check::<Code>();
+}
+
+#[test]
+fn test_cranelift() {
+    let b = Builder::new();
+    let f = b.parse("x^2 + 3 y").unwrap();
+    let g = b.parse("sin(x) / y").unwrap();
+    let code = compile(&[f, g], &["x", "y"]).unwrap();
+
+    let mut out = [0.0; 2];
+    code.call(&[2.0, 1.0], &mut out);
+    assert_eq!(out[0], 7.0);
+    assert!((out[1] - 2f64.sin()).abs() < 1e-3);
+
+    let x = [0.0, 1.0, 2.0, 3.0, 4.0];
+    let y = [1.0, 2.0, 3.0, 4.0, 5.0];
+    let (mut o0, mut o1) = ([0.0; 5], [0.0; 5]);
+    code.map(&[&x, &y], &mut [&mut o0, &mut o1]);
+    for i in 0 .. 5 {
+        assert_eq!(o0[i], x[i] * x[i] + 3.0 * y[i]);
+        assert!((o1[i] - x[i].sin() / y[i]).abs() < 1e-3);
+    }
+}
@@ -1,9 +1,17 @@
-#[cfg(target_feature="avx")]
+pub mod batch;
+
+#[cfg(all(feature="jit", target_feature="avx"))]
 pub mod simd_jit;
 
-#[cfg(target_feature="avx")]
+#[cfg(all(feature="jit", target_feature="avx"))]
 pub mod x86_64;
 
+#[cfg(feature="simd")]
+pub mod aarch64;
+
+#[cfg(feature="cranelift")]
+pub mod cranelift;
+
 #[cfg(feature="nvidia")]
 pub mod ptx;
 
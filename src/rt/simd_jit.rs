@@ -1,16 +1,29 @@
-use prelude::*;
+use crate::prelude::*;
 use packed_simd::f32x8;
-use vm::simd::{SimdAsm, Source, Instr};
-use compiler::Compiler;
-use vm::{Round, Cmp};
-use rt::x86_64::{Writer, op, Mode, Reg};
+use crate::vm::simd::{SimdAsm, Source, Instr};
+use crate::compiler::Compiler;
+use crate::vm::{Round, Cmp};
+use crate::rt::x86_64::{Writer, Evex, op, Mode, Reg, decode};
 use memmap::{Mmap, MmapOptions};
-use vm::simd::Reg as SimdReg;
+use crate::vm::simd::Reg as SimdReg;
+use std::fmt::Write;
+use std::marker::PhantomData;
+use std::mem;
 
 
+/// signature of the generated code (System V ABI)
+type Entry = unsafe extern "sysv64" fn(consts: *const f32x8, inputs: *const f32x8, outputs: *mut f32x8);
+
+/// Executable code for 8 lanes of f32.
+///
+/// The code only reads `consts` and its arguments, so it can be shared between threads.
 pub struct Code {
     consts: Vec<f32x8>,
     code: Mmap,
+    len: usize,
+    listing: Vec<(usize, Option<usize>)>, // start of each instruction and the node it came from
+    nodes: Vec<String>,
+    inputs: Vec<String>,
     pub num_inputs: usize,
     pub num_outputs: usize,
 }
@@ -19,19 +32,11 @@ impl Code {
     pub fn call(&self, inputs: &[f32x8], outputs: &mut [f32x8]) {
         assert_eq!(self.num_inputs, inputs.len());
         assert_eq!(self.num_outputs, outputs.len());
-        
-        unsafe { llvm_asm!{
-            "call rax"
-          : // no outputs
-          : "{rdi}"(self.consts.as_ptr()),
-            "{rdx}"(inputs.as_ptr()),
-            "{rbx}"(outputs.as_mut_ptr()),
-            "{rax}"(self.code.as_ptr())
-          :
-          : "intel"
-          : "{ymm0}", "{ymm1}", "{ymm2}", "{ymm3}", "{ymm4}", "{ymm5}", "{ymm6}", "{ymm7}",
-            "{ymm8}", "{ymm9}", "{ymm10}", "{ymm11}", "{ymm12}", "{ymm13}", "{ymm14}", "{ymm15}"
-        } }
+
+        unsafe {
+            let entry: Entry = mem::transmute(self.code.as_ptr());
+            entry(self.consts.as_ptr(), inputs.as_ptr(), outputs.as_mut_ptr());
+        }
     }
     
     pub fn bench(&self, inputs: &[f32x8], outputs: &mut [f32x8], n: usize) {
@@ -42,77 +47,345 @@ impl Code {
             self.call(inputs, outputs);
         }
     }
+
+    /// list the generated instructions, grouped by the node they compute,
+    /// with the values of constants and the names of inputs they read
+    pub fn disassemble(&self) -> String {
+        let code = &self.code[.. self.len];
+        let mut out = String::new();
+        let mut last_node = None;
+        let mut pos = 0;
+        while pos < code.len() {
+            let node = self.listing.iter().rev()
+                .find(|&&(start, _)| start <= pos)
+                .and_then(|&(_, node)| node);
+            if node != last_node {
+                match node {
+                    Some(n) => writeln!(out, "; {}", self.nodes[n]),
+                    None => writeln!(out, ";")
+                }.unwrap();
+                last_node = node;
+            }
+
+            let (instr, len) = match decode(&code[pos ..]) {
+                Some(d) => d,
+                None => {
+                    writeln!(out, "{:4x}  {:02x}  (bad)", pos, code[pos]).unwrap();
+                    pos += 1;
+                    continue;
+                }
+            };
+            let bytes = code[pos .. pos + len].iter().map(|b| format!("{:02x}", b)).join(" ");
+            write!(out, "{:4x}  {:30} {:50}", pos, bytes, instr.to_string()).unwrap();
+            match instr.mode() {
+                Some(Mode::Memory(Reg::RDI, off)) => {
+                    let idx = off as usize / 32;
+                    write!(out, " ; const {} = {}", idx, self.consts[idx].extract(0)).unwrap();
+                },
+                Some(Mode::Memory(Reg::RSI, off)) => {
+                    write!(out, " ; input {}", self.inputs[off as usize / 32]).unwrap();
+                },
+                Some(Mode::Memory(Reg::RDX, off)) => {
+                    write!(out, " ; output {}", off / 32).unwrap();
+                },
+                _ => {}
+            }
+            out.push('\n');
+            pos += len;
+        }
+        out
+    }
 }
 
-pub fn compile(nodes: &[NodeRc], vars: &[&str]) -> Result<Code, Error>
-{
-    let mut asm = SimdAsm::new();
-    let outputs = Compiler::compile(&mut asm, nodes, vars)?;
+/// the vcmpps predicate for `ord`
+fn predicate(ord: Cmp) -> u8 {
+    match ord {
+        Cmp::EQ => 0x0,
+        Cmp::NE => 0xC,
+        Cmp::LT => 0x11,
+        Cmp::LE => 0x12,
+        Cmp::GT => 0x1E,
+        Cmp::GE => 0x1D
+    }
+}
+
+/// true if the cpu can run the EVEX encoded compare path
+fn has_opmask() -> bool {
+    is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512vl")
+}
+
+/// the register `instr` writes
+fn target(instr: &Instr) -> SimdReg {
+    match *instr {
+        Instr::Add(r, _, _) | Instr::Sub(r, _, _) | Instr::Mul(r, _, _) | Instr::Div(r, _, _) |
+        Instr::Inv(r, _) | Instr::Sqrt(r, _) | Instr::Round(r, _, _) | Instr::Load(r, _) |
+        Instr::MaskMove(r, _, _) | Instr::Cmp(r, _, _, _) => r
+    }
+}
+
+/// true if `instr` reads the register `r`
+fn reads(instr: &Instr, r: SimdReg) -> bool {
+    let src = |s: Source| s == Source::Reg(r);
+    match *instr {
+        Instr::Add(_, a, s) | Instr::Sub(_, a, s) | Instr::Mul(_, a, s) | Instr::Div(_, a, s) |
+        Instr::MaskMove(_, a, s) | Instr::Cmp(_, a, s, _) => a == r || src(s),
+        Instr::Inv(_, s) | Instr::Sqrt(_, s) | Instr::Round(_, s, _) | Instr::Load(_, s) => src(s)
+    }
+}
+
+/// true if the mask that `instrs[pos]` writes to `r` is only used by the masked load right after it
+fn mask_only(instrs: &[Instr], pos: usize, r: SimdReg, outputs: &[Source]) -> bool {
+    match instrs.get(pos + 1) {
+        Some(&Instr::MaskMove(y, m, s)) if m == r && s != Source::Reg(r) => if y == r {
+            return true;
+        },
+        _ => return false
+    }
+    for instr in &instrs[pos + 2 ..] {
+        if reads(instr, r) {
+            return false;
+        }
+        if target(instr) == r {
+            return true;
+        }
+    }
+    !outputs.contains(&Source::Reg(r))
+}
 
+/// Translate the instructions of `asm` to machine code.
+///
+/// With `opmask`, a compare whose mask only feeds the masked load right after it writes the
+/// opmask register k1 and the load uses it (AVX-512VL), instead of a mask in a ymm register
+/// and `vmaskmovps`. Other compares still write their ymm register.
+/// Returns the code and the start of each instruction with the node it came from.
+fn assemble(asm: &mut SimdAsm, outputs: &[Source], opmask: bool) -> (Vec<u8>, Vec<(usize, Option<usize>)>) {
     let reg = |r: SimdReg| r.0;
     let mode = |s: Source| match s {
         Source::Reg(r) => Mode::Direct(reg(r)),
         Source::Const(idx) => Mode::Memory(Reg::RDI, idx as i32 * 32),
-        Source::Input(idx) => Mode::Memory(Reg::RDX, idx as i32 * 32),
+        Source::Input(idx) => Mode::Memory(Reg::RSI, idx as i32 * 32),
     };
 
     let mut writer = Writer::new();
-    for instr in asm.instr.iter() {
+    writer.prologue();
+    let mut listing = Vec::with_capacity(asm.instr.len());
+    // the register of `asm` whose compare result is in k1, only valid for the next instruction
+    let mut in_k1 = None;
+    for (pos, (instr, &origin)) in asm.instr.iter().zip(asm.origin.iter()).enumerate() {
+        listing.push((writer.len(), origin));
+        let k1 = in_k1.take();
         match *instr {
+            Instr::Cmp(r0, r1, s, ord) if opmask && mask_only(&asm.instr, pos, r0, outputs) => {
+                writer.evex(op::CMP, 1, reg(r1), mode(s), Evex::NONE.ymm(), Some(predicate(ord)));
+                in_k1 = Some(r0);
+            },
+            Instr::MaskMove(r0, r1, s) if k1 == Some(r1) => {
+                writer.evex(op::MOVE, reg(r0), 0, mode(s), Evex::masked(1).zeroing().ymm(), None);
+            },
             Instr::Add(r0, r1, s)      => writer.vex(op::ADD,   reg(r0), reg(r1), mode(s), None),
             Instr::Sub(r0, r1, s)      => writer.vex(op::SUB,   reg(r0), reg(r1), mode(s), None),
             Instr::Mul(r0, r1, s)      => writer.vex(op::MUL,   reg(r0), reg(r1), mode(s), None),
             Instr::Div(r0, r1, s)      => writer.vex(op::DIV,   reg(r0), reg(r1), mode(s), None),
             Instr::Inv(r0, s)          => writer.vex(op::RECIP, reg(r0), 0,       mode(s), None),
+            Instr::Sqrt(r0, s)         => writer.vex(op::SQRT,  reg(r0), 0,       mode(s), None),
             Instr::Round(r0, s, dir)   => writer.vex(op::ROUND, reg(r0), 0,       mode(s), Some(match dir {
                 Round::Down => 0x9,
                 Round::Up => 0xA
             })),
             Instr::Load(r0, s)         => writer.vex(op::READ,  reg(r0), 0,       mode(s), None),
-            Instr::Cmp(r0, r1, s, ord) => writer.vex(op::CMP,   reg(r0), reg(r1),   mode(s), Some(match ord {
-                Cmp::EQ => 0x0,
-                Cmp::NE => 0xC,
-                Cmp::LT => 0x11,
-                Cmp::LE => 0x12,
-                Cmp::GT => 0x1E,
-                Cmp::GE => 0x1D
-            })),
+            Instr::Cmp(r0, r1, s, ord) => writer.vex(op::CMP,   reg(r0), reg(r1),   mode(s), Some(predicate(ord))),
             Instr::MaskMove(r0, r1, s) => writer.vex(op::MASKREAD, reg(r0), reg(r1), mode(s), None)
         }
     }
+    listing.push((writer.len(), None));
     for (i, &r) in outputs.iter().enumerate() {
         let r = match r {
             Source::Reg(r) => r,
             s => {
+                // write the source to the output register
                 let r = asm.alloc();
-                asm.push(Instr::Load(r, s)); // write the source to the output register
+                writer.vex(op::READ, reg(r), 0, mode(s), None);
                 r
             }
         };
 
-        writer.vex(op::WRITE, reg(r), 0, Mode::Memory(Reg::RBX, i as i32 * 32), None);
+        writer.vex(op::WRITE, reg(r), 0, Mode::Memory(Reg::RDX, i as i32 * 32), None);
         asm.drop(r);
     }
-    
-    println!("{:?}", asm.registers);
-    let code = writer.finish();
-    /*{
-        use std::fs::File;
-        use std::io::Write;
-        File::create("/tmp/out").unwrap().write_all(&code).unwrap();
-    }*/
+    writer.epilogue();
+    (writer.finish(), listing)
+}
+
+pub fn compile(nodes: &[NodeRc], vars: &[&str]) -> Result<Code, Error>
+{
+    let mut asm = SimdAsm::new();
+    let outputs = Compiler::compile(&mut asm, nodes, vars)?;
+    let (code, listing) = assemble(&mut asm, &outputs, has_opmask());
 
     let mut anon_mmap = MmapOptions::new()
-        .len(4096)
-        .map_anon().unwrap();
+        .len(code.len())
+        .map_anon()?;
     
-    anon_mmap[.. code.len()].copy_from_slice(&code);
-    let mmap = anon_mmap.make_exec().unwrap();
+    anon_mmap.copy_from_slice(&code);
+    let mmap = anon_mmap.make_exec()?;
     
     Ok(Code {
         code: mmap,
+        len: code.len(),
+        listing,
         consts: asm.consts.iter().map(|&c| f32x8::splat(c)).collect(),
         num_inputs: asm.inputs.len(),
-        num_outputs: outputs.len()
+        num_outputs: outputs.len(),
+        nodes: asm.nodes,
+        inputs: asm.inputs
     })
 }
+
+/// Values that can be passed to and returned from a `Kernel`
+pub trait Lanes: Sized {
+    const LEN: usize;
+
+    /// write lane `lane` of `out[0 .. LEN]`
+    fn store(&self, out: &mut [f32x8], lane: usize);
+    /// read lane `lane` of `data[0 .. LEN]`
+    fn load(data: &[f32x8], lane: usize) -> Self;
+}
+impl Lanes for f32 {
+    const LEN: usize = 1;
+    fn store(&self, out: &mut [f32x8], lane: usize) {
+        out[0] = out[0].replace(lane, *self);
+    }
+    fn load(data: &[f32x8], lane: usize) -> Self {
+        data[0].extract(lane)
+    }
+}
+macro_rules! impl_lanes {
+    ($len:expr; $($T:ident $idx:tt),*) => {
+        impl Lanes for ($($T,)*) {
+            const LEN: usize = $len;
+            fn store(&self, out: &mut [f32x8], lane: usize) {
+                $( out[$idx] = out[$idx].replace(lane, self.$idx); )*
+            }
+            fn load(data: &[f32x8], lane: usize) -> Self {
+                ($( data[$idx].extract(lane) as $T, )*)
+            }
+        }
+    }
+}
+impl_lanes!(1; f32 0);
+impl_lanes!(2; f32 0, f32 1);
+impl_lanes!(3; f32 0, f32 1, f32 2);
+impl_lanes!(4; f32 0, f32 1, f32 2, f32 3);
+
+/// A compiled function taking `In` and returning `Out`.
+///
+/// `In` and `Out` are `f32` or tuples of `f32`.
+pub struct Kernel<In, Out> {
+    code: Code,
+    _m: PhantomData<fn(In) -> Out>
+}
+impl<In: Lanes, Out: Lanes> Kernel<In, Out> {
+    pub fn new(code: Code) -> Result<Self, Error> {
+        if code.num_inputs != In::LEN || code.num_outputs != Out::LEN {
+            return Err(Error::Other(format!(
+                "kernel has {} inputs and {} outputs, but the types have {} and {}",
+                code.num_inputs, code.num_outputs, In::LEN, Out::LEN
+            )));
+        }
+        Ok(Kernel { code, _m: PhantomData })
+    }
+    pub fn compile(nodes: &[NodeRc], vars: &[&str]) -> Result<Self, Error> {
+        Kernel::new(compile(nodes, vars)?)
+    }
+    pub fn code(&self) -> &Code {
+        &self.code
+    }
+
+    /// evaluate a single value
+    pub fn call(&self, x: In) -> Out {
+        let mut inputs = vec![f32x8::splat(0.0); In::LEN];
+        let mut outputs = vec![f32x8::splat(0.0); Out::LEN];
+        x.store(&mut inputs, 0);
+        self.code.call(&inputs, &mut outputs);
+        Out::load(&outputs, 0)
+    }
+
+    /// evaluate for every element of `xs`, 8 at a time
+    pub fn map(&self, xs: &[In]) -> Vec<Out> {
+        let mut inputs = vec![f32x8::splat(0.0); In::LEN];
+        let mut outputs = vec![f32x8::splat(0.0); Out::LEN];
+        let mut result = Vec::with_capacity(xs.len());
+        for chunk in xs.chunks(8) {
+            // unused lanes keep the values of the last chunk, which are valid inputs
+            for (lane, x) in chunk.iter().enumerate() {
+                x.store(&mut inputs, lane);
+            }
+            self.code.call(&inputs, &mut outputs);
+            result.extend((0 .. chunk.len()).map(|lane| Out::load(&outputs, lane)));
+        }
+        result
+    }
+}
+
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn check<T: Send + Sync>() {}
+    check::<Code>();
+    check::<Kernel<(f32, f32), f32>>();
+}
+
+#[test]
+fn test_opmask_compare() {
+    let builder = Builder::new();
+    let node = builder.parse("sign(x + 1)").unwrap();
+    for &opmask in &[false, true] {
+        let mut asm = SimdAsm::new();
+        let outputs = Compiler::compile(&mut asm, &[node.clone()], &["x"]).unwrap();
+        let (code, _) = assemble(&mut asm, &outputs, opmask);
+
+        let mut lines = vec![];
+        let mut pos = 0;
+        while pos < code.len() {
+            let (instr, len) = decode(&code[pos ..]).expect("failed to decode");
+            lines.push(instr.to_string());
+            pos += len;
+        }
+        let count = |prefix: &str| lines.iter().filter(|l| l.starts_with(prefix)).count();
+        // sign uses two steps
+        if opmask {
+            assert_eq!(count("vcmpps k1, ymm"), 2);
+            assert_eq!(count("vmovaps ymm"), 2);
+            assert!(lines.iter().filter(|l| l.starts_with("vmovaps")).all(|l| l.contains("{k1}{z}")));
+            assert_eq!(count("vmaskmovps"), 0);
+        } else {
+            assert_eq!(count("vcmpps ymm"), 2);
+            assert_eq!(count("vmaskmovps"), 2);
+        }
+    }
+}
+
+#[test]
+fn test_opmask_fallback() {
+    use crate::vm::simd::Reg as R;
+
+    // the first mask is compared again before its load, the second one is only used by its load
+    let mut asm = SimdAsm::new();
+    asm.push(Instr::Cmp(R(2), R(0), Source::Input(0), Cmp::GE));
+    asm.push(Instr::Cmp(R(3), R(2), Source::Input(0), Cmp::GE));
+    asm.push(Instr::MaskMove(R(4), R(3), Source::Const(0)));
+    asm.push(Instr::MaskMove(R(5), R(2), Source::Const(0)));
+    let (code, _) = assemble(&mut asm, &[Source::Reg(R(4)), Source::Reg(R(5))], true);
+
+    let mut lines = vec![];
+    let mut pos = 0;
+    while pos < code.len() {
+        let (instr, len) = decode(&code[pos ..]).expect("failed to decode");
+        lines.push(instr.to_string());
+        pos += len;
+    }
+    let count = |prefix: &str| lines.iter().filter(|l| l.starts_with(prefix)).count();
+    assert_eq!(count("vcmpps ymm2"), 1);
+    assert_eq!(count("vcmpps k1"), 1);
+    assert_eq!(count("vmovaps ymm4"), 1);
+    assert_eq!(count("vmaskmovps ymm5"), 1);
+}
@@ -1,8 +1,9 @@
 #![allow(non_snake_case, non_camel_case_types)]
 
 use std::mem::transmute;
+use std::fmt;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Reg {
     RAX,
     RCX,
@@ -14,17 +15,18 @@ pub enum Reg {
     RDI
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Mode {
     Direct(u8), // reg3 operates normal
     Memory(Reg, i32) // reg3 spcifies memory base. index, offset
 }
-#[derive(PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Prefix {
     P_0F,
     P_0F_38,
     P_0F_3A
 }
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum SimdPrefix {
     None,
     S_66,
@@ -39,15 +41,60 @@ pub mod op {
     use super::SimdPrefix::*;
     
     pub const ADD: Opcode = (None, P_0F, 0x58);
-    pub const SUB: Opcode = (S_66, P_0F, 0x5C);
+    pub const SUB: Opcode = (None, P_0F, 0x5C);
     pub const MUL: Opcode = (None, P_0F, 0x59);
     pub const DIV: Opcode = (None, P_0F, 0x5E);
     pub const RECIP: Opcode = (None, P_0F, 0x53);
+    pub const SQRT: Opcode = (None, P_0F, 0x51);
     pub const ROUND: Opcode = (S_66, P_0F_3A, 0x08);
     pub const READ: Opcode = (S_66, P_0F, 0x6F);
     pub const WRITE: Opcode = (S_66, P_0F, 0x7F);
-    pub const CMP: Opcode = (S_66, P_0F, 0xC2);
+    pub const CMP: Opcode = (None, P_0F, 0xC2);
     pub const MASKREAD: Opcode = (S_66, P_0F_38, 0x2C);
+
+    // EVEX only
+    pub const RECIP14: Opcode = (S_66, P_0F_38, 0x4C);
+    pub const MOVE: Opcode = (None, P_0F, 0x28);
+    pub const BLEND: Opcode = (S_66, P_0F_38, 0x65);
+}
+
+/// embedded rounding mode (EVEX.RC)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Rounding {
+    Nearest = 0b00,
+    Down = 0b01,
+    Up = 0b10,
+    Zero = 0b11
+}
+
+/// the EVEX specific parts of an instruction
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Evex {
+    pub mask: u8, // opmask register k1-k7, 0 for no masking
+    pub zeroing: bool, // zero instead of merge masked lanes
+    pub rounding: Option<Rounding>, // only for register operands, implies 512bit
+    pub ymm: bool // 256bit on ymm0-ymm31 (AVX-512VL) instead of 512bit on zmm
+}
+impl Evex {
+    pub const NONE: Evex = Evex { mask: 0, zeroing: false, rounding: None, ymm: false };
+
+    pub fn masked(k: u8) -> Evex {
+        assert!(k < 8);
+        Evex { mask: k, .. Evex::NONE }
+    }
+    pub fn zeroing(self) -> Evex {
+        Evex { zeroing: true, .. self }
+    }
+    pub fn round(self, mode: Rounding) -> Evex {
+        Evex { rounding: Some(mode), .. self }
+    }
+    pub fn ymm(self) -> Evex {
+        Evex { ymm: true, .. self }
+    }
+    /// vector size in bytes
+    fn size(&self) -> i32 {
+        if self.ymm { 32 } else { 64 }
+    }
 }
 pub struct Writer {
     buf: Vec<u8>
@@ -60,6 +107,18 @@ impl Writer {
         self.push(0xc3); // ret
         self.buf
     }
+    /// set up a stack frame: `push rbp; mov rbp, rsp`
+    pub fn prologue(&mut self) {
+        self.buf.extend_from_slice(PROLOGUE);
+    }
+    /// leave the stack frame and avx state: `vzeroupper; pop rbp`
+    pub fn epilogue(&mut self) {
+        self.buf.extend_from_slice(EPILOGUE);
+    }
+    /// number of bytes written so far
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
     fn push(&mut self, b: u8) {
         self.buf.push(b);
     }
@@ -91,33 +150,364 @@ impl Writer {
         
         if X | B || prefix != Prefix::P_0F {
             self.push(0xc4);
-            self.push(((!R as u8) << 7) | ((!X as u8) << 6) | (!(B as u8) << 5) | m);
+            self.push(((!R as u8) << 7) | ((!X as u8) << 6) | ((!B as u8) << 5) | m);
             self.push((W as u8) << 7 | (0xf ^ reg2) << 3 | (L as u8) << 2 | pp);
         } else {
             self.push(0xc5);
             self.push((!R as u8) << 7 | (0xf ^ reg2) << 3 | (L as u8) << 2 | pp);
         }
         self.push(op);
-        
+        self.modrm(reg1, mode, 1);
+        if let Some(imm) = imm8 {
+            self.push(imm);
+        }
+    }
+
+    /// EVEX encoded 512bit operation on zmm0-zmm31, or 256bit on ymm0-ymm31 with `Evex::ymm`.
+    ///
+    /// For compares reg1 is the opmask register that receives the result.
+    pub fn evex(&mut self, (simd, prefix, op): Opcode, reg1: u8, reg2: u8, mode: Mode, evex: Evex, imm8: Option<u8>) {
+        assert!(reg1 < 32 && reg2 < 32);
+        let (B, X) = match mode {
+            Mode::Direct(r) => {
+                assert!(r < 32);
+                (r & 8 != 0, r & 16 != 0)
+            },
+            Mode::Memory(r, _) => (r as u8 & 8 != 0, false)
+        };
+        let R = reg1 & 8 != 0;
+        let R_ = reg1 & 16 != 0;
+        let V_ = reg2 & 16 != 0;
+        let W = false;
+        let pp = match simd {
+            SimdPrefix::None => 0b00,
+            SimdPrefix::S_66 => 0b01,
+            SimdPrefix::S_F3 => 0b10,
+            SimdPrefix::S_F2 => 0b11
+        };
+        let m = match prefix {
+            Prefix::P_0F    => 0b01,
+            Prefix::P_0F_38 => 0b10,
+            Prefix::P_0F_3A => 0b11
+        };
+        // with b set on register operands L'L holds the rounding mode instead of the length
+        let (LL, b) = match (evex.rounding, mode) {
+            (None, _) if evex.ymm => (0b01, false),
+            (None, _) => (0b10, false),
+            (Some(_), _) if evex.ymm => panic!("embedded rounding implies 512bit"),
+            (Some(rc), Mode::Direct(_)) => (rc as u8, true),
+            (Some(_), Mode::Memory(..)) => panic!("embedded rounding needs a register operand")
+        };
+
+        self.push(0x62);
+        self.push((!R as u8) << 7 | (!X as u8) << 6 | (!B as u8) << 5 | (!R_ as u8) << 4 | m);
+        self.push((W as u8) << 7 | (0xf ^ (reg2 & 0xf)) << 3 | 1 << 2 | pp);
+        self.push((evex.zeroing as u8) << 7 | LL << 5 | (b as u8) << 4 | (!V_ as u8) << 3 | evex.mask);
+        self.push(op);
+        self.modrm(reg1, mode, evex.size()); // disp8 is scaled by the vector size
+        if let Some(imm) = imm8 {
+            self.push(imm);
+        }
+    }
+
+    fn modrm(&mut self, reg1: u8, mode: Mode, disp_scale: i32) {
+        let reg3 = match mode {
+            Mode::Direct(r) => r,
+            Mode::Memory(r, _) => r as u8
+        };
         let sip = ((reg1 & 7) << 3) | (reg3 & 7);
         match mode {
             Mode::Direct(_) => self.push(0b11 << 6 | sip),
-            Mode::Memory(_, 0) => self.push(0b00 << 6 | sip),
-            Mode::Memory(_, off) if off >= -128 && off < 128 => {
+            // rbp without displacement would mean rip-relative
+            Mode::Memory(r, 0) if r as u8 != Reg::RBP as u8 => {
+                self.push(0b00 << 6 | sip);
+                self.sib(r);
+            },
+            Mode::Memory(r, off) if off % disp_scale == 0 && off / disp_scale >= -128 && off / disp_scale < 128 => {
                 self.push(0b01 << 6 | sip);
-                self.push(off as u8);
+                self.sib(r);
+                self.push((off / disp_scale) as u8);
             },
-            Mode::Memory(_, off) => {
+            Mode::Memory(r, off) => {
                 self.push(0b10 << 6 | sip);
+                self.sib(r);
                 self.pushq(off as u32);
             }
         }
-        if let Some(imm) = imm8 {
-            self.push(imm);
+    }
+    fn sib(&mut self, base: Reg) {
+        // rsp as base needs a SIB byte without index
+        if let Reg::RSP = base {
+            self.push(0x24);
         }
     }
 }
-        
+
+const PROLOGUE: &[u8] = &[0x55, 0x48, 0x89, 0xe5];
+const EPILOGUE: &[u8] = &[0xc5, 0xf8, 0x77, 0x5d];
+
+// instructions without operands we care about
+const FIXED: &[(&[u8], &str)] = &[
+    (&[0x55], "push rbp"),
+    (&[0x48, 0x89, 0xe5], "mov rbp, rsp"),
+    (&[0xc5, 0xf8, 0x77], "vzeroupper"),
+    (&[0x5d], "pop rbp"),
+];
+
+const MNEMONICS: &[(Opcode, &str)] = &[
+    (op::ADD, "vaddps"),
+    (op::SUB, "vsubps"),
+    (op::MUL, "vmulps"),
+    (op::DIV, "vdivps"),
+    (op::RECIP, "vrcpps"),
+    (op::SQRT, "vsqrtps"),
+    (op::ROUND, "vroundps"),
+    (op::READ, "vmovdqa"),
+    (op::WRITE, "vmovdqa"),
+    (op::CMP, "vcmpps"),
+    (op::MASKREAD, "vmaskmovps"),
+];
+// where the EVEX form has a different name
+const EVEX_MNEMONICS: &[(Opcode, &str)] = &[
+    (op::ROUND, "vrndscaleps"),
+    (op::READ, "vmovdqa32"),
+    (op::WRITE, "vmovdqa32"),
+    (op::RECIP14, "vrcp14ps"),
+    (op::MOVE, "vmovaps"),
+    (op::BLEND, "vblendmps"),
+];
+// only these use the vvvv operand
+const THREE_OPERANDS: &[Opcode] = &[op::ADD, op::SUB, op::MUL, op::DIV, op::CMP, op::MASKREAD, op::BLEND];
+
+fn mnemonic(table: &[(Opcode, &'static str)], opcode: Opcode) -> Option<&'static str> {
+    table.iter().find(|&&(o, _)| o == opcode).map(|&(_, name)| name)
+}
+
+/// A decoded instruction, as emitted by `Writer`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Instruction {
+    Vex { opcode: Opcode, reg1: u8, reg2: u8, mode: Mode, imm8: Option<u8> },
+    Evex { opcode: Opcode, reg1: u8, reg2: u8, mode: Mode, evex: Evex, imm8: Option<u8> },
+    Fixed(&'static str),
+    Ret
+}
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        match *self {
+            Instruction::Ret => "ret",
+            Instruction::Fixed(name) => name,
+            Instruction::Vex { opcode, .. } => mnemonic(MNEMONICS, opcode).unwrap_or("(unknown)"),
+            Instruction::Evex { opcode, .. } => mnemonic(EVEX_MNEMONICS, opcode)
+                .or(mnemonic(MNEMONICS, opcode))
+                .unwrap_or("(unknown)")
+        }
+    }
+    /// the register or memory operand
+    pub fn mode(&self) -> Option<Mode> {
+        match *self {
+            Instruction::Vex { mode, .. } | Instruction::Evex { mode, .. } => Some(mode),
+            _ => None
+        }
+    }
+}
+fn write_mode(f: &mut fmt::Formatter, mode: Mode, reg: &str, ptr: &str) -> fmt::Result {
+    match mode {
+        Mode::Direct(r) => write!(f, "{}{}", reg, r),
+        Mode::Memory(base, 0) => write!(f, "{} PTR [{}]", ptr, format!("{:?}", base).to_lowercase()),
+        Mode::Memory(base, off) if off < 0 => write!(f, "{} PTR [{}-{:#x}]", ptr, format!("{:?}", base).to_lowercase(), -off),
+        Mode::Memory(base, off) => write!(f, "{} PTR [{}+{:#x}]", ptr, format!("{:?}", base).to_lowercase(), off)
+    }
+}
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_mode(f, *self, "ymm", "YMMWORD")
+    }
+}
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.mnemonic();
+        match *self {
+            Instruction::Ret | Instruction::Fixed(_) => write!(f, "{}", name),
+            Instruction::Vex { opcode, reg1, mode, .. } if opcode == op::WRITE => write!(f, "{} {}, ymm{}", name, mode, reg1),
+            Instruction::Vex { opcode, reg1, reg2, mode, imm8 } => {
+                write!(f, "{} ymm{}, ", name, reg1)?;
+                if THREE_OPERANDS.contains(&opcode) {
+                    write!(f, "ymm{}, ", reg2)?;
+                }
+                write!(f, "{}", mode)?;
+                if let Some(imm) = imm8 {
+                    write!(f, ", {:#x}", imm)?;
+                }
+                Ok(())
+            },
+            Instruction::Evex { opcode, reg1, reg2, mode, evex, imm8 } => {
+                let (reg, ptr) = if evex.ymm { ("ymm", "YMMWORD") } else { ("zmm", "ZMMWORD") };
+                write!(f, "{} ", name)?;
+                if opcode == op::WRITE {
+                    write_mode(f, mode, reg, ptr)?;
+                    return write!(f, ", {}{}", reg, reg1);
+                }
+                // compares write an opmask register
+                match opcode == op::CMP {
+                    true => write!(f, "k{}", reg1)?,
+                    false => write!(f, "{}{}", reg, reg1)?
+                }
+                if evex.mask != 0 {
+                    write!(f, "{{k{}}}", evex.mask)?;
+                }
+                if evex.zeroing {
+                    write!(f, "{{z}}")?;
+                }
+                write!(f, ", ")?;
+                if THREE_OPERANDS.contains(&opcode) {
+                    write!(f, "{}{}, ", reg, reg2)?;
+                }
+                write_mode(f, mode, reg, ptr)?;
+                if let Some(rc) = evex.rounding {
+                    write!(f, "{}", match rc {
+                        Rounding::Nearest => "{rn-sae}",
+                        Rounding::Down => "{rd-sae}",
+                        Rounding::Up => "{ru-sae}",
+                        Rounding::Zero => "{rz-sae}"
+                    })?;
+                }
+                if let Some(imm) = imm8 {
+                    write!(f, ", {:#x}", imm)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn simd_prefix(pp: u8) -> SimdPrefix {
+    match pp & 0b11 {
+        0b00 => SimdPrefix::None,
+        0b01 => SimdPrefix::S_66,
+        0b10 => SimdPrefix::S_F3,
+        _ => SimdPrefix::S_F2
+    }
+}
+
+/// Decode the ModRM byte at `code[pos]` and what follows it.
+///
+/// `B` and `X` extend the register operand, `scale` multiplies 8bit displacements.
+/// Returns the reg field, the other operand and the position after them.
+fn decode_modrm(code: &[u8], mut pos: usize, B: bool, X: bool, scale: i32) -> Option<(u8, Mode, usize)> {
+    let byte = |i: usize| code.get(i).cloned();
+    let modrm = byte(pos)?;
+    pos += 1;
+
+    let rm = modrm & 7;
+    let mode = match modrm >> 6 {
+        0b11 => Mode::Direct((X as u8) << 4 | (B as u8) << 3 | rm),
+        md => {
+            use self::Reg::*;
+            if B || X {
+                return None; // r8-r15 are not used as base
+            }
+            let base = [RAX, RCX, RDX, RBX, RSP, RBP, RSI, RDI][rm as usize];
+            if base == RSP {
+                if byte(pos)? != 0x24 {
+                    return None;
+                }
+                pos += 1;
+            }
+            let off = match md {
+                0b00 if base == RBP => return None, // rip-relative
+                0b00 => 0,
+                0b01 => {
+                    pos += 1;
+                    byte(pos - 1)? as i8 as i32 * scale
+                },
+                _ => {
+                    let d = code.get(pos .. pos + 4)?;
+                    pos += 4;
+                    i32::from_le_bytes([d[0], d[1], d[2], d[3]])
+                }
+            };
+            Mode::Memory(base, off)
+        }
+    };
+    Some((modrm >> 3 & 7, mode, pos))
+}
+
+fn decode_imm8(code: &[u8], opcode: Opcode, pos: usize) -> Option<(Option<u8>, usize)> {
+    match opcode {
+        (_, Prefix::P_0F_3A, _) | (_, Prefix::P_0F, 0xC2) => Some((Some(*code.get(pos)?), pos + 1)),
+        _ => Some((None, pos))
+    }
+}
+
+/// Decode the instruction at the start of `code`.
+///
+/// Only understands what `Writer::vex`, `Writer::evex` and `Writer::finish` produce.
+/// Returns the instruction and its length in bytes.
+pub fn decode(code: &[u8]) -> Option<(Instruction, usize)> {
+    if let Some(&(bytes, name)) = FIXED.iter().find(|&&(bytes, _)| code.starts_with(bytes)) {
+        return Some((Instruction::Fixed(name), bytes.len()));
+    }
+    let byte = |i: usize| code.get(i).cloned();
+    let escape = |m: u8| match m {
+        0b00001 => Some(Prefix::P_0F),
+        0b00010 => Some(Prefix::P_0F_38),
+        0b00011 => Some(Prefix::P_0F_3A),
+        _ => None
+    };
+    let (R, B, prefix, vvvv_byte, pos) = match byte(0)? {
+        0xc3 => return Some((Instruction::Ret, 1)),
+        0x62 => return decode_evex(code),
+        0xc5 => (byte(1)? & 0x80 == 0, false, Prefix::P_0F, byte(1)?, 2),
+        0xc4 => {
+            let b1 = byte(1)?;
+            (b1 & 0x80 == 0, b1 & 0x20 == 0, escape(b1 & 0x1f)?, byte(2)?, 3)
+        },
+        _ => return None
+    };
+    let reg2 = (vvvv_byte >> 3 & 0xf) ^ 0xf;
+    let opcode = (simd_prefix(vvvv_byte), prefix, byte(pos)?);
+    let (reg, mode, pos) = decode_modrm(code, pos + 1, B, false, 1)?;
+    let reg1 = (R as u8) << 3 | reg;
+    let (imm8, pos) = decode_imm8(code, opcode, pos)?;
+    Some((Instruction::Vex { opcode, reg1, reg2, mode, imm8 }, pos))
+}
+
+fn decode_evex(code: &[u8]) -> Option<(Instruction, usize)> {
+    let byte = |i: usize| code.get(i).cloned();
+    let (p0, p1, p2) = (byte(1)?, byte(2)?, byte(3)?);
+    let R = p0 & 0x80 == 0;
+    let X = p0 & 0x40 == 0;
+    let B = p0 & 0x20 == 0;
+    let R_ = p0 & 0x10 == 0;
+    let prefix = match p0 & 0b11 {
+        0b01 => Prefix::P_0F,
+        0b10 => Prefix::P_0F_38,
+        0b11 => Prefix::P_0F_3A,
+        _ => return None
+    };
+    let reg2 = ((p1 >> 3 & 0xf) ^ 0xf) | ((p2 & 0x08 == 0) as u8) << 4;
+    let LL = p2 >> 5 & 0b11;
+    let b = p2 & 0x10 != 0;
+    let opcode = (simd_prefix(p1), prefix, byte(4)?);
+
+    let memory = byte(5)? >> 6 != 0b11;
+    let evex = match (b, memory) {
+        (true, false) => Evex {
+            rounding: Some([Rounding::Nearest, Rounding::Down, Rounding::Up, Rounding::Zero][LL as usize]),
+            .. Evex::NONE
+        },
+        (false, _) if LL == 0b01 => Evex::NONE.ymm(),
+        (false, _) if LL == 0b10 => Evex::NONE,
+        _ => return None // broadcast and 128bit are not used
+    };
+    let evex = Evex { mask: p2 & 7, zeroing: p2 & 0x80 != 0, .. evex };
+
+    let (reg, mode, pos) = decode_modrm(code, 5, B, X && !memory, evex.size())?;
+    let reg1 = (R_ as u8) << 4 | (R as u8) << 3 | reg;
+    let (imm8, pos) = decode_imm8(code, opcode, pos)?;
+    Some((Instruction::Evex { opcode, reg1, reg2, mode, evex, imm8 }, pos))
+}
+
 #[test]
 fn test_opcodes() {
     use self::Reg::*;
@@ -134,6 +524,15 @@ fn test_opcodes() {
     w.vex(op::ADD, 0,  0, Mode::Memory(RDI, 4), None);
     w.vex(op::ADD, 0,  0, Mode::Memory(RBP, 128), None);
     w.vex(op::ROUND, 0, 0, Mode::Direct(0), Some(9));
+    w.vex(op::SUB, 0,  1, Mode::Direct(2), None);
+    w.vex(op::CMP, 0,  1, Mode::Direct(2), Some(0x1d));
+    w.vex(op::ROUND, 8, 0, Mode::Direct(1), Some(9));
+    w.vex(op::MUL, 9,  0, Mode::Direct(10), None);
+    w.vex(op::MASKREAD, 3, 4, Mode::Memory(RDI, 0x20), None);
+    w.vex(op::ADD, 0,  0, Mode::Memory(RBP, 0), None);
+    w.vex(op::ADD, 0,  0, Mode::Memory(RSP, 0), None);
+    w.vex(op::ADD, 0,  0, Mode::Memory(RSP, 0x20), None);
+    w.vex(op::ADD, 0,  0, Mode::Memory(RSP, 0x100), None);
     
     let a = w.finish();
     let b = vec![
@@ -148,6 +547,15 @@ fn test_opcodes() {
         0xc5, 0xfc, 0x58, 0x47, 0x04, // vaddps ymm0,ymm0,YMMWORD PTR [rdi+0x4]
         0xc5, 0xfc, 0x58, 0x85, 0x80, 0x00, 0x00, 0x00, // vaddps ymm0,ymm0,YMMWORD PTR [rbp+0x80]
         0xc4, 0xe3, 0x7d, 0x08, 0xc0, 0x09, // vroundps ymm0,ymm0,0x9
+        0xc5, 0xf4, 0x5c, 0xc2, // vsubps ymm0,ymm1,ymm2
+        0xc5, 0xf4, 0xc2, 0xc2, 0x1d, // vcmpps ymm0,ymm1,ymm2,0x1d
+        0xc4, 0x63, 0x7d, 0x08, 0xc1, 0x09, // vroundps ymm8,ymm1,0x9
+        0xc4, 0x41, 0x7c, 0x59, 0xca, // vmulps ymm9,ymm0,ymm10
+        0xc4, 0xe2, 0x5d, 0x2c, 0x5f, 0x20, // vmaskmovps ymm3,ymm4,YMMWORD PTR [rdi+0x20]
+        0xc5, 0xfc, 0x58, 0x45, 0x00, // vaddps ymm0,ymm0,YMMWORD PTR [rbp+0x0]
+        0xc5, 0xfc, 0x58, 0x04, 0x24, // vaddps ymm0,ymm0,YMMWORD PTR [rsp]
+        0xc5, 0xfc, 0x58, 0x44, 0x24, 0x20, // vaddps ymm0,ymm0,YMMWORD PTR [rsp+0x20]
+        0xc5, 0xfc, 0x58, 0x84, 0x24, 0x00, 0x01, 0x00, 0x00, // vaddps ymm0,ymm0,YMMWORD PTR [rsp+0x100]
         0xc3                    // ret
     ];
 
@@ -157,3 +565,125 @@ fn test_opcodes() {
     }
     assert_eq!(a, b);
 }
+
+#[test]
+fn test_evex() {
+    use self::Reg::*;
+
+    let mut w = Writer::new();
+    w.evex(op::ADD, 0,  0, Mode::Direct(0), Evex::NONE, None);
+    w.evex(op::ADD, 1,  2, Mode::Direct(3), Evex::NONE, None);
+    w.evex(op::MUL, 8,  0, Mode::Direct(0), Evex::NONE, None);
+    w.evex(op::MUL, 16, 0, Mode::Direct(0), Evex::NONE, None);
+    w.evex(op::MUL, 0, 31, Mode::Direct(0), Evex::NONE, None);
+    w.evex(op::MUL, 0,  0, Mode::Direct(15), Evex::NONE, None);
+    w.evex(op::MUL, 0,  0, Mode::Direct(24), Evex::NONE, None);
+    w.evex(op::ADD, 0,  0, Mode::Memory(RDI, 0), Evex::NONE, None);
+    w.evex(op::ADD, 0,  0, Mode::Memory(RDI, 64), Evex::NONE, None);
+    w.evex(op::ADD, 0,  0, Mode::Memory(RDI, 4), Evex::NONE, None);
+    w.evex(op::ADD, 0,  0, Mode::Memory(RBP, 128), Evex::NONE, None);
+    w.evex(op::SUB, 17, 18, Mode::Memory(RDX, 0x2000), Evex::NONE, None);
+    w.evex(op::ADD, 0,  1, Mode::Direct(2), Evex::NONE.round(Rounding::Down), None);
+    w.evex(op::MUL, 5,  6, Mode::Direct(7), Evex::NONE.round(Rounding::Zero), None);
+    w.evex(op::ROUND, 0, 0, Mode::Direct(1), Evex::NONE, Some(9));
+    w.evex(op::RECIP14, 20, 0, Mode::Direct(3), Evex::NONE, None);
+    w.evex(op::CMP, 1,  2, Mode::Direct(3), Evex::NONE, Some(0x1D));
+    w.evex(op::CMP, 2, 21, Mode::Memory(RDI, 64), Evex::NONE, Some(0x11));
+    w.evex(op::MOVE, 4, 0, Mode::Memory(RDI, 128), Evex::masked(1).zeroing(), None);
+    w.evex(op::BLEND, 4, 5, Mode::Direct(6), Evex::masked(2), None);
+    w.evex(op::WRITE, 9, 0, Mode::Memory(RBX, 64), Evex::NONE, None);
+    w.evex(op::ADD, 1,  2, Mode::Direct(3), Evex::NONE.ymm(), None);
+    w.evex(op::CMP, 1,  2, Mode::Memory(RDI, 64), Evex::NONE.ymm(), Some(0x1D));
+    w.evex(op::MOVE, 4, 0, Mode::Memory(RDI, 32), Evex::masked(1).zeroing().ymm(), None);
+
+    let a = w.finish();
+    let b = vec![
+        0x62, 0xf1, 0x7c, 0x48, 0x58, 0xc0, // vaddps zmm0,zmm0,zmm0
+        0x62, 0xf1, 0x6c, 0x48, 0x58, 0xcb, // vaddps zmm1,zmm2,zmm3
+        0x62, 0x71, 0x7c, 0x48, 0x59, 0xc0, // vmulps zmm8,zmm0,zmm0
+        0x62, 0xe1, 0x7c, 0x48, 0x59, 0xc0, // vmulps zmm16,zmm0,zmm0
+        0x62, 0xf1, 0x04, 0x40, 0x59, 0xc0, // vmulps zmm0,zmm31,zmm0
+        0x62, 0xd1, 0x7c, 0x48, 0x59, 0xc7, // vmulps zmm0,zmm0,zmm15
+        0x62, 0x91, 0x7c, 0x48, 0x59, 0xc0, // vmulps zmm0,zmm0,zmm24
+        0x62, 0xf1, 0x7c, 0x48, 0x58, 0x07, // vaddps zmm0,zmm0,ZMMWORD PTR [rdi]
+        0x62, 0xf1, 0x7c, 0x48, 0x58, 0x47, 0x01, // vaddps zmm0,zmm0,ZMMWORD PTR [rdi+0x40]
+        0x62, 0xf1, 0x7c, 0x48, 0x58, 0x87, 0x04, 0x00, 0x00, 0x00, // vaddps zmm0,zmm0,ZMMWORD PTR [rdi+0x4]
+        0x62, 0xf1, 0x7c, 0x48, 0x58, 0x45, 0x02, // vaddps zmm0,zmm0,ZMMWORD PTR [rbp+0x80]
+        0x62, 0xe1, 0x6c, 0x40, 0x5c, 0x8a, 0x00, 0x20, 0x00, 0x00, // vsubps zmm17,zmm18,ZMMWORD PTR [rdx+0x2000]
+        0x62, 0xf1, 0x74, 0x38, 0x58, 0xc2, // vaddps zmm0,zmm1,zmm2{rd-sae}
+        0x62, 0xf1, 0x4c, 0x78, 0x59, 0xef, // vmulps zmm5,zmm6,zmm7{rz-sae}
+        0x62, 0xf3, 0x7d, 0x48, 0x08, 0xc1, 0x09, // vrndscaleps zmm0,zmm1,0x9
+        0x62, 0xe2, 0x7d, 0x48, 0x4c, 0xe3, // vrcp14ps zmm20,zmm3
+        0x62, 0xf1, 0x6c, 0x48, 0xc2, 0xcb, 0x1d, // vcmpps k1,zmm2,zmm3,0x1d (ge)
+        0x62, 0xf1, 0x54, 0x40, 0xc2, 0x57, 0x01, 0x11, // vcmpps k2,zmm21,ZMMWORD PTR [rdi+0x40],0x11 (lt)
+        0x62, 0xf1, 0x7c, 0xc9, 0x28, 0x67, 0x02, // vmovaps zmm4{k1}{z},ZMMWORD PTR [rdi+0x80]
+        0x62, 0xf2, 0x55, 0x4a, 0x65, 0xe6, // vblendmps zmm4{k2},zmm5,zmm6
+        0x62, 0x71, 0x7d, 0x48, 0x7f, 0x4b, 0x01, // vmovdqa32 ZMMWORD PTR [rbx+0x40],zmm9
+        0x62, 0xf1, 0x6c, 0x28, 0x58, 0xcb, // vaddps ymm1,ymm2,ymm3
+        0x62, 0xf1, 0x6c, 0x28, 0xc2, 0x4f, 0x02, 0x1d, // vcmpps k1,ymm2,YMMWORD PTR [rdi+0x40],0x1d
+        0x62, 0xf1, 0x7c, 0xa9, 0x28, 0x67, 0x01, // vmovaps ymm4{k1}{z},YMMWORD PTR [rdi+0x20]
+        0xc3                    // ret
+    ];
+
+    println!("");
+    for (i, (&a, &b)) in a.iter().zip(b.iter()).enumerate() {
+        println!("{:4x}  {:02x} ({:08b})  {:02x} ({:08b})  {}", i, a, a, b, b, if a == b {"✓"} else {""});
+    }
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_decode() {
+    use self::Reg::*;
+
+    let mut w = Writer::new();
+    w.prologue();
+    w.vex(op::ADD, 1,  2, Mode::Direct(3), None);
+    w.vex(op::SUB, 8,  0, Mode::Direct(15), None);
+    w.vex(op::MUL, 0, 15, Mode::Memory(RDI, 4), None);
+    w.vex(op::DIV, 3,  4, Mode::Memory(RDX, 0), None);
+    w.vex(op::RECIP, 5, 0, Mode::Direct(6), None);
+    w.vex(op::ROUND, 0, 0, Mode::Memory(RBP, 128), Some(9));
+    w.vex(op::READ, 9, 0, Mode::Memory(RDI, 64), None);
+    w.vex(op::WRITE, 2, 0, Mode::Memory(RBX, 32), None);
+    w.vex(op::CMP, 1, 2, Mode::Memory(RSP, -8), Some(0x1d));
+    w.vex(op::MASKREAD, 3, 4, Mode::Memory(RDI, 0), None);
+    w.evex(op::CMP, 1, 2, Mode::Memory(RDI, 64), Evex::NONE.ymm(), Some(0x1d));
+    w.evex(op::MOVE, 4, 0, Mode::Memory(RDI, 32), Evex::masked(1).zeroing().ymm(), None);
+    w.evex(op::ADD, 17, 18, Mode::Direct(30), Evex::NONE, None);
+    w.evex(op::MUL, 5, 6, Mode::Direct(7), Evex::NONE.round(Rounding::Zero), None);
+    w.evex(op::WRITE, 9, 0, Mode::Memory(RBX, 64), Evex::NONE, None);
+    w.epilogue();
+    let code = w.finish();
+
+    let mut lines = vec![];
+    let mut pos = 0;
+    while pos < code.len() {
+        let (instr, len) = decode(&code[pos ..]).expect("failed to decode");
+        lines.push(instr.to_string());
+        pos += len;
+    }
+    assert_eq!(lines, [
+        "push rbp",
+        "mov rbp, rsp",
+        "vaddps ymm1, ymm2, ymm3",
+        "vsubps ymm8, ymm0, ymm15",
+        "vmulps ymm0, ymm15, YMMWORD PTR [rdi+0x4]",
+        "vdivps ymm3, ymm4, YMMWORD PTR [rdx]",
+        "vrcpps ymm5, ymm6",
+        "vroundps ymm0, YMMWORD PTR [rbp+0x80], 0x9",
+        "vmovdqa ymm9, YMMWORD PTR [rdi+0x40]",
+        "vmovdqa YMMWORD PTR [rbx+0x20], ymm2",
+        "vcmpps ymm1, ymm2, YMMWORD PTR [rsp-0x8], 0x1d",
+        "vmaskmovps ymm3, ymm4, YMMWORD PTR [rdi]",
+        "vcmpps k1, ymm2, YMMWORD PTR [rdi+0x40], 0x1d",
+        "vmovaps ymm4{k1}{z}, YMMWORD PTR [rdi+0x20]",
+        "vaddps zmm17, zmm18, zmm30",
+        "vmulps zmm5, zmm6, zmm7{rz-sae}",
+        "vmovdqa32 ZMMWORD PTR [rbx+0x40], zmm9",
+        "vzeroupper",
+        "pop rbp",
+        "ret"
+    ]);
+    assert!(decode(&[0x90]).is_none());
+}
@@ -0,0 +1,383 @@
+//! Rewrite rules written in the expression syntax.
+//!
+//! A rule `lhs => rhs` contains pattern variables like `?a`, which match any expression:
+//!
+//! ```text
+//! # comments start with '#'
+//! sin(?a)^2 + cos(?a)^2 => 1
+//! exp(?a) exp(?b) => exp(?a + ?b)
+//! ```
+//!
+//! Sums and products are matched regardless of the order of their terms and factors.
+//! A sum on the left side matches any subset of the terms of a sum, all scaled by the same
+//! factor, so the first rule turns `2 sin(x)² + 2 cos(x)² + y` into `y + 2`. Likewise a
+//! product matches any subset of the factors of a term. A term `c ?a` only matches integer
+//! multiples of `c`.
+//!
+//! Functions applied to constants with a rational value, like the `exp(0)` that
+//! `exp(?a) exp(?b) => exp(?a + ?b)` makes of `exp(x) exp(-x)`, are evaluated while rewriting.
+
+use crate::prelude::*;
+use crate::poly::Poly;
+use crate::func::Func;
+use crate::simplify::{to_node, value};
+use crate::lang::RuleParser;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// the maximum number of passes over an expression before `Rules::apply` gives up
+const MAX_PASSES: usize = 100;
+
+type Bindings = HashMap<String, NodeRc>;
+
+/// the name of the pattern variable `node`, if it is one
+fn pattern_var(node: &Node) -> Option<&str> {
+    match *node {
+        Node::Var(ref name) if name.starts_with('?') => Some(name.as_str()),
+        _ => None
+    }
+}
+
+fn pattern_vars(node: &Node, vars: &mut HashSet<String>) {
+    match *node {
+        Node::Var(ref name) if name.starts_with('?') => {
+            vars.insert(name.clone());
+        },
+        Node::Apply(ref f, ref g) => {
+            pattern_vars(f, vars);
+            pattern_vars(g, vars);
+        },
+        Node::Poly(ref p) => for (base, _) in p.factors() {
+            for &(ref v, _) in base.iter() {
+                pattern_vars(v, vars);
+            }
+        },
+        Node::Tuple(ref parts) => for p in parts {
+            pattern_vars(p, vars);
+        },
+        Node::Var(_) | Node::Op(_) => {}
+    }
+}
+
+/// where a rule matches
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// some of the terms of a sum
+    Sum,
+    /// some of the factors of a term
+    Product,
+    /// a whole node
+    Node
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    lhs: NodeRc,
+    rhs: NodeRc,
+    kind: Kind
+}
+impl Rule {
+    pub fn new(lhs: NodeRc, rhs: NodeRc) -> Result<Rule, Error> {
+        if pattern_var(&lhs).is_some() {
+            return Err(Error::Other(format!("the left side {} matches everything", lhs)));
+        }
+        let (mut bound, mut used) = (HashSet::new(), HashSet::new());
+        pattern_vars(&lhs, &mut bound);
+        pattern_vars(&rhs, &mut used);
+        if let Some(v) = used.difference(&bound).next() {
+            return Err(Error::Other(format!("the pattern variable {} is not bound by the left side", v)));
+        }
+        let kind = match *lhs {
+            Node::Poly(ref p) if p.factors().count() > 1 => Kind::Sum,
+            Node::Poly(_) => Kind::Product,
+            _ => Kind::Node
+        };
+        Ok(Rule { lhs, rhs, kind })
+    }
+    /// parse `lhs => rhs`
+    pub fn parse(builder: &Builder, rule: &str) -> Result<Rule, Error> {
+        let (lhs, rhs) = RuleParser::new().parse(builder, rule).unwrap_or_else(|e| Err(Error::parse_error(e, rule)))?;
+        Rule::new(lhs, rhs)
+    }
+
+    fn rhs(&self, builder: &Builder, b: &Bindings) -> NodeResult {
+        let map = b.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+        builder.substitute(&self.rhs, &map)
+    }
+
+    /// rewrite `node` if the rule matches
+    fn apply(&self, builder: &Builder, node: &NodeRc) -> Result<Option<NodeRc>, Error> {
+        let m = Matcher { builder };
+        match self.kind {
+            Kind::Node => match m.node(&self.lhs, node, &Bindings::new()).into_iter().next() {
+                Some(b) => Ok(Some(self.rhs(builder, &b)?)),
+                None => Ok(None)
+            },
+            Kind::Sum => {
+                let (pattern, subject) = match (&*self.lhs, &**node) {
+                    (&Node::Poly(ref p), &Node::Poly(ref q)) => (p, q),
+                    _ => return Ok(None)
+                };
+                let (b, k, used) = match m.terms(pattern, subject, false, &Bindings::new()).into_iter().next() {
+                    Some(t) => t,
+                    None => return Ok(None)
+                };
+                let mut sum = Poly::from_node(self.rhs(builder, &b)?) * Poly::rational(k);
+                for (i, (base, fac)) in subject.factors().enumerate() {
+                    if !used.contains(&i) {
+                        sum = sum + Poly::product(base.clone(), fac.clone());
+                    }
+                }
+                Ok(Some(to_node(builder, sum)))
+            },
+            Kind::Product => {
+                let (pattern, subject) = match (&*self.lhs, &**node) {
+                    (&Node::Poly(ref p), &Node::Poly(ref q)) => (p, q),
+                    _ => return Ok(None)
+                };
+                let (p_base, p_fac) = pattern.leading_term().unwrap();
+                for (i, (base, fac)) in subject.factors().enumerate() {
+                    let (b, used) = match m.factors(p_base, base, false, &Bindings::new()).into_iter().next() {
+                        Some(t) => t,
+                        None => continue
+                    };
+                    let rest = base.iter().enumerate()
+                        .filter(|&(j, _)| !used.contains(&j))
+                        .map(|(_, t)| t.clone())
+                        .collect();
+                    let mut sum = Poly::product(rest, fac.clone() / p_fac.clone())
+                        * Poly::from_node(self.rhs(builder, &b)?);
+                    for (j, (base, fac)) in subject.factors().enumerate() {
+                        if j != i {
+                            sum = sum + Poly::product(base.clone(), fac.clone());
+                        }
+                    }
+                    return Ok(Some(to_node(builder, sum)));
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+struct Matcher<'a> {
+    builder: &'a Builder
+}
+impl<'a> Matcher<'a> {
+    /// all ways `pattern` matches `node`, extending `b`
+    fn node(&self, pattern: &NodeRc, node: &NodeRc, b: &Bindings) -> Vec<Bindings> {
+        if let Some(name) = pattern_var(pattern) {
+            return match b.get(name) {
+                Some(bound) if bound == node => vec![b.clone()],
+                Some(_) => vec![],
+                None => {
+                    let mut b = b.clone();
+                    b.insert(name.into(), node.clone());
+                    vec![b]
+                }
+            };
+        }
+        match (&**pattern, &**node) {
+            (&Node::Poly(ref p), _) => {
+                let q = Poly::from_node(node.clone());
+                self.terms(p, &q, true, b).into_iter().map(|(b, _, _)| b).collect()
+            },
+            (&Node::Apply(ref f, ref g), &Node::Apply(ref f2, ref g2)) if f == f2 => self.node(g, g2, b),
+            (&Node::Tuple(ref ps), &Node::Tuple(ref ns)) if ps.len() == ns.len() => {
+                ps.iter().zip(ns.iter()).fold(vec![b.clone()], |bs, (p, n)| {
+                    bs.iter().flat_map(|b| self.node(p, n, b)).collect()
+                })
+            },
+            _ if pattern == node => vec![b.clone()],
+            _ => vec![]
+        }
+    }
+
+    /// Match the terms of `pattern` to distinct terms of `subject`, which are `k` times as large.
+    /// A term `c ?a` matches a term `d m` with `?a = d m / (k c)`, if `d / (k c)` is an integer.
+    /// Otherwise `sin(2 ?a)` would match `sin(x)` with `?a = x / 2`.
+    ///
+    /// With `full`, all terms of `subject` have to be used and `k = 1`.
+    /// Returns the bindings, `k` and the indices of the used terms.
+    fn terms(&self, pattern: &Poly, subject: &Poly, full: bool, b: &Bindings) -> Vec<(Bindings, Rational, Vec<usize>)> {
+        let subject: Vec<_> = subject.factors().collect();
+        if pattern.factors().count() > subject.len() || (full && pattern.factors().count() < subject.len()) {
+            return vec![];
+        }
+        // the other terms determine k, so they go first
+        let (vars, others): (Vec<_>, Vec<_>) = pattern.factors().partition(|&(base, _)| {
+            base.len() == 1 && base[0].1 == 1 && pattern_var(&base[0].0).is_some()
+        });
+        let order: Vec<_> = others.into_iter().chain(vars).collect();
+
+        let mut out = vec![];
+        let k = if full { Some(Rational::from(1)) } else { None };
+        self.assign_terms(&order, &subject, k, vec![], b.clone(), &mut out);
+        out
+    }
+    fn assign_terms(&self, pattern: &[(&Base, &Rational)], subject: &[(&Base, &Rational)],
+        k: Option<Rational>, used: Vec<usize>, b: Bindings, out: &mut Vec<(Bindings, Rational, Vec<usize>)>)
+    {
+        let (p_base, p_fac) = match pattern.first() {
+            Some(&t) => t,
+            None => {
+                out.push((b, k.unwrap_or(1.into()), used));
+                return;
+            }
+        };
+        let var = match p_base.len() {
+            1 if p_base[0].1 == 1 && pattern_var(&p_base[0].0).is_some() => Some(&p_base[0].0),
+            _ => None
+        };
+        for (j, &(s_base, s_fac)) in subject.iter().enumerate() {
+            if used.contains(&j) {
+                continue;
+            }
+            let mut used = used.clone();
+            used.push(j);
+            match var {
+                Some(v) => {
+                    let k = k.clone().unwrap_or(1.into());
+                    let fac = s_fac.clone() / (k.clone() * p_fac.clone());
+                    if fac.to_int().is_none() {
+                        continue;
+                    }
+                    let value = Poly::product(s_base.clone(), fac);
+                    for b in self.node(v, &to_node(self.builder, value), &b) {
+                        self.assign_terms(&pattern[1..], subject, Some(k.clone()), used.clone(), b, out);
+                    }
+                },
+                None => {
+                    let kj = s_fac.clone() / p_fac.clone();
+                    if k.as_ref().map_or(false, |k| *k != kj) {
+                        continue;
+                    }
+                    for (b, _) in self.factors(p_base, s_base, true, &b) {
+                        self.assign_terms(&pattern[1..], subject, Some(kj.clone()), used.clone(), b, out);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Match the factors of `pattern` to distinct factors of `subject` with the same exponents.
+    /// With `full`, all factors of `subject` have to be used.
+    /// Returns the bindings and the indices of the used factors.
+    fn factors(&self, pattern: &Base, subject: &Base, full: bool, b: &Bindings) -> Vec<(Bindings, Vec<usize>)> {
+        if pattern.len() > subject.len() || (full && pattern.len() < subject.len()) {
+            return vec![];
+        }
+        let mut out = vec![];
+        self.assign_factors(pattern, subject, vec![], b.clone(), &mut out);
+        out
+    }
+    fn assign_factors(&self, pattern: &[(NodeRc, Rational)], subject: &[(NodeRc, Rational)],
+        used: Vec<usize>, b: Bindings, out: &mut Vec<(Bindings, Vec<usize>)>)
+    {
+        let (p, n) = match pattern.first() {
+            Some(t) => t,
+            None => {
+                out.push((b, used));
+                return;
+            }
+        };
+        for (j, &(ref s, ref m)) in subject.iter().enumerate() {
+            if used.contains(&j) || m != n {
+                continue;
+            }
+            let mut used = used.clone();
+            used.push(j);
+            for b in self.node(p, s, &b) {
+                self.assign_factors(&pattern[1..], subject, used.clone(), b, out);
+            }
+        }
+    }
+}
+
+/// A list of rules, tried in order.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    rules: Vec<Rule>
+}
+impl Rules {
+    pub fn new() -> Rules {
+        Rules::default()
+    }
+    pub fn push(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+    /// one rule per line, empty lines and everything after a `#` are ignored
+    pub fn parse(builder: &Builder, text: &str) -> Result<Rules, Error> {
+        let mut rules = Rules::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let rule = Rule::parse(builder, line).map_err(|e| Error::Other(format!("line {}: {}", i + 1, e)))?;
+            rules.push(rule);
+        }
+        Ok(rules)
+    }
+    /// read the rules from the file at `path`, see `parse`
+    pub fn load<P: AsRef<Path>>(builder: &Builder, path: P) -> Result<Rules, Error> {
+        Rules::parse(builder, &fs::read_to_string(path)?)
+    }
+
+    /// rewrite `node` until no rule matches anymore
+    pub fn apply(&self, builder: &Builder, node: &NodeRc) -> NodeResult {
+        let mut node = node.clone();
+        for _ in 0 .. MAX_PASSES {
+            let next = self.pass(builder, &node)?;
+            if next == node {
+                return Ok(node);
+            }
+            node = next;
+        }
+        Err(Error::Other(format!("the rules did not reach a fixpoint after {} passes", MAX_PASSES)))
+    }
+
+    /// rewrite the children of `node` and then `node` itself, with the first rule that matches
+    fn pass(&self, builder: &Builder, node: &NodeRc) -> NodeResult {
+        let node = match **node {
+            Node::Apply(ref f, ref g) => {
+                let g = self.pass(builder, g)?;
+                // constant applications the rules produced, like exp(0)
+                let r = match (&**f, &*g) {
+                    (&Node::Op(Func::Transient(t)), &Node::Poly(ref p)) => p.as_rational().and_then(|r| value(t, &r)),
+                    _ => None
+                };
+                match r {
+                    Some(r) => builder.rational(r),
+                    None => builder.intern(Node::Apply(f.clone(), g))
+                }
+            },
+            Node::Poly(ref p) => {
+                let mut sum = Poly::zero();
+                for (base, fac) in p.factors() {
+                    let mut term = Poly::rational(fac.clone());
+                    for &(ref v, ref n) in base.iter() {
+                        let w = self.pass(builder, v)?;
+                        term = term * match *w {
+                            _ if w == *v => Poly::product(vec![(w.clone(), n.clone())], 1.into()),
+                            // keep sums as factors
+                            Node::Poly(ref q) if q.factors().count() > 1 => Poly::product(vec![(w.clone(), n.clone())], 1.into()),
+                            _ => Poly::from_node(w.clone()).pow(builder, n.clone())?
+                        };
+                    }
+                    sum = sum + term;
+                }
+                to_node(builder, sum)
+            },
+            Node::Tuple(ref parts) => builder.tuple(parts.iter().map(|p| self.pass(builder, p)))?,
+            Node::Var(_) | Node::Op(_) => node.clone()
+        };
+        for rule in self.rules.iter() {
+            if let Some(n) = rule.apply(builder, &node)? {
+                return Ok(n);
+            }
+        }
+        Ok(node)
+    }
+}
@@ -0,0 +1,339 @@
+//! Identities of the elementary functions.
+//!
+//! `simplify` only uses rules that hold for all real arguments where both sides are defined:
+//! values at 0 and 1, parity, inverse functions, sin² + cos² = 1 and eᵃ eᵇ = eᵃ⁺ᵇ.
+//! Rules that depend on the branch of `log`, like log(x y) = log x + log y, are only applied
+//! to factors that the `Assumptions` prove to be positive.
+
+use crate::prelude::*;
+use crate::func::{Func, Transient};
+use crate::func::Transient::*;
+use crate::poly::{Poly, Base};
+use std::collections::HashSet;
+use std::iter::once;
+
+/// what is known about the variables
+#[derive(Debug, Clone, Default)]
+pub struct Assumptions {
+    positive: HashSet<String>
+}
+impl Assumptions {
+    pub fn new() -> Assumptions {
+        Assumptions::default()
+    }
+    /// assume `var > 0`
+    pub fn positive(mut self, var: &str) -> Assumptions {
+        self.positive.insert(var.into());
+        self
+    }
+    /// true if `node` is known to be positive
+    pub fn is_positive(&self, node: &Node) -> bool {
+        match *node {
+            Node::Var(ref name) => self.positive.contains(name),
+            Node::Poly(ref p) => !p.is_zero() && p.factors().all(|(base, fac)| {
+                !fac.is_negative() && base.iter().all(|&(ref v, _)| self.is_positive(v))
+            }),
+            Node::Apply(..) => match transient(node) {
+                Some((Exp, _)) | Some((Cosh, _)) => true,
+                _ => false
+            },
+            _ => false
+        }
+    }
+}
+
+/// `f` and its argument, if `node` is an elementary function
+fn transient(node: &Node) -> Option<(Transient, &NodeRc)> {
+    match *node {
+        Node::Apply(ref f, ref g) => match **f {
+            Node::Op(Func::Transient(t)) => Some((t, g)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// `p` as a node, where a single atom is not wrapped into a polynomial
+pub(crate) fn to_node(builder: &Builder, p: Poly) -> NodeRc {
+    if p.factors().count() == 1 {
+        let (base, fac) = p.leading_term().unwrap();
+        if *fac == 1 && base.len() == 1 && base[0].1 == 1 {
+            return base[0].0.clone();
+        }
+    }
+    builder.poly(p)
+}
+
+/// f(r) for the rational numbers `r` where it is rational
+pub(crate) fn value(t: Transient, r: &Rational) -> Option<Rational> {
+    match t {
+        Abs => Some(r.abs()),
+        Sign if r.is_zero() => Some(0.into()),
+        Sign => Some(Rational::from(if r.is_negative() { -1 } else { 1 })),
+        Floor => Some(r.floor().into()),
+        Ceil => Some(Rational::from((r.clone() * -1).floor()) * -1),
+        Sin | Tan | Asin | Atan | Sinh | Tanh | Asinh | Atanh | Erf if r.is_zero() => Some(0.into()),
+        Cos | Cosh | Exp if r.is_zero() => Some(1.into()),
+        Log | Acosh if *r == 1 => Some(0.into()),
+        _ => None
+    }
+}
+
+struct Simplifier<'a> {
+    builder: &'a Builder,
+    assume: &'a Assumptions
+}
+impl<'a> Simplifier<'a> {
+    fn node(&self, node: &NodeRc) -> NodeResult {
+        match **node {
+            Node::Apply(ref f, ref g) => {
+                let g = self.node(g)?;
+                match **f {
+                    Node::Op(Func::Transient(t)) => self.apply(t, g),
+                    _ => Ok(self.builder.intern(Node::Apply(f.clone(), g)))
+                }
+            },
+            Node::Poly(ref p) => {
+                let mut sum = Poly::zero();
+                for (base, fac) in p.factors() {
+                    let mut term = Poly::rational(fac.clone());
+                    for &(ref v, ref n) in base.iter() {
+                        term = term * self.power(self.node(v)?, n)?;
+                    }
+                    sum = sum + term;
+                }
+                let sum = self.pythagoras(self.combine_exp(sum)?)?;
+                Ok(to_node(self.builder, sum))
+            },
+            Node::Tuple(ref parts) => self.builder.tuple(parts.iter().map(|p| self.node(p))),
+            Node::Var(_) | Node::Op(_) => Ok(node.clone())
+        }
+    }
+
+    /// vⁿ, without expanding sums
+    fn power(&self, v: NodeRc, n: &Rational) -> Result<Poly, Error> {
+        match *v {
+            Node::Poly(ref p) if p.factors().count() > 1 => Ok(Poly::product(vec![(v.clone(), n.clone())], 1.into())),
+            _ => Poly::from_node(v.clone()).pow(self.builder, n.clone())
+        }
+    }
+
+    /// f(g), where `g` is already simplified
+    fn apply(&self, t: Transient, g: NodeRc) -> NodeResult {
+        let builder = self.builder;
+        if let Node::Poly(ref p) = *g {
+            if let Some(r) = p.as_rational().and_then(|r| value(t, &r)) {
+                return Ok(builder.rational(r));
+            }
+        }
+
+        if let Some((h, u)) = transient(&g) {
+            match (t, h) {
+                // log(eˣ) = x only for real x
+                (Exp, Log) | (Log, Exp) | (Sin, Asin) | (Cos, Acos) | (Tan, Atan) |
+                (Sinh, Asinh) | (Cosh, Acosh) | (Tanh, Atanh) => return Ok(u.clone()),
+                (Abs, Abs) | (Abs, Exp) | (Abs, Cosh) => return Ok(g.clone()),
+                _ => {}
+            }
+        }
+
+        // f(-u) = ±f(u), so that f(a - b) and f(b - a) end up with the same argument
+        if let Node::Poly(ref p) = *g {
+            if p.leading_term().map_or(false, |(_, c)| c.is_negative()) {
+                let u = to_node(builder, p.clone() * (-1));
+                match t {
+                    Sin | Tan | Asin | Atan | Sinh | Tanh | Asinh | Atanh | Erf | Sign => return builder.neg(self.apply(t, u)?),
+                    Cos | Cosh | Abs => return self.apply(t, u),
+                    _ => {}
+                }
+            }
+        }
+
+        let rewritten = match t {
+            Log => self.split_log(&g)?,
+            Exp => self.exp_of_logs(&g)?,
+            _ => None
+        };
+        match rewritten {
+            Some(n) => Ok(n),
+            None => builder.func(t.into(), g)
+        }
+    }
+
+    /// log(c ∏ vᵢ^nᵢ) = log c + ∑ nᵢ log vᵢ, if c and all vᵢ are positive.
+    ///
+    /// log(vⁿ) = n log v also holds wherever the left side is defined, if n has an odd numerator.
+    fn split_log(&self, g: &NodeRc) -> Result<Option<NodeRc>, Error> {
+        let builder = self.builder;
+        let (base, fac) = match **g {
+            Node::Poly(ref p) if p.factors().count() == 1 => p.leading_term().unwrap(),
+            _ => return Ok(None)
+        };
+        let single = *fac == 1 && base.len() == 1 && base[0].1.frac().0.is_odd();
+        let positive = !fac.is_negative() && base.iter().all(|&(ref v, _)| self.assume.is_positive(v));
+        if base.is_empty() || !(single || positive) {
+            return Ok(None);
+        }
+        let mut sum = self.apply(Log, builder.rational(fac.clone()))?;
+        for &(ref v, ref n) in base.iter() {
+            let log = self.apply(Log, v.clone())?;
+            sum = builder.add(sum, builder.mul(builder.rational(n.clone()), log)?)?;
+        }
+        Ok(Some(sum))
+    }
+
+    /// exp(∑ cᵢ log vᵢ + r) = ∏ vᵢ^cᵢ exp(r)
+    fn exp_of_logs(&self, g: &NodeRc) -> Result<Option<NodeRc>, Error> {
+        let p = match **g {
+            Node::Poly(ref p) => p,
+            _ => return Ok(None)
+        };
+        let mut prod = Poly::int(1);
+        let mut rest = Poly::zero();
+        let mut found = false;
+        for (base, fac) in p.factors() {
+            let log = match base.as_slice() {
+                [(l, n)] if *n == 1 => transient(l).filter(|&(t, _)| t == Log).map(|(_, v)| v.clone()),
+                _ => None
+            };
+            match log {
+                Some(v) => {
+                    prod = prod * self.power(v, fac)?;
+                    found = true;
+                },
+                None => rest = rest + Poly::product(base.clone(), fac.clone())
+            }
+        }
+        if !found {
+            return Ok(None);
+        }
+        let exp = self.apply(Exp, to_node(self.builder, rest))?;
+        Ok(Some(to_node(self.builder, prod * Poly::from_node(exp))))
+    }
+
+    /// exp(a)ⁿ exp(b)ᵐ = exp(n a + m b) in every term
+    fn combine_exp(&self, p: Poly) -> Result<Poly, Error> {
+        let mut sum = Poly::zero();
+        for (base, fac) in p.factors() {
+            let (exps, others): (Base, Base) = base.iter().cloned()
+                .partition(|&(ref v, _)| transient(v).map_or(false, |(t, _)| t == Exp));
+            if exps.is_empty() || (exps.len() == 1 && exps[0].1 == 1) {
+                sum = sum + Poly::product(base.clone(), fac.clone());
+                continue;
+            }
+            let mut arg = Poly::zero();
+            for (v, n) in exps {
+                let (_, a) = transient(&v).unwrap();
+                arg = arg + Poly::rational(n) * Poly::from_node(a.clone());
+            }
+            let exp = self.apply(Exp, to_node(self.builder, arg))?;
+            sum = sum + Poly::product(others, fac.clone()) * Poly::from_node(exp);
+        }
+        Ok(sum)
+    }
+
+    /// Replace sin(u)² by 1 - cos(u)², cos(u)² by 1 - sin(u)², cosh(u)² by 1 + sinh(u)² or
+    /// sinh(u)² by cosh(u)² - 1, as long as that reduces the number of terms.
+    fn pythagoras(&self, mut p: Poly) -> Result<Poly, Error> {
+        loop {
+            let mut atoms: Vec<&NodeRc> = p.factors().flat_map(|(base, _)| base.iter().map(|t| &t.0)).collect();
+            atoms.sort();
+            atoms.dedup();
+
+            let mut best: Option<Poly> = None;
+            for &a in atoms.iter() {
+                let (t, u) = match transient(a) {
+                    Some(tu) => tu,
+                    None => continue
+                };
+                // a² = k + c b²
+                let (partner, k, c) = match t {
+                    Sin => (Cos, 1, -1),
+                    Cos => (Sin, 1, -1),
+                    Sinh => (Cosh, -1, 1),
+                    Cosh => (Sinh, 1, 1),
+                    _ => continue
+                };
+                let b = self.builder.func(partner.into(), u.clone())?;
+                if !atoms.contains(&&b) {
+                    continue;
+                }
+                let square = Poly::int(k) + Poly::product(vec![(b, 2.into())], Rational::from(c as i64));
+                let q = replace_square(&p, a, &square);
+                let n = best.as_ref().unwrap_or(&p).factors().count();
+                if q.factors().count() < n {
+                    best = Some(q);
+                }
+            }
+            match best {
+                Some(q) => p = q,
+                None => return Ok(p)
+            }
+        }
+    }
+}
+
+/// `p` with all even powers a²ᵏ replaced by `square`ᵏ
+fn replace_square(p: &Poly, a: &NodeRc, square: &Poly) -> Poly {
+    let mut sum = Poly::zero();
+    for (base, fac) in p.factors() {
+        let mut term = Poly::rational(fac.clone());
+        for &(ref v, ref n) in base.iter() {
+            term = term * match n.as_i32() {
+                Some(n) if v == a && n >= 2 => {
+                    square.clone().pow_n(n as u32 / 2) * Poly::product(vec![(v.clone(), Rational::from((n % 2) as i64))], 1.into())
+                },
+                _ => Poly::product(vec![(v.clone(), n.clone())], 1.into())
+            };
+        }
+        sum = sum + term;
+    }
+    sum
+}
+
+/// Simplify the elementary functions in `node`, using the rules that need `assume` where
+/// it allows them.
+pub fn simplify(builder: &Builder, node: &NodeRc, assume: &Assumptions) -> NodeResult {
+    Simplifier { builder, assume }.node(node)
+}
+
+/// sin(a + b) = sin a cos b + cos a sin b, and likewise for cos, sinh and cosh
+pub fn expand_trig(builder: &Builder, node: &NodeRc) -> NodeResult {
+    match **node {
+        Node::Apply(ref f, ref g) => {
+            let g = expand_trig(builder, g)?;
+            let t = match **f {
+                Node::Op(Func::Transient(t)) => t,
+                _ => return Ok(builder.intern(Node::Apply(f.clone(), g)))
+            };
+            let (sin, cos) = match t {
+                Sin | Cos => (Sin, Cos),
+                Sinh | Cosh => (Sinh, Cosh),
+                _ => return builder.func(t.into(), g)
+            };
+            let p = match *g {
+                Node::Poly(ref p) if p.factors().count() > 1 => p.clone(),
+                // sin(-b) = -sin(b) and cos(-b) = cos(b) for the parts
+                _ => return Simplifier { builder, assume: &Assumptions::new() }.apply(t, g)
+            };
+            let first = p.split().swap_remove(0);
+            let a = to_node(builder, first.clone());
+            let b = to_node(builder, p + first * (-1));
+            let f = |t: Transient, x: &NodeRc| expand_trig(builder, &builder.func(t.into(), x.clone())?);
+            let (sa, ca, sb, cb) = (f(sin, &a)?, f(cos, &a)?, f(sin, &b)?, f(cos, &b)?);
+            match t {
+                Sin | Sinh => builder.add(builder.mul(sa, cb)?, builder.mul(ca, sb)?),
+                Cos => builder.sub(builder.mul(ca, cb)?, builder.mul(sa, sb)?),
+                _ => builder.add(builder.mul(ca, cb)?, builder.mul(sa, sb)?)
+            }
+        },
+        Node::Poly(ref p) => builder.sum(p.factors().map(|(base, fac)| {
+            builder.product(
+                once(Ok(builder.rational(fac.clone())))
+                    .chain(base.iter().map(|&(ref v, ref n)| builder.pow_r(expand_trig(builder, v)?, n.clone())))
+            )
+        })),
+        Node::Tuple(ref parts) => builder.tuple(parts.iter().map(|p| expand_trig(builder, p))),
+        Node::Var(_) | Node::Op(_) => Ok(node.clone())
+    }
+}
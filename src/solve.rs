@@ -0,0 +1,421 @@
+//! Real roots of univariate polynomials.
+//!
+//! Rational roots are found with the rational root theorem. The remaining irreducible
+//! factors are solved by radicals up to degree four, as long as the roots can be written
+//! with real square and cube roots. All other real roots are isolated with Sturm sequences
+//! and refined by bisection, so they are returned as rationals close to the root.
+//!
+//! Systems of polynomial equations are solved with lexicographic Gröbner bases, which
+//! contain a univariate polynomial in the last variable when the system has finitely many
+//! solutions.
+
+use crate::prelude::*;
+use crate::poly::Poly;
+use crate::factor::factor;
+use crate::eval::EvalContext;
+use crate::groebner::{groebner, Order};
+use std::collections::BTreeSet;
+use std::cmp::{max, Ordering};
+
+/// coefficients, lowest power first
+type Dense = Vec<Rational>;
+
+/// bisection stops when the interval is shorter than 2^-PRECISION
+const PRECISION: u32 = 48;
+
+/// the rational root theorem is only tried when a₀ and aₙ are at most this large
+const MAX_DIVISOR_SEARCH: i64 = 1 << 40;
+
+/// The distinct real roots of the polynomial `node` in `var`, from the smallest to the largest.
+pub fn solve(builder: &Builder, node: &NodeRc, var: &str) -> Result<NodeRc, Error> {
+    let v = builder.var(var);
+    let p = Poly::from_node(builder.expand(node.clone())?);
+    let mut p = dense(&p, &v)
+        .ok_or_else(|| Error::Other(format!("{} is not a polynomial in {}", node, var)))?;
+    if p.is_empty() {
+        return Err(Error::Other(format!("every value of {} is a solution", var)));
+    }
+
+    let mut roots = vec![];
+    if p[0].is_zero() {
+        roots.push(Rational::from(0));
+        while p[0].is_zero() {
+            p.remove(0);
+        }
+    }
+    for r in rational_roots(&p) {
+        while eval(&p, &r).is_zero() {
+            p = deflate(&p, &r);
+        }
+        roots.push(r);
+    }
+
+    let mut out: Vec<NodeRc> = roots.into_iter().map(|r| builder.rational(r)).collect();
+    if p.len() > 1 {
+        for (f, _) in factor(&to_poly(&p, &v))?.factors {
+            let f = dense(&f, &v).unwrap();
+            match radicals(builder, &f)? {
+                Some(r) => out.extend(r),
+                None => out.extend(isolate(&f).into_iter().map(|r| builder.rational(r)))
+            }
+        }
+    }
+
+    let ctx = EvalContext::new();
+    let mut keyed = out.into_iter()
+        .map(|n| Ok((ctx.eval(&n)?, n)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    builder.tuple(keyed.into_iter().map(|(_, n)| Ok(n)))
+}
+
+/// The real solutions of the system `eqs = 0` with finitely many solutions, as a tuple of
+/// tuples with one value per variable.
+///
+/// The candidates for each variable are the roots of its elimination polynomial, and the
+/// combinations that satisfy all equations numerically are kept.
+pub fn solve_system(builder: &Builder, eqs: &NodeRc, vars: &[&str]) -> Result<NodeRc, Error> {
+    let eqs = match **eqs {
+        Node::Tuple(ref parts) => parts.clone(),
+        _ => vec![eqs.clone()]
+    };
+    let polys = eqs.iter()
+        .map(|e| Ok(Poly::from_node(builder.expand(e.clone())?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let nodes: Vec<NodeRc> = vars.iter().map(|v| builder.var(v)).collect();
+
+    let mut ctx = EvalContext::new();
+    let mut candidates = vec![];
+    for (i, &var) in vars.iter().enumerate() {
+        // the lex basis with `var` as the smallest variable
+        let mut order = nodes.clone();
+        let v = order.remove(i);
+        order.push(v.clone());
+        let basis = groebner(&polys, &order, Order::Lex)?;
+        if basis.iter().any(|p| p.as_rational().is_some()) {
+            // 1 is in the ideal
+            return builder.tuple(vec![]);
+        }
+        let univariate = basis.iter()
+            .find(|p| p.factors().all(|(base, _)| base.iter().all(|&(ref w, _)| *w == v)))
+            .ok_or_else(|| Error::Other("the system does not have finitely many solutions".into()))?;
+        let roots = match *solve(builder, &builder.poly(univariate.clone()), var)? {
+            Node::Tuple(ref roots) => roots.clone(),
+            _ => unreachable!()
+        };
+        candidates.push(roots.into_iter()
+            .map(|r| Ok((ctx.eval(&r)?, r)))
+            .collect::<Result<Vec<_>, Error>>()?);
+    }
+    if candidates.iter().any(|c| c.is_empty()) {
+        return builder.tuple(vec![]);
+    }
+
+    // every combination of candidates, counting like an odometer
+    let mut out = vec![];
+    let mut index = vec![0; vars.len()];
+    'outer: loop {
+        for (&var, (c, &k)) in vars.iter().zip(candidates.iter().zip(index.iter())) {
+            ctx.set(var, c[k].0);
+        }
+        if eqs.iter().all(|e| ctx.eval(e).map_or(false, |y| y.abs() < 1e-6)) {
+            out.push(builder.tuple(candidates.iter().zip(index.iter()).map(|(c, &k)| Ok(c[k].1.clone()))));
+        }
+        for i in (0 .. index.len()).rev() {
+            index[i] += 1;
+            if index[i] < candidates[i].len() {
+                continue 'outer;
+            }
+            index[i] = 0;
+        }
+        break;
+    }
+    builder.tuple(out)
+}
+
+fn dense(p: &Poly, v: &NodeRc) -> Option<Dense> {
+    let mut out = vec![];
+    for (base, fac) in p.factors() {
+        let k = match base.as_slice() {
+            [] => 0,
+            [(w, n)] if w == v => n.as_i32().filter(|&n| n > 0)? as usize,
+            _ => return None
+        };
+        if out.len() <= k {
+            out.resize(k + 1, Rational::from(0));
+        }
+        out[k] = fac.clone();
+    }
+    Some(out)
+}
+
+fn to_poly(p: &[Rational], v: &NodeRc) -> Poly {
+    p.iter().enumerate().fold(Poly::zero(), |sum, (k, c)| {
+        sum + Poly::product(vec![(v.clone(), Rational::from(k as i64))], c.clone())
+    })
+}
+
+fn eval(p: &[Rational], x: &Rational) -> Rational {
+    p.iter().rev().fold(Rational::from(0), |acc, c| acc * x.clone() + c.clone())
+}
+
+/// `p / (x - r)` for a root `r` of `p`
+fn deflate(p: &[Rational], r: &Rational) -> Dense {
+    let mut q = vec![Rational::from(0); p.len() - 1];
+    let mut acc = Rational::from(0);
+    for i in (1 .. p.len()).rev() {
+        acc = acc * r.clone() + p[i].clone();
+        q[i - 1] = acc.clone();
+    }
+    q
+}
+
+/// the roots among ±d/e, where d divides the lowest and e the leading coefficient
+fn rational_roots(p: &[Rational]) -> Vec<Rational> {
+    if p.len() < 2 {
+        return vec![];
+    }
+    // scale to integer coefficients
+    let lcm = p.iter().fold(Int::from(1), |l, c| {
+        let d = c.frac().1;
+        l.clone() * &d / &l.gcd(&d)
+    });
+    let scaled: Dense = p.iter().map(|c| c.clone() * Rational::from(lcm.clone())).collect();
+    let a0 = scaled.iter().find(|c| !c.is_zero()).and_then(|c| c.as_i64());
+    let an = scaled.last().and_then(|c| c.as_i64());
+    let (a0, an) = match (a0, an) {
+        (Some(a0), Some(an)) if a0.abs() <= MAX_DIVISOR_SEARCH && an.abs() <= MAX_DIVISOR_SEARCH => (a0, an),
+        _ => return vec![]
+    };
+
+    let mut candidates = BTreeSet::new();
+    for &d in divisors(a0).iter() {
+        for &e in divisors(an).iter() {
+            let r = Rational::new(d.into(), e.into());
+            candidates.insert(r.clone() * -1);
+            candidates.insert(r);
+        }
+    }
+    candidates.into_iter().filter(|r| eval(p, r).is_zero()).collect()
+}
+
+fn divisors(n: i64) -> Vec<i64> {
+    let n = n.abs();
+    let mut out = vec![];
+    let mut d = 1;
+    while d * d <= n {
+        if n % d == 0 {
+            out.push(d);
+            if d * d != n {
+                out.push(n / d);
+            }
+        }
+        d += 1;
+    }
+    out
+}
+
+/// the real roots of the irreducible `f` by radicals, if there is a real formula for them
+fn radicals(builder: &Builder, f: &[Rational]) -> Result<Option<Vec<NodeRc>>, Error> {
+    let c = |i: usize| f[i].clone() / f[f.len() - 1].clone();
+    Ok(match f.len() - 1 {
+        1 => Some(vec![builder.rational(c(0) * -1)]),
+        2 => Some(quadratic(builder, c(1), c(0))?),
+        3 => cubic(builder, c(2), c(1), c(0))?,
+        4 => quartic(builder, c(3), c(2), c(1), c(0))?,
+        _ => None
+    })
+}
+
+/// x² + b x + c
+fn quadratic(builder: &Builder, b: Rational, c: Rational) -> Result<Vec<NodeRc>, Error> {
+    let half = Rational::new(1.into(), 2.into());
+    let d = b.clone() * b.clone() - Rational::from(4) * c;
+    if d.is_negative() {
+        return Ok(vec![]);
+    }
+    // -b/2 ± √d / 2
+    let mid = builder.rational(b * half.clone() * -1);
+    let s = builder.mul(builder.rational(half), builder.sqrt(builder.rational(d))?)?;
+    Ok(vec![builder.sub(mid.clone(), s.clone())?, builder.add(mid, s)?])
+}
+
+/// x³ + b x² + c x + d, only if it has a single real root (Cardano)
+fn cubic(builder: &Builder, b: Rational, c: Rational, d: Rational) -> Result<Option<Vec<NodeRc>>, Error> {
+    // x = t - b/3 gives t³ + p t + q
+    let third = Rational::new(1.into(), 3.into());
+    let p = c.clone() - b.clone() * b.clone() * third.clone();
+    let q = Rational::new(2.into(), 27.into()) * b.pow(3) - b.clone() * c * third.clone() + d;
+    let delta = q.pow(2) * Rational::new(1.into(), 4.into()) + p.pow(3) * Rational::new(1.into(), 27.into());
+    if delta.is_negative() || delta.is_zero() {
+        // three real roots need complex cube roots
+        return Ok(None);
+    }
+
+    // t = ∛(-q/2 + √Δ) + ∛(-q/2 - √Δ)
+    let u = builder.rational(q * Rational::new((-1).into(), 2.into()));
+    let s = builder.sqrt(builder.rational(delta))?;
+    let t = builder.add(
+        builder.cbrt(builder.add(u.clone(), s.clone())?)?,
+        builder.cbrt(builder.sub(u, s)?)?
+    )?;
+    Ok(Some(vec![builder.add(t, builder.rational(b * third * -1))?]))
+}
+
+/// x⁴ + b x³ + c x² + d x + e, if it is biquadratic or the resolvent has a rational root (Ferrari)
+fn quartic(builder: &Builder, b: Rational, c: Rational, d: Rational, e: Rational) -> Result<Option<Vec<NodeRc>>, Error> {
+    let frac = |n: i64, m: i64| Rational::new(n.into(), m.into());
+    let half = frac(1, 2);
+
+    // x = t + shift gives t⁴ + p t² + q t + r
+    let shift = builder.rational(b.clone() * frac(-1, 4));
+    let p = c.clone() - b.pow(2) * frac(3, 8);
+    let q = d.clone() - b.clone() * c.clone() * half.clone() + b.pow(3) * frac(1, 8);
+    let r = e - b.clone() * d * frac(1, 4) + b.pow(2) * c * frac(1, 16) - b.pow(4) * frac(3, 256);
+
+    // mid ± √radicand / 2, if the radicand (numerically `value`) is positive
+    let mut roots = vec![];
+    let mut push_pair = |mid: NodeRc, radicand: NodeRc, value: f64| -> Result<(), Error> {
+        if value > 0.0 {
+            let w = builder.mul(builder.rational(half.clone()), builder.sqrt(radicand)?)?;
+            roots.push(builder.sub(mid.clone(), w.clone())?);
+            roots.push(builder.add(mid, w)?);
+        }
+        Ok(())
+    };
+
+    if q.is_zero() {
+        // t² = (-p ± √(p² - 4r)) / 2
+        let disc = p.pow(2) - r * 4;
+        if disc.is_negative() {
+            return Ok(Some(vec![]));
+        }
+        let sd = builder.sqrt(builder.rational(disc.clone()))?;
+        for &sign in [-1, 1].iter() {
+            let u = builder.add(
+                builder.rational(p.clone() * half.clone() * -1),
+                builder.mul(builder.rational(half.clone() * sign), sd.clone())?
+            )?;
+            let value = -p.as_f64() / 2.0 + sign as f64 * disc.as_f64().sqrt() / 2.0;
+            // ±√u = (±√(4u)) / 2
+            push_pair(shift.clone(), builder.mul(builder.int(4), u)?, value)?;
+        }
+        return Ok(Some(roots));
+    }
+
+    // 8m³ + 8p m² + (2p² - 8r) m - q² = 0 makes both sides of
+    // (t² + p/2 + m)² = 2m t² - q t + m² + m p + p²/4 - r squares
+    let resolvent = vec![q.pow(2) * -1, p.pow(2) * 2 - r * 8, p.clone() * 8, Rational::from(8)];
+    let m = match rational_roots(&resolvent).into_iter().find(|m| !m.is_negative() && !m.is_zero()) {
+        Some(m) => m,
+        None => return Ok(None)
+    };
+
+    // t² ± s t + p/2 + m ∓ q/(2s) with s = √(2m)
+    // t = ∓s/2 ± √(-2p - 2m ± q s / m) / 2
+    let s = builder.sqrt(builder.rational(m.clone() * 2))?;
+    let s_value = (m.as_f64() * 2.0).sqrt();
+    let k = q / m.clone();
+    for &sign in [1, -1].iter() {
+        let radicand = builder.add(
+            builder.rational((p.clone() + m.clone()) * -2),
+            builder.mul(builder.rational(k.clone() * sign), s.clone())?
+        )?;
+        let value = -2.0 * (p.as_f64() + m.as_f64()) + sign as f64 * k.as_f64() * s_value;
+        let mid = builder.add(shift.clone(), builder.mul(builder.rational(half.clone() * -sign), s.clone())?)?;
+        push_pair(mid, radicand, value)?;
+    }
+    Ok(Some(roots))
+}
+
+fn trim(p: &mut Dense) {
+    while p.last().map_or(false, |c| c.is_zero()) {
+        p.pop();
+    }
+}
+
+/// remainder of `a / b`
+fn rem(a: &[Rational], b: &[Rational]) -> Dense {
+    let mut r = a.to_vec();
+    while r.len() >= b.len() {
+        let k = r.len() - b.len();
+        let c = r[r.len() - 1].clone() / b[b.len() - 1].clone();
+        for (i, x) in b.iter().enumerate() {
+            r[i + k] = r[i + k].clone() - c.clone() * x.clone();
+        }
+        r.pop();
+        trim(&mut r);
+    }
+    r
+}
+
+fn derivative(p: &[Rational]) -> Dense {
+    p.iter().enumerate().skip(1).map(|(i, c)| c.clone() * i as i32).collect()
+}
+
+/// f, f', and the negated remainders
+fn sturm(f: &[Rational]) -> Vec<Dense> {
+    let mut seq = vec![f.to_vec(), derivative(f)];
+    loop {
+        let r = rem(&seq[seq.len() - 2], &seq[seq.len() - 1]);
+        if r.is_empty() {
+            break;
+        }
+        seq.push(r.into_iter().map(|c| c * -1).collect());
+    }
+    seq
+}
+
+/// sign changes of the Sturm sequence at `x`
+fn variations(seq: &[Dense], x: &Rational) -> usize {
+    let signs: Vec<bool> = seq.iter()
+        .map(|p| eval(p, x))
+        .filter(|y| !y.is_zero())
+        .map(|y| y.is_negative())
+        .collect();
+    signs.windows(2).filter(|w| w[0] != w[1]).count()
+}
+
+/// approximations of the real roots of the square-free `f`, which has no rational roots
+fn isolate(f: &[Rational]) -> Vec<Rational> {
+    let seq = sturm(f);
+    let half = Rational::new(1.into(), 2.into());
+    let lead = f[f.len() - 1].abs();
+
+    // all roots are within ±(1 + max |aᵢ / aₙ|)
+    let bound = f[.. f.len() - 1].iter()
+        .fold(Rational::from(0), |m, c| max(m, c.abs() / lead.clone()))
+        + Rational::from(1);
+
+    let mut out = vec![];
+    let mut work = vec![(bound.clone() * -1, bound)];
+    while let Some((lo, hi)) = work.pop() {
+        // the number of roots in (lo, hi]
+        match variations(&seq, &lo) - variations(&seq, &hi) {
+            0 => {},
+            1 => out.push(bisect(f, lo, hi)),
+            _ => {
+                let mid = (lo.clone() + hi.clone()) * half.clone();
+                work.push((mid.clone(), hi));
+                work.push((lo, mid));
+            }
+        }
+    }
+    out
+}
+
+/// refine the single root of `f` in (lo, hi)
+fn bisect(f: &[Rational], mut lo: Rational, mut hi: Rational) -> Rational {
+    let half = Rational::new(1.into(), 2.into());
+    let eps = Rational::new(1.into(), Int::from(2).pow(PRECISION));
+    let lo_negative = eval(f, &lo).is_negative();
+    while hi.clone() - lo.clone() > eps {
+        let mid = (lo.clone() + hi.clone()) * half.clone();
+        if eval(f, &mid).is_negative() == lo_negative {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    (lo + hi) * half
+}
@@ -12,9 +12,25 @@ pub mod simd;
 #[cfg(feature="wasm")]
 pub mod wasm;
 
-#[cfg(feature="glsl")]
+pub mod ptx;
+pub mod opencl;
+pub mod shader;
 pub mod glsl;
 
+/// format `x` as a floating point literal for C-like languages, followed by `suffix`
+pub(crate) fn float_literal(x: f64, suffix: &str) -> String {
+    let s = if x.fract() == 0.0 && x.abs() < 1e15 {
+        format!("{:.1}{}", x, suffix)
+    } else {
+        format!("{:?}{}", x, suffix)
+    };
+    if x < 0.0 {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Round {
     Up,
@@ -48,6 +64,10 @@ pub trait Vm {
     fn store(&mut self, var: &mut Self::Var, uses: usize) -> Self::Storage;
     fn load(&mut self, storage: &Self::Storage) -> Self::Var;
     fn round(&mut self, a: Self::Var, mode: Round) -> Self::Var;
+
+    /// called before and after the code for `node` is generated
+    fn enter(&mut self, _node: &Node) {}
+    fn leave(&mut self, _node: &Node) {}
     
     fn copy(&mut self, var: &mut Self::Var) -> Self::Var {
         let s = self.store(var, 1);
@@ -64,6 +84,29 @@ pub trait Vm {
         let one = self.make_const(1.0);
         self.div(one, a)
     }
+
+    /// √a = a r, with r → 1/√a by the Newton iteration r ← r (3 - a r²) / 2 starting at 2 / (1 + a).
+    ///
+    /// Accurate for a in about 10⁻⁷ … 10⁷. Backends with a square root instruction should use it instead.
+    fn sqrt(&mut self, mut a: Self::Var) -> Self::Var {
+        let a2 = self.copy(&mut a);
+        let one = self.make_int(1);
+        let den = self.add(a2, one);
+        let two = self.make_int(2);
+        let mut r = self.div(two, den);
+        for _ in 0 .. 24 {
+            let r2 = self.copy(&mut r);
+            let a2 = self.copy(&mut a);
+            let r_square = self.pow_n(r2, 2);
+            let ar_square = self.mul(a2, r_square);
+            let three = self.make_int(3);
+            let t = self.sub(three, ar_square);
+            let half = self.make_const(0.5);
+            let t = self.mul(t, half);
+            r = self.mul(r, t);
+        }
+        self.mul(a, r)
+    }
     
     fn add(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
         self.make_sum(vec![a, b])
@@ -121,19 +164,22 @@ pub trait Vm {
             return x;
         }
         
-        let mut y = self.copy(&mut x); // holds the power so far
+        // n is odd now, so the power so far starts with x itself
+        let mut y = self.copy(&mut x);
+        n /= 2;
         while n > 1 {
+            let x2 = self.copy(&mut x);
+            x = self.mul(x, x2);
             if n & 1 == 1 {
                 let x2 = self.copy(&mut x);
                 y = self.mul(y, x2);
             }
-
-            let x2 = self.copy(&mut x);
-            x = self.mul(x, x2);
             n /= 2;
         }
 
         assert_eq!(n, 1);
+        let x2 = self.copy(&mut x);
+        x = self.mul(x, x2); // highest bit
         self.mul(x, y) // final multiplication
     }
 
@@ -220,6 +266,273 @@ pub trait Vm {
         self.poly(&k, y_square)
     }
 
+    /// tan x = sin x / cos x
+    fn tan(&mut self, mut x: Self::Var) -> Self::Var {
+        let x2 = self.copy(&mut x);
+        let s = self.sin(x);
+        let c = self.cos(x2);
+        self.div(s, c)
+    }
+
+    /// eˣ = (e^(x/2⁸))^(2⁸), with the Taylor polynomial for the small argument
+    fn exp(&mut self, x: Self::Var) -> Self::Var {
+        let scale = self.make_const(1.0 / 256.0);
+        let y = self.mul(x, scale);
+        let k: Vec<_> = (0 ..= 6u32).rev() // 1/n!, highest power first
+            .map(|n| 1.0 / (1 ..= n).product::<u32>() as f64)
+            .collect();
+        let p = self.poly(&k, y);
+        self.pow_n(p, 256)
+    }
+
+    /// ln x = 2⁸ ln x^(1/2⁸), and ln y = 2 atanh((y - 1) / (y + 1)) for y close to 1
+    fn log(&mut self, x: Self::Var) -> Self::Var {
+        let mut y = x;
+        for _ in 0 .. 8 {
+            y = self.sqrt(y);
+        }
+        let y2 = self.copy(&mut y);
+        let one = self.make_int(1);
+        let num = self.sub(y, one);
+        let one = self.make_int(1);
+        let den = self.add(y2, one);
+        let mut s = self.div(num, den);
+        let s2 = self.copy(&mut s);
+
+        // 2⁹ (s + s³/3 + s⁵/5 + s⁷/7)
+        let k: Vec<_> = [7.0, 5.0, 3.0, 1.0].iter().map(|&n| 512.0 / n).collect();
+        let s_square = self.pow_n(s, 2);
+        let p = self.poly(&k, s_square);
+        self.mul(p, s2)
+    }
+
+    /// the real q-th root for odd q: sign(x) exp(ln |x| / q)
+    fn odd_root(&mut self, mut x: Self::Var, q: u32) -> Self::Var {
+        let x2 = self.copy(&mut x);
+        let abs = self.abs(x);
+        let log = self.log(abs);
+        let scale = self.make_const(1.0 / q as f64);
+        let y = self.mul(log, scale);
+        let root = self.exp(y);
+        let sign = self.sign(x2);
+        self.mul(sign, root)
+    }
+
+    /// atan x = 8 atan t, where t is x with the angle halved three times by
+    /// atan x = 2 atan(x / (1 + √(1 + x²)))
+    fn atan(&mut self, x: Self::Var) -> Self::Var {
+        let mut t = x;
+        for _ in 0 .. 3 {
+            let t2 = self.copy(&mut t);
+            let t_square = self.pow_n(t2, 2);
+            let one = self.make_int(1);
+            let r = self.add(t_square, one);
+            let r = self.sqrt(r);
+            let one = self.make_int(1);
+            let d = self.add(r, one);
+            t = self.div(t, d);
+        }
+        // |t| < tan(π/16), so the Taylor series up to t¹³ is accurate to 2e-11
+        let t2 = self.copy(&mut t);
+        let k: Vec<_> = (0 .. 7).rev()
+            .map(|i| 8.0 * (-1f64).powi(i) / (2 * i + 1) as f64)
+            .collect();
+        let t_square = self.pow_n(t, 2);
+        let p = self.poly(&k, t_square);
+        self.mul(p, t2)
+    }
+
+    /// asin x = 2 atan(x / (1 + √(1 - x²)))
+    fn asin(&mut self, mut x: Self::Var) -> Self::Var {
+        let x2 = self.copy(&mut x);
+        let x_square = self.pow_n(x2, 2);
+        let one = self.make_int(1);
+        let r = self.sub(one, x_square);
+        let r = self.sqrt(r);
+        let one = self.make_int(1);
+        let d = self.add(r, one);
+        let t = self.div(x, d);
+        let a = self.atan(t);
+        let two = self.make_int(2);
+        self.mul(a, two)
+    }
+
+    /// acos x = π/2 - asin x
+    fn acos(&mut self, x: Self::Var) -> Self::Var {
+        let a = self.asin(x);
+        let half_pi = self.make_const(f64::PI / 2.0);
+        self.sub(half_pi, a)
+    }
+
+    /// atan2(y, x) = 2 atan(y / (r + x)) = 2 atan((r - x) / y) with r = √(x² + y²).
+    ///
+    /// The second form is used for x < 0, where r + x cancels.
+    /// Not defined for y = 0 and x < 0.
+    fn atan2(&mut self, mut y: Self::Var, mut x: Self::Var) -> Self::Var {
+        let y2 = self.copy(&mut y);
+        let y3 = self.copy(&mut y);
+        let y4 = self.copy(&mut y);
+        let x2 = self.copy(&mut x);
+        let x3 = self.copy(&mut x);
+        let x4 = self.copy(&mut x);
+        let x_square = self.pow_n(x2, 2);
+        let y_square = self.pow_n(y2, 2);
+        let r = self.add(x_square, y_square);
+        let mut r = self.sqrt(r);
+        let r2 = self.copy(&mut r);
+        let zero = self.make_int(0);
+        let mut s = self.step_at(zero, x3);
+        let s2 = self.copy(&mut s);
+
+        // num / den is y / (r + x) for s = 1 and (r - x) / y for s = 0
+        let mut r_minus_x = self.sub(r, x);
+        let r_minus_x2 = self.copy(&mut r_minus_x);
+        let d = self.sub(y, r_minus_x2);
+        let num = self.mul_add(s, d, r_minus_x);
+        let r_plus_x = self.add(r2, x4);
+        let d = self.sub(r_plus_x, y3);
+        let den = self.mul_add(s2, d, y4);
+        let t = self.div(num, den);
+        let a = self.atan(t);
+        let two = self.make_int(2);
+        self.mul(a, two)
+    }
+
+    /// sinh x = (eˣ - e⁻ˣ) / 2
+    fn sinh(&mut self, x: Self::Var) -> Self::Var {
+        let mut e = self.exp(x);
+        let e2 = self.copy(&mut e);
+        let e_inv = self.inv(e2);
+        let d = self.sub(e, e_inv);
+        let half = self.make_const(0.5);
+        self.mul(d, half)
+    }
+
+    /// cosh x = (eˣ + e⁻ˣ) / 2
+    fn cosh(&mut self, x: Self::Var) -> Self::Var {
+        let mut e = self.exp(x);
+        let e2 = self.copy(&mut e);
+        let e_inv = self.inv(e2);
+        let s = self.add(e, e_inv);
+        let half = self.make_const(0.5);
+        self.mul(s, half)
+    }
+
+    /// tanh x = 1 - 2 / (e²ˣ + 1)
+    fn tanh(&mut self, x: Self::Var) -> Self::Var {
+        let two = self.make_int(2);
+        let x = self.mul(x, two);
+        let e = self.exp(x);
+        let one = self.make_int(1);
+        let d = self.add(e, one);
+        let two = self.make_int(2);
+        let q = self.div(two, d);
+        let one = self.make_int(1);
+        self.sub(one, q)
+    }
+
+    /// asinh x = ln(x + √(x² + 1))
+    fn asinh(&mut self, mut x: Self::Var) -> Self::Var {
+        let x2 = self.copy(&mut x);
+        let x_square = self.pow_n(x2, 2);
+        let one = self.make_int(1);
+        let r = self.add(x_square, one);
+        let r = self.sqrt(r);
+        let s = self.add(x, r);
+        self.log(s)
+    }
+
+    /// acosh x = ln(x + √(x² - 1))
+    fn acosh(&mut self, mut x: Self::Var) -> Self::Var {
+        let x2 = self.copy(&mut x);
+        let x_square = self.pow_n(x2, 2);
+        let one = self.make_int(1);
+        let r = self.sub(x_square, one);
+        let r = self.sqrt(r);
+        let s = self.add(x, r);
+        self.log(s)
+    }
+
+    /// atanh x = ln((1 + x) / (1 - x)) / 2
+    fn atanh(&mut self, mut x: Self::Var) -> Self::Var {
+        let x2 = self.copy(&mut x);
+        let one = self.make_int(1);
+        let num = self.add(one, x);
+        let one = self.make_int(1);
+        let den = self.sub(one, x2);
+        let q = self.div(num, den);
+        let l = self.log(q);
+        let half = self.make_const(0.5);
+        self.mul(l, half)
+    }
+
+    /// |x| = √(x²)
+    fn abs(&mut self, x: Self::Var) -> Self::Var {
+        let x_square = self.pow_n(x, 2);
+        self.sqrt(x_square)
+    }
+
+    /// sign x = step(x) - step(-x), which is 0 for x = 0
+    fn sign(&mut self, mut x: Self::Var) -> Self::Var {
+        let x2 = self.copy(&mut x);
+        let zero = self.make_int(0);
+        let pos = self.step_at(zero, x);
+        let minus_one = self.make_int(-1);
+        let minus_x = self.mul(x2, minus_one);
+        let zero = self.make_int(0);
+        let neg = self.step_at(zero, minus_x);
+        self.sub(pos, neg)
+    }
+
+    /// min(a, b) = (a + b - |a - b|) / 2
+    fn min(&mut self, mut a: Self::Var, mut b: Self::Var) -> Self::Var {
+        let a2 = self.copy(&mut a);
+        let b2 = self.copy(&mut b);
+        let d = self.sub(a2, b2);
+        let d = self.abs(d);
+        let s = self.add(a, b);
+        let s = self.sub(s, d);
+        let half = self.make_const(0.5);
+        self.mul(s, half)
+    }
+
+    /// max(a, b) = (a + b + |a - b|) / 2
+    fn max(&mut self, mut a: Self::Var, mut b: Self::Var) -> Self::Var {
+        let a2 = self.copy(&mut a);
+        let b2 = self.copy(&mut b);
+        let d = self.sub(a2, b2);
+        let d = self.abs(d);
+        let s = self.add(a, b);
+        let s = self.add(s, d);
+        let half = self.make_const(0.5);
+        self.mul(s, half)
+    }
+
+    /// erf x = sign x (1 - p(t) e^(-x²)) with t = 1 / (1 + 0.3275911 |x|)
+    /// (Abramowitz and Stegun 7.1.26, err ~ 1.5e-7)
+    fn erf(&mut self, mut x: Self::Var) -> Self::Var {
+        let x2 = self.copy(&mut x);
+        let s = self.sign(x);
+        let mut a = self.abs(x2);
+        let a2 = self.copy(&mut a);
+
+        let p = self.make_const(0.3275911);
+        let one = self.make_int(1);
+        let d = self.mul_add(a, p, one);
+        let t = self.inv(d);
+        let k = [1.061405429, -1.453152027, 1.421413741, -0.284496736, 0.254829592, 0.0];
+        let p = self.poly(&k, t);
+
+        let a_square = self.pow_n(a2, 2);
+        let minus_one = self.make_int(-1);
+        let y = self.mul(a_square, minus_one);
+        let e = self.exp(y);
+        let pe = self.mul(p, e);
+        let one = self.make_int(1);
+        let r = self.sub(one, pe);
+        self.mul(s, r)
+    }
+
     /// return 1 if x >= at else 0
     fn step_at(&mut self, at: Self::Var, x: Self::Var) -> Self::Var;
 }
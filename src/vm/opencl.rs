@@ -0,0 +1,195 @@
+use crate::prelude::*;
+use crate::compiler::Compiler;
+use crate::vm::{Vm, Round, float_literal};
+use std::mem;
+use std::fmt::Write;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Precision {
+    Single,
+    Double
+}
+impl Precision {
+    fn typ(self) -> &'static str {
+        match self {
+            Precision::Single => "float",
+            Precision::Double => "double"
+        }
+    }
+    fn suffix(self) -> &'static str {
+        match self {
+            Precision::Single => "f",
+            Precision::Double => ""
+        }
+    }
+}
+
+/// Emits an OpenCL C kernel.
+///
+/// Every input `k` is a separate `__global` buffer `in_k` and work item `i = get_global_id(0)`
+/// reads `in_k[i]` and writes `out_k[i]` for each output `k`. Work items past the length `n`
+/// of the buffers return right away.
+pub struct OpenCl {
+    precision: Precision,
+    decl: String,
+    stored: usize,
+    inputs: Vec<String>
+}
+
+impl OpenCl {
+    pub fn new(precision: Precision) -> OpenCl {
+        OpenCl {
+            precision,
+            decl: String::new(),
+            stored: 0,
+            inputs: vec![]
+        }
+    }
+    fn join(&self, mut parts: Vec<String>, op: &str) -> String {
+        match parts.len() {
+            1 => parts.pop().unwrap(),
+            _ => format!("({})", parts.join(op))
+        }
+    }
+
+    /// wrap the generated code into the kernel `name`
+    pub fn assemble(&self, name: &str, outputs: &[String]) -> String {
+        let typ = self.precision.typ();
+        let params = (0 .. self.inputs.len()).map(|k| format!("__global const {}* in_{}", typ, k))
+            .chain((0 .. outputs.len()).map(|k| format!("__global {}* out_{}", typ, k)))
+            .chain(Some("const ulong n".to_owned()))
+            .join(", ");
+
+        let mut s = String::new();
+        if self.precision == Precision::Double {
+            writeln!(s, "#pragma OPENCL EXTENSION cl_khr_fp64 : enable\n").unwrap();
+        }
+        writeln!(s, "__kernel void {}({}) {{", name, params).unwrap();
+        writeln!(s, "    size_t i = get_global_id(0);").unwrap();
+        writeln!(s, "    if (i >= n) return;").unwrap();
+        s.push_str(&self.decl);
+        for (k, out) in outputs.iter().enumerate() {
+            writeln!(s, "    out_{}[i] = {};", k, out).unwrap();
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+/// Generate the OpenCL C source of a kernel `name` computing `nodes` from the inputs `vars`.
+pub fn kernel(name: &str, nodes: &[NodeRc], vars: &[&str], precision: Precision) -> Result<String, Error> {
+    let mut cl = OpenCl::new(precision);
+    let outputs = Compiler::compile(&mut cl, nodes, vars)?;
+    Ok(cl.assemble(name, &outputs))
+}
+
+impl Vm for OpenCl {
+    type Var = String;
+    type Storage = String;
+
+    fn make_int(&mut self, i: i64) -> Self::Var {
+        float_literal(i as f64, self.precision.suffix())
+    }
+    fn make_const(&mut self, x: f64) -> Self::Var {
+        float_literal(x, self.precision.suffix())
+    }
+    fn make_source(&mut self, name: &str) -> Self::Var {
+        // the names of the inputs may collide with the ones of the kernel
+        self.inputs.push(name.into());
+        format!("in_{}[i]", self.inputs.len() - 1)
+    }
+    fn make_sum(&mut self, parts: Vec<Self::Var>) -> Self::Var {
+        self.join(parts, " + ")
+    }
+    fn make_product(&mut self, parts: Vec<Self::Var>) -> Self::Var {
+        self.join(parts, " * ")
+    }
+    fn store(&mut self, var: &mut Self::Var, _uses: usize) -> Self::Storage {
+        let name = format!("storage_{}", self.stored);
+        self.stored += 1;
+        let var = mem::replace(var, self.load(&name));
+        writeln!(self.decl, "    {} {} = {};", self.precision.typ(), name, var).unwrap();
+        name
+    }
+    fn load(&mut self, name: &Self::Storage) -> Self::Var {
+        name.clone()
+    }
+    fn round(&mut self, x: Self::Var, mode: Round) -> Self::Var {
+        match mode {
+            Round::Up => format!("ceil({})", x),
+            Round::Down => format!("floor({})", x),
+        }
+    }
+    fn div(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        format!("({} / {})", a, b)
+    }
+    fn inv(&mut self, a: Self::Var) -> Self::Var {
+        format!("({} / {})", self.make_int(1), a)
+    }
+    fn sqrt(&mut self, x: Self::Var) -> Self::Var {
+        format!("sqrt({})", x)
+    }
+    fn sin(&mut self, x: Self::Var) -> Self::Var {
+        format!("sin({})", x)
+    }
+    fn cos(&mut self, x: Self::Var) -> Self::Var {
+        format!("cos({})", x)
+    }
+    fn tan(&mut self, x: Self::Var) -> Self::Var {
+        format!("tan({})", x)
+    }
+    fn asin(&mut self, x: Self::Var) -> Self::Var {
+        format!("asin({})", x)
+    }
+    fn acos(&mut self, x: Self::Var) -> Self::Var {
+        format!("acos({})", x)
+    }
+    fn atan(&mut self, x: Self::Var) -> Self::Var {
+        format!("atan({})", x)
+    }
+    fn sinh(&mut self, x: Self::Var) -> Self::Var {
+        format!("sinh({})", x)
+    }
+    fn cosh(&mut self, x: Self::Var) -> Self::Var {
+        format!("cosh({})", x)
+    }
+    fn tanh(&mut self, x: Self::Var) -> Self::Var {
+        format!("tanh({})", x)
+    }
+    fn asinh(&mut self, x: Self::Var) -> Self::Var {
+        format!("asinh({})", x)
+    }
+    fn acosh(&mut self, x: Self::Var) -> Self::Var {
+        format!("acosh({})", x)
+    }
+    fn atanh(&mut self, x: Self::Var) -> Self::Var {
+        format!("atanh({})", x)
+    }
+    fn exp(&mut self, x: Self::Var) -> Self::Var {
+        format!("exp({})", x)
+    }
+    fn log(&mut self, x: Self::Var) -> Self::Var {
+        format!("log({})", x)
+    }
+    fn abs(&mut self, x: Self::Var) -> Self::Var {
+        format!("fabs({})", x)
+    }
+    fn sign(&mut self, x: Self::Var) -> Self::Var {
+        format!("sign({})", x)
+    }
+    fn erf(&mut self, x: Self::Var) -> Self::Var {
+        format!("erf({})", x)
+    }
+    fn atan2(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        format!("atan2({}, {})", a, b)
+    }
+    fn min(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        format!("fmin({}, {})", a, b)
+    }
+    fn max(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        format!("fmax({}, {})", a, b)
+    }
+    fn step_at(&mut self, at: Self::Var, x: Self::Var) -> Self::Var {
+        format!("step({}, {})", at, x)
+    }
+}
@@ -0,0 +1,190 @@
+use crate::prelude::*;
+use crate::compiler::Compiler;
+use crate::vm::{Vm, Round};
+use std::fmt::Write;
+
+/// Emits PTX assembly. Does not need CUDA, see `rt::ptx` for loading the result.
+pub struct Ptx {
+    num_regs: usize, // we do SSA here, the ptx jit will do the rest
+    lines: Vec<String>,
+    inputs: Vec<String>
+}
+macro_rules! line {
+    ($selv:ident, $instr:expr, out, $($arg:expr),*) => (
+        {
+            let out = $selv.alloc();
+            let mut instr = format!("    {:19} {}", $instr, out);
+            $( write!(instr, ", {}", $arg).unwrap(); )*
+            write!(instr, ";").unwrap();
+            $selv.push(instr);
+            out
+        }
+    )
+}
+
+fn f32_to_hex(f: f32) -> String {
+    format!("0F{:08x}", f.to_bits())
+}
+
+pub type Reg = String;
+
+impl Ptx {
+    pub fn new() -> Ptx {
+        Ptx {
+            num_regs: 0,
+            lines: Vec::new(),
+            inputs: Vec::new()
+        }
+    }
+    fn push(&mut self, line: String) {
+        self.lines.push(line);
+    }
+    fn alloc(&mut self) -> Reg {
+        let n = self.num_regs;
+        self.num_regs += 1;
+        format!("_r{}", n)
+    }
+
+    /// Wrap the generated code into the kernel `name`.
+    ///
+    /// Thread `i` reads its inputs from `src + i * 4 * inputs` (one f32 per input, in the order of the sources)
+    /// and writes the outputs to `dst + i * 4 * outputs.len()`. Threads with `i >= n` return right away.
+    pub fn assemble(&self, name: &str, outputs: &[Reg]) -> String {
+        let mut store = String::new();
+        for (i, out) in outputs.iter().enumerate() {
+            writeln!(store, "    st.cs.f32           [data_out+{}], {};", 4 * i, out).unwrap();
+        }
+
+        format!("\
+.version 3.0
+.target sm_30
+.address_size 64
+
+.entry {name}(.param.u64 src, .param.u64 dst, .param.u32 n) {{
+    .reg.u64            data_in, data_out;
+    .reg.u64            off_in, off_out;
+    .reg.u32            a, b, c, i, len;
+    .reg.pred           done;
+    .reg.f32            _r<{num_regs}>;
+
+    ld.param.u64        data_in,    [src];
+    ld.param.u64        data_out,   [dst];
+    ld.param.u32        len,        [n];
+    mov.u32             a,          %ctaid.x;
+    mov.u32             b,          %ntid.x;
+    mov.u32             c,          %tid.x;
+    mad.lo.u32          i,          a, b, c;		// global thread index
+    setp.ge.u32         done,       i, len;
+    @done               ret;
+    mul.wide.u32        off_in,     i, {in_size};		// sizeof(f32)*num_inputs
+    mul.wide.u32        off_out,    i, {out_size};		// sizeof(f32)*num_outputs
+    add.u64             data_in,    data_in, off_in;
+    add.u64             data_out,   data_out, off_out;
+
+// generated code
+{code}
+
+// end of generated code
+{store}
+    ret;
+}}
+",
+                name=name,
+                code=self.lines.join("\n"),
+                num_regs=self.num_regs,
+                in_size=self.inputs.len() * 4,
+                out_size=outputs.len() * 4,
+                store=store
+        )
+    }
+}
+
+/// Generate the PTX source of a kernel `name` computing `nodes` from the inputs `vars`.
+pub fn kernel(name: &str, nodes: &[NodeRc], vars: &[&str]) -> Result<String, Error> {
+    let mut ptx = Ptx::new();
+    let outputs = Compiler::compile(&mut ptx, nodes, vars)?;
+    Ok(ptx.assemble(name, &outputs))
+}
+
+impl Vm for Ptx {
+    type Storage = Reg;
+    type Var = Reg;
+    fn make_const(&mut self, c: f64) -> Self::Var {
+        line!(self, "mov.f32", out, f32_to_hex(c as f32))
+    }
+    fn make_source(&mut self, name: &str) -> Self::Var {
+        let off = self.inputs.len() * 4;
+        self.inputs.push(name.to_owned());
+
+        let reg = self.alloc();
+        self.push(format!("    ld.cs.f32           {}, [data_in+{}];", reg, off));
+        reg
+    }
+    fn store(&mut self, var: &mut Self::Var, _uses: usize) -> Self::Storage {
+        var.clone()
+    }
+    fn load(&mut self, storage: &Self::Storage) -> Self::Var {
+        storage.clone()
+    }
+    fn round(&mut self, a: Self::Var, mode: Round) -> Self::Var {
+        match mode {
+            Round::Down => line!(self, "cvt.rmi.f32.f32", out, a),
+            Round::Up => line!(self, "cvt.rpi.f32.f32", out, a)
+        }
+    }
+
+    fn copy(&mut self, var: &mut Self::Var) -> Self::Var {
+        let s = self.store(var, 1);
+        self.load(&s)
+    }
+
+    fn add(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        line!(self, "add.f32", out, a, b)
+    }
+    fn sub(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        line!(self, "sub.f32", out, a, b)
+    }
+    fn mul(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        line!(self, "mul.f32", out, a, b)
+    }
+    fn div(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        line!(self, "div.rn.f32", out, a, b)
+    }
+    fn inv(&mut self, a: Self::Var) -> Self::Var {
+        line!(self, "rcp.rn.f32", out, a)
+    }
+    fn sqrt(&mut self, a: Self::Var) -> Self::Var {
+        line!(self, "sqrt.rn.f32", out, a)
+    }
+    fn sin(&mut self, a: Self::Var) -> Self::Var {
+        line!(self, "sin.approx.f32", out, a)
+    }
+    fn cos(&mut self, a: Self::Var) -> Self::Var {
+        line!(self, "cos.approx.f32", out, a)
+    }
+    fn exp(&mut self, a: Self::Var) -> Self::Var {
+        // eˣ = 2^(x log₂ e)
+        let log2_e = self.make_const(std::f64::consts::LOG2_E);
+        let y = self.mul(a, log2_e);
+        line!(self, "ex2.approx.f32", out, y)
+    }
+    fn log(&mut self, a: Self::Var) -> Self::Var {
+        // ln x = log₂ x ln 2
+        let y = line!(self, "lg2.approx.f32", out, a);
+        let ln_2 = self.make_const(std::f64::consts::LN_2);
+        self.mul(y, ln_2)
+    }
+    fn abs(&mut self, a: Self::Var) -> Self::Var {
+        line!(self, "abs.f32", out, a)
+    }
+    fn min(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        line!(self, "min.f32", out, a, b)
+    }
+    fn max(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        line!(self, "max.f32", out, a, b)
+    }
+    fn step_at(&mut self, at: Self::Var, x: Self::Var) -> Self::Var {
+        // 1.0 if x >= at
+        line!(self, "set.ge.f32.f32", out, x, at)
+    }
+}
@@ -0,0 +1,370 @@
+use crate::prelude::*;
+use crate::compiler::Compiler;
+use crate::vm::{Vm, Round, float_literal};
+use std::mem;
+use std::fmt::Write;
+
+/// the shading language to emit
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Lang {
+    Glsl,
+    Wgsl
+}
+
+/// where the value of an input variable comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binding {
+    /// a `float` uniform (location in GLSL, binding in WGSL)
+    Uniform(u32),
+    /// a storage buffer with one `float` per invocation
+    Storage(u32),
+    /// a fragment shader input at the given location (not available in compute shaders)
+    Attribute(u32),
+    /// a component (0 = x, 1 = y, 2 = z) of the global invocation id
+    Invocation(u8),
+    /// any expression in the target language
+    Expr(String)
+}
+
+/// maps the variables of an expression to shader inputs
+#[derive(Debug, Clone)]
+pub struct ShaderConfig {
+    pub lang: Lang,
+    pub inputs: Vec<(String, Binding)>,
+    /// binding of the storage buffer the results are written to (compute shaders only)
+    pub output: u32,
+    /// bind group of all resources (WGSL only)
+    pub group: u32,
+    pub workgroup_size: u32,
+}
+impl ShaderConfig {
+    pub fn new(lang: Lang) -> ShaderConfig {
+        ShaderConfig {
+            lang,
+            inputs: vec![],
+            output: 0,
+            group: 0,
+            workgroup_size: 64
+        }
+    }
+    pub fn input(mut self, name: &str, binding: Binding) -> ShaderConfig {
+        self.inputs.push((name.into(), binding));
+        self
+    }
+    pub fn output(mut self, binding: u32) -> ShaderConfig {
+        self.output = binding;
+        self
+    }
+    pub fn group(mut self, group: u32) -> ShaderConfig {
+        self.group = group;
+        self
+    }
+    pub fn workgroup_size(mut self, size: u32) -> ShaderConfig {
+        self.workgroup_size = size;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    Compute,
+    Fragment
+}
+
+pub(crate) struct Shader<'a> {
+    config: &'a ShaderConfig,
+    decl: String,
+    stored: usize,
+}
+
+impl<'a> Shader<'a> {
+    fn new(config: &'a ShaderConfig) -> Shader<'a> {
+        Shader {
+            config,
+            decl: String::new(),
+            stored: 0
+        }
+    }
+    fn join(&self, mut parts: Vec<String>, op: &str) -> String {
+        match parts.len() {
+            1 => parts.pop().unwrap(),
+            _ => format!("({})", parts.join(op))
+        }
+    }
+}
+
+impl<'a> Vm for Shader<'a> {
+    type Var = String;
+    type Storage = String;
+
+    fn make_int(&mut self, i: i64) -> Self::Var {
+        float_literal(i as f64, "")
+    }
+    fn make_const(&mut self, x: f64) -> Self::Var {
+        float_literal(x, "")
+    }
+    fn make_source(&mut self, name: &str) -> Self::Var {
+        let binding = &self.config.inputs.iter().find(|&&(ref n, _)| n == name).expect("undefined input").1;
+        match (binding, self.config.lang) {
+            (&Binding::Storage(_), _) => format!("{}[index]", name),
+            (&Binding::Invocation(c), Lang::Glsl) => format!("float(gl_GlobalInvocationID.{})", component(c)),
+            (&Binding::Invocation(c), Lang::Wgsl) => format!("f32(id.{})", component(c)),
+            (&Binding::Expr(ref e), _) => e.clone(),
+            (_, _) => name.into()
+        }
+    }
+    fn make_sum(&mut self, parts: Vec<Self::Var>) -> Self::Var {
+        self.join(parts, " + ")
+    }
+    fn make_product(&mut self, parts: Vec<Self::Var>) -> Self::Var {
+        self.join(parts, " * ")
+    }
+    fn store(&mut self, var: &mut Self::Var, _uses: usize) -> Self::Storage {
+        let name = format!("storage_{}", self.stored);
+        self.stored += 1;
+        let var = mem::replace(var, self.load(&name));
+        let keyword = match self.config.lang {
+            Lang::Glsl => "float",
+            Lang::Wgsl => "let"
+        };
+        writeln!(self.decl, "    {} {} = {};", keyword, name, var).unwrap();
+        name
+    }
+    fn load(&mut self, name: &Self::Storage) -> Self::Var {
+        name.clone()
+    }
+    fn round(&mut self, x: Self::Var, mode: Round) -> Self::Var {
+        match mode {
+            Round::Up => format!("ceil({})", x),
+            Round::Down => format!("floor({})", x),
+        }
+    }
+    fn div(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        format!("({} / {})", a, b)
+    }
+    fn inv(&mut self, a: Self::Var) -> Self::Var {
+        format!("(1.0 / {})", a)
+    }
+    fn sqrt(&mut self, x: Self::Var) -> Self::Var {
+        format!("sqrt({})", x)
+    }
+    fn sin(&mut self, x: Self::Var) -> Self::Var {
+        format!("sin({})", x)
+    }
+    fn cos(&mut self, x: Self::Var) -> Self::Var {
+        format!("cos({})", x)
+    }
+    fn tan(&mut self, x: Self::Var) -> Self::Var {
+        format!("tan({})", x)
+    }
+    fn asin(&mut self, x: Self::Var) -> Self::Var {
+        format!("asin({})", x)
+    }
+    fn acos(&mut self, x: Self::Var) -> Self::Var {
+        format!("acos({})", x)
+    }
+    fn atan(&mut self, x: Self::Var) -> Self::Var {
+        format!("atan({})", x)
+    }
+    fn sinh(&mut self, x: Self::Var) -> Self::Var {
+        format!("sinh({})", x)
+    }
+    fn cosh(&mut self, x: Self::Var) -> Self::Var {
+        format!("cosh({})", x)
+    }
+    fn tanh(&mut self, x: Self::Var) -> Self::Var {
+        format!("tanh({})", x)
+    }
+    fn asinh(&mut self, x: Self::Var) -> Self::Var {
+        format!("asinh({})", x)
+    }
+    fn acosh(&mut self, x: Self::Var) -> Self::Var {
+        format!("acosh({})", x)
+    }
+    fn atanh(&mut self, x: Self::Var) -> Self::Var {
+        format!("atanh({})", x)
+    }
+    fn exp(&mut self, x: Self::Var) -> Self::Var {
+        format!("exp({})", x)
+    }
+    fn log(&mut self, x: Self::Var) -> Self::Var {
+        format!("log({})", x)
+    }
+    fn abs(&mut self, x: Self::Var) -> Self::Var {
+        format!("abs({})", x)
+    }
+    fn sign(&mut self, x: Self::Var) -> Self::Var {
+        format!("sign({})", x)
+    }
+    fn min(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        format!("min({}, {})", a, b)
+    }
+    fn max(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        format!("max({}, {})", a, b)
+    }
+    fn atan2(&mut self, y: Self::Var, x: Self::Var) -> Self::Var {
+        match self.config.lang {
+            Lang::Glsl => format!("atan({}, {})", y, x),
+            Lang::Wgsl => format!("atan2({}, {})", y, x)
+        }
+    }
+    fn step_at(&mut self, at: Self::Var, x: Self::Var) -> Self::Var {
+        format!("step({}, {})", at, x)
+    }
+}
+
+fn component(c: u8) -> char {
+    match c {
+        0 => 'x',
+        1 => 'y',
+        2 => 'z',
+        _ => panic!("invocation id has only three components")
+    }
+}
+
+fn vector(lang: Lang, parts: &[String]) -> String {
+    match (parts.len(), lang) {
+        (1, _) => parts[0].clone(),
+        (n, Lang::Glsl) => format!("vec{}({})", n, parts.join(", ")),
+        (n, Lang::Wgsl) => format!("vec{}<f32>({})", n, parts.join(", ")),
+    }
+}
+
+fn vector_type(lang: Lang, n: usize) -> String {
+    match (n, lang) {
+        (1, Lang::Glsl) => "float".into(),
+        (1, Lang::Wgsl) => "f32".into(),
+        (n, Lang::Glsl) => format!("vec{}", n),
+        (n, Lang::Wgsl) => format!("vec{}<f32>", n),
+    }
+}
+
+/// compile `node` and return the declarations and the output expressions
+pub(crate) fn body(config: &ShaderConfig, node: &NodeRc) -> Result<(String, Vec<String>), Error> {
+    let parts = match **node {
+        Node::Tuple(ref parts) => parts.clone(),
+        _ => vec![node.clone()]
+    };
+    if parts.len() > 4 {
+        return Err(Error::Other(format!("shaders can return at most 4 values, not {}", parts.len())));
+    }
+    let names: Vec<&str> = config.inputs.iter().map(|&(ref n, _)| n.as_str()).collect();
+    let mut shader = Shader::new(config);
+    let outputs = Compiler::compile(&mut shader, &parts, &names)?;
+    Ok((shader.decl, outputs))
+}
+
+fn declare_inputs(config: &ShaderConfig, stage: Stage) -> Result<String, Error> {
+    let mut s = String::new();
+    for &(ref name, ref binding) in config.inputs.iter() {
+        match (binding, config.lang, stage) {
+            (&Binding::Uniform(n), Lang::Glsl, Stage::Compute) =>
+                writeln!(s, "layout(location = {}) uniform float {};", n, name),
+            (&Binding::Uniform(_), Lang::Glsl, Stage::Fragment) =>
+                writeln!(s, "uniform float {};", name),
+            (&Binding::Uniform(n), Lang::Wgsl, _) =>
+                writeln!(s, "@group({}) @binding({}) var<uniform> {}: f32;", config.group, n, name),
+            (&Binding::Storage(n), Lang::Glsl, Stage::Compute) =>
+                writeln!(s, "layout(std430, binding = {}) readonly buffer Input_{} {{ float {}[]; }};", n, name, name),
+            (&Binding::Storage(n), Lang::Wgsl, Stage::Compute) =>
+                writeln!(s, "@group({}) @binding({}) var<storage, read> {}: array<f32>;", config.group, n, name),
+            (&Binding::Attribute(_), Lang::Glsl, Stage::Fragment) =>
+                writeln!(s, "in float {};", name),
+            (&Binding::Attribute(_), Lang::Wgsl, Stage::Fragment) => Ok(()), // function parameter
+            (&Binding::Storage(_), _, Stage::Fragment) |
+            (&Binding::Invocation(_), _, Stage::Fragment) =>
+                return Err(Error::Other(format!("'{}' can only be bound in a compute shader", name))),
+            (&Binding::Attribute(_), _, Stage::Compute) =>
+                return Err(Error::Other(format!("'{}' can only be bound in a fragment shader", name))),
+            (&Binding::Invocation(_), _, Stage::Compute) |
+            (&Binding::Expr(_), _, _) => Ok(()),
+        }.unwrap();
+    }
+    Ok(s)
+}
+
+/// Generate a compute shader that writes one value (or vector for tuples) per invocation
+/// into the storage buffer `config.output`.
+pub fn compute(config: &ShaderConfig, node: &NodeRc) -> Result<String, Error> {
+    let inputs = declare_inputs(config, Stage::Compute)?;
+    let (decl, outputs) = body(config, node)?;
+    let result = vector(config.lang, &outputs);
+    let result_type = vector_type(config.lang, outputs.len());
+
+    Ok(match config.lang {
+        Lang::Glsl => format!("\
+#version 430
+layout(local_size_x = {size}) in;
+{inputs}layout(std430, binding = {output}) writeonly buffer Output {{ {result_type} result[]; }};
+
+void main() {{
+    uint index = gl_GlobalInvocationID.x;
+    if (index >= result.length()) {{
+        return;
+    }}
+{decl}    result[index] = {result};
+}}
+",
+            size=config.workgroup_size, inputs=inputs, output=config.output,
+            result_type=result_type, decl=decl, result=result
+        ),
+        Lang::Wgsl => format!("\
+{inputs}@group({group}) @binding({output}) var<storage, read_write> result: array<{result_type}>;
+
+@compute @workgroup_size({size})
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {{
+    let index = id.x;
+    if (index >= arrayLength(&result)) {{
+        return;
+    }}
+{decl}    result[index] = {result};
+}}
+",
+            inputs=inputs, group=config.group, output=config.output, result_type=result_type,
+            size=config.workgroup_size, decl=decl, result=result
+        )
+    })
+}
+
+/// Generate a fragment shader that writes the result as color.
+///
+/// A single value is used as gray level, tuples fill the color channels (alpha defaults to 1).
+pub fn fragment(config: &ShaderConfig, node: &NodeRc) -> Result<String, Error> {
+    let inputs = declare_inputs(config, Stage::Fragment)?;
+    let (decl, mut outputs) = body(config, node)?;
+    let color = match (outputs.len(), config.lang) {
+        (1, Lang::Glsl) => format!("vec4(vec3({}), 1.0)", outputs[0]),
+        (1, Lang::Wgsl) => format!("vec4<f32>(vec3<f32>({}), 1.0)", outputs[0]),
+        (n, lang) => {
+            outputs.extend((n .. 4).map(|i| if i == 3 { "1.0" } else { "0.0" }.into()));
+            vector(lang, &outputs)
+        }
+    };
+
+    Ok(match config.lang {
+        Lang::Glsl => format!("\
+#version 330
+{inputs}out vec4 final_col;
+
+void main() {{
+{decl}    final_col = {color};
+}}
+",
+            inputs=inputs, decl=decl, color=color
+        ),
+        Lang::Wgsl => {
+            let params = config.inputs.iter().filter_map(|&(ref name, ref binding)| match *binding {
+                Binding::Attribute(n) => Some(format!("@location({}) {}: f32", n, name)),
+                _ => None
+            }).join(", ");
+            format!("\
+{inputs}@fragment
+fn main({params}) -> @location(0) vec4<f32> {{
+{decl}    return {color};
+}}
+",
+                inputs=inputs, params=params, decl=decl, color=color
+            )
+        }
+    })
+}
@@ -1,14 +1,12 @@
-extern crate memmap;
-
 #[cfg(feature="codegen")]
 use quote::{Tokens};
 #[cfg(feature="codegen")]
 use proc_macro2::{Term, Span};
 
 use std::fmt;
-use compiler::Compiler;
-use vm::{Vm, Round, Cmp};
-use node::NodeRc;
+use crate::compiler::Compiler;
+use crate::vm::{Vm, Round, Cmp};
+use crate::node::{NodeRc, Node};
 
 impl fmt::Display for Reg {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -40,6 +38,7 @@ pub enum Instr {
     Mul(Reg, Reg, Source),
     Div(Reg, Reg, Source),
     Inv(Reg, Source),
+    Sqrt(Reg, Source),
     Round(Reg, Source, Round),
     Load(Reg, Source),
     MaskMove(Reg, Reg, Source), // conditinal load from const i
@@ -51,7 +50,10 @@ pub struct SimdAsm {
     pub registers: [usize; 16],
     pub used: u8,
     pub inputs: Vec<String>,
-    pub consts: Vec<f32>
+    pub consts: Vec<f32>,
+    pub origin: Vec<Option<usize>>, // for every instruction the index into `nodes` it was generated for
+    pub nodes: Vec<String>,
+    stack: Vec<usize>
 }
 impl SimdAsm {
     pub fn new() -> SimdAsm {
@@ -61,6 +63,9 @@ impl SimdAsm {
             inputs: vec![],
             consts: vec![],
             registers: [0; 16],
+            origin: vec![],
+            nodes: vec![],
+            stack: vec![]
         }
     }
     pub fn alloc_uses(&mut self, uses: usize) -> Reg {
@@ -86,8 +91,9 @@ impl SimdAsm {
     pub fn push(&mut self, i: Instr) {
         println!("{:40} {:?}", format!("{:?}", i), self.registers);
         self.instr.push(i);
+        self.origin.push(self.stack.last().cloned());
     }
-    fn fold(&mut self, mut parts: Vec<Source>, f: &Fn(Reg, Reg, Source) -> Instr) -> Source {
+    fn fold(&mut self, mut parts: Vec<Source>, f: &dyn Fn(Reg, Reg, Source) -> Instr) -> Source {
         // get a non-const source
         let (skip, mut r_last) = parts.iter().enumerate().filter_map(|(i, p)| {
             match *p {
@@ -151,6 +157,13 @@ impl Vm for SimdAsm {
     fn load(&mut self, storage: &Self::Storage) -> Self::Var {
         *storage
     }
+    fn enter(&mut self, node: &Node) {
+        self.stack.push(self.nodes.len());
+        self.nodes.push(node.to_string());
+    }
+    fn leave(&mut self, _node: &Node) {
+        self.stack.pop();
+    }
     fn round(&mut self, x: Self::Var, mode: Round) -> Self::Var {
         self.drop_s(x);
         let y = self.alloc();
@@ -159,10 +172,12 @@ impl Vm for SimdAsm {
     }
     fn step_at(&mut self, at: Self::Var, x: Self::Var) -> Self::Var {
         self.drop_s(at);
+        self.drop_s(x);
         let mask = self.alloc();
+        // mask = x >= at
         match (at, x) {
-            (Source::Reg(a), s) => self.push(Instr::Cmp(mask, a, s, Cmp::GE)),
-            (s, Source::Reg(b)) => self.push(Instr::Cmp(mask, b, s, Cmp::LT)),
+            (s, Source::Reg(b)) => self.push(Instr::Cmp(mask, b, s, Cmp::GE)),
+            (Source::Reg(a), s) => self.push(Instr::Cmp(mask, a, s, Cmp::LE)),
             (_, _) => panic!("can't use two memory sources")
         }
         self.drop(mask);
@@ -194,6 +209,12 @@ impl Vm for SimdAsm {
         self.push(Instr::Inv(r, a));
         Source::Reg(r)
     }
+    fn sqrt(&mut self, a: Self::Var) -> Self::Var {
+        self.drop_s(a);
+        let r = self.alloc();
+        self.push(Instr::Sqrt(r, a));
+        Source::Reg(r)
+    }
 }
 
 #[cfg(feature="codegen")]
@@ -236,6 +257,7 @@ pub fn simd_asm(nodes: &[NodeRc], vars: &[&str]) -> Tokens
             Instr::Mul(r0, r1, s)            => writeln!(lines, "\tvmulps {}, {}, {}", r0, r1, s),
             Instr::Div(r0, r1, s)            => writeln!(lines, "\tvdivps {}, {}, {}", r0, r1, s),
             Instr::Inv(r0, s)                => writeln!(lines, "\tvrcpps {}, {}", r0, s),
+            Instr::Sqrt(r0, s)               => writeln!(lines, "\tvsqrtps {}, {}", r0, s),
             Instr::Round(r0, s, Round::Up)   => writeln!(lines, "\tvroundps {}, {}, 0x0A", r0, s),
             Instr::Round(r0, s, Round::Down) => writeln!(lines, "\tvroundps {}, {}, 0x09", r0, s),
             Instr::Load(r0, s)               => writeln!(lines, "\tvmovdqa {}, {}", r0, s),
@@ -5,6 +5,9 @@ use quote::{Tokens};
 use std::mem;
 use std::iter::once;
 use proc_macro2::{Term, Span};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
 
 struct Syn {
     tokens: Tokens,
@@ -78,6 +81,9 @@ impl Vm for Syn {
     fn inv(&mut self, a: Self::Var) -> Self::Var {
         quote! { <T as Real>::inv(#a) }
     }
+    fn sqrt(&mut self, x: Self::Var) -> Self::Var {
+        quote! { T::sqrt(#x) }
+    }
     fn step_at(&mut self, at: Self::Var, x: Self::Var) -> Self::Var {
         quote! {
             #x.ge(#at).select(f32x8::splat(1.0), f32::splat(0.0))
@@ -93,13 +99,20 @@ impl Vm for Syn {
     */
 }
 
+/// generate the statements and the final expression that compute `nodes` from `args`
+fn body(nodes: &[NodeRc], args: &[&str]) -> Result<(Syn, Vec<Tokens>), Error> {
+    let mut syn = Syn::new();
+    let outputs = Compiler::compile(&mut syn, nodes, args)?;
+    Ok((syn, outputs))
+}
+
 pub fn syn(node: NodeRc) -> Tokens {
     let mut syn = Syn::new();
     let inner = Compiler::run(&mut syn, &node).unwrap();
     let store = syn.tokens;
     let args = &syn.inputs;
 
-    let out = quote! {
+    quote! {
         #[allow(unused_imports)]
         {
             extern crate math_traits;
@@ -112,19 +125,72 @@ pub fn syn(node: NodeRc) -> Tokens {
             }
             f(#(#args),*)
         }
+    }
+}
+
+/// Generate `pub fn name<T: Real>(args…) -> T`.
+///
+/// The arguments appear in the order given by `args`, not in the order they are used.
+/// A `Node::Tuple` turns into a function returning a tuple `(T, T, …)`.
+pub fn function(name: &str, node: &NodeRc, args: &[&str]) -> Result<Tokens, Error> {
+    let name = Term::new(name, Span::call_site());
+    let (syn, outputs, ret) = match **node {
+        Node::Tuple(ref parts) => {
+            let (syn, outputs) = body(parts, args)?;
+            let types = parts.iter().map(|_| quote! { T });
+            let ret = quote! { ( #(#types),* ) };
+            (syn, quote! { ( #(#outputs),* ) }, ret)
+        },
+        _ => {
+            let (syn, mut outputs) = body(&[node.clone()], args)?;
+            (syn, outputs.pop().unwrap(), quote! { T })
+        }
+    };
+    let store = syn.tokens;
+    let args = &syn.inputs;
+
+    Ok(quote! {
+        #[allow(unused_variables, unused_parens)]
+        pub fn #name<T: Real>(#(#args: T),*) -> #ret {
+            #store
+            #outputs
+        }
+    })
+}
+
+/// Generate a complete module containing one function per `(name, expression, arguments)`.
+///
+/// The output only depends on `math_traits`, so it can be `include!`d by a crate
+/// that does not depend on bullet at all.
+pub fn module(functions: &[(&str, NodeRc, &[&str])]) -> Result<Tokens, Error> {
+    let mut tokens = quote! {
+        #[allow(unused_imports)]
+        use math_traits::Real;
+        #[allow(unused_imports)]
+        use std::ops::*;
     };
-    {
-        use std::fs::OpenOptions;
-        use std::io::Write;
-        writeln!(
-            OpenOptions::new()
-                .write(true)
-                .append(true)
-                .create(true)
-                .open("/tmp/out.rs")
-                .unwrap(),
-            "{}", out
-        ).unwrap();
-    }
-    out
+    for &(name, ref node, args) in functions {
+        tokens.append_all(once(function(name, node, args)?));
+    }
+    Ok(tokens)
+}
+
+/// Write the output of `module` to `path`.
+///
+/// Meant to be called from a `build.rs`:
+///
+/// ```ignore
+/// let b = Builder::new();
+/// let f = b.parse("sin(x)^2 * y")?;
+/// let df = diff(&b, &f, "x")?;
+/// let out = Path::new(&env::var("OUT_DIR").unwrap()).join("math.rs");
+/// write_module(out, &[("f", f, &["x", "y"]), ("df_dx", df, &["x", "y"])])?;
+/// ```
+/// and then `include!(concat!(env!("OUT_DIR"), "/math.rs"));` in the crate.
+pub fn write_module<P: AsRef<Path>>(path: P, functions: &[(&str, NodeRc, &[&str])]) -> Result<(), Error> {
+    let tokens = module(functions)?;
+    let mut file = File::create(path)?;
+    writeln!(file, "// generated by bullet. do not edit.")?;
+    writeln!(file, "{}", tokens)?;
+    Ok(())
 }
@@ -1,26 +1,125 @@
-use parity_wasm::elements::{
-    Instructions,
-    Instruction, Local, ValueType,
-    Module
+use wasm_encoder::{
+    Instruction, ValType, BlockType, MemArg, MemoryType, ExportKind,
+    Function, TypeSection, FunctionSection, MemorySection, ExportSection, CodeSection, Module
 };
-use parity_wasm::builder;
 
-use vm::{Vm, Round};
-use compiler::Compiler;
-use node::NodeRc;
-use error::Error;
+use crate::vm::{Vm, Round};
+use crate::compiler::Compiler;
+use crate::node::{Node, NodeRc};
+use crate::error::Error;
 
-pub struct Wasm<'a> {
-    instructions: Vec<Instruction>,
-    inputs: &'a [&'a str],
-    locals: u32,
+/// how many values are processed at once, and in which precision
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Lanes {
+    /// one `f64` at a time
+    Scalar,
+    /// one `f32` at a time
+    F32,
+    /// two `f64` in a `v128` (needs wasm SIMD)
+    #[cfg(feature="wasm_simd")]
+    F64x2,
+    /// four `f32` in a `v128` (needs wasm SIMD)
+    #[cfg(feature="wasm_simd")]
+    F32x4
+}
+impl Lanes {
+    fn count(self) -> i32 {
+        match self {
+            Lanes::Scalar | Lanes::F32 => 1,
+            #[cfg(feature="wasm_simd")]
+            Lanes::F64x2 => 2,
+            #[cfg(feature="wasm_simd")]
+            Lanes::F32x4 => 4
+        }
+    }
+    fn value_type(self) -> ValType {
+        match self {
+            Lanes::Scalar => ValType::F64,
+            Lanes::F32 => ValType::F32,
+            #[cfg(feature="wasm_simd")]
+            Lanes::F64x2 | Lanes::F32x4 => ValType::V128
+        }
+    }
+    /// log2 of the size of one array element in bytes
+    fn shift(self) -> i32 {
+        match self {
+            Lanes::Scalar => 3,
+            Lanes::F32 => 2,
+            #[cfg(feature="wasm_simd")]
+            Lanes::F64x2 => 3,
+            #[cfg(feature="wasm_simd")]
+            Lanes::F32x4 => 2
+        }
+    }
+    /// the lanes processing the remainder
+    fn scalar(self) -> Lanes {
+        match self {
+            Lanes::Scalar | Lanes::F32 => self,
+            #[cfg(feature="wasm_simd")]
+            Lanes::F64x2 => Lanes::Scalar,
+            #[cfg(feature="wasm_simd")]
+            Lanes::F32x4 => Lanes::F32
+        }
+    }
+}
+
+fn mem_arg(align: u32) -> MemArg {
+    MemArg { offset: 0, align, memory_index: 0 }
+}
+
+/// a function with its signature
+struct Definition {
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+    body: Function
+}
+
+/// Builds the body of one wasm function.
+///
+/// Parameters come first in the local index space, followed by `locals`.
+pub struct Wasm {
+    instructions: Vec<Instruction<'static>>,
+    sources: Vec<(String, u32)>,
+    num_params: u32,
+    locals: Vec<ValType>,
+    lanes: Lanes
 }
 
 #[derive(Debug)]
 pub struct LocalVar(u32);
 
-impl<'a> Wasm<'a> {
-    fn fold(&mut self, parts: Vec<Vec<Instruction>>, instruction: Instruction) -> Vec<Instruction> {
+#[derive(Debug, Copy, Clone)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    Floor,
+    Ceil,
+    Ge,
+    Load,
+    Store
+}
+
+impl Wasm {
+    fn new(params: u32, lanes: Lanes) -> Wasm {
+        Wasm {
+            instructions: vec![],
+            sources: vec![],
+            num_params: params,
+            locals: vec![],
+            lanes
+        }
+    }
+    fn alloc(&mut self, t: ValType) -> u32 {
+        self.locals.push(t);
+        self.num_params + self.locals.len() as u32 - 1
+    }
+    fn fold(&mut self, parts: Vec<Vec<Instruction<'static>>>, instruction: Instruction<'static>) -> Vec<Instruction<'static>> {
         let mut parts = parts.into_iter();
         let mut instr = parts.next().unwrap();
         for mut other in parts {
@@ -29,95 +128,324 @@ impl<'a> Wasm<'a> {
         }
         instr
     }
+    fn binary(&self, mut a: Vec<Instruction<'static>>, mut b: Vec<Instruction<'static>>, op: Instruction<'static>) -> Vec<Instruction<'static>> {
+        a.append(&mut b);
+        a.push(op);
+        a
+    }
+    fn op(&self, op: Op) -> Instruction<'static> {
+        match self.lanes {
+            Lanes::Scalar => match op {
+                Op::Add => Instruction::F64Add,
+                Op::Sub => Instruction::F64Sub,
+                Op::Mul => Instruction::F64Mul,
+                Op::Div => Instruction::F64Div,
+                Op::Sqrt => Instruction::F64Sqrt,
+                Op::Abs => Instruction::F64Abs,
+                Op::Min => Instruction::F64Min,
+                Op::Max => Instruction::F64Max,
+                Op::Floor => Instruction::F64Floor,
+                Op::Ceil => Instruction::F64Ceil,
+                Op::Ge => Instruction::F64Ge,
+                Op::Load => Instruction::F64Load(mem_arg(3)),
+                Op::Store => Instruction::F64Store(mem_arg(3)),
+            },
+            Lanes::F32 => match op {
+                Op::Add => Instruction::F32Add,
+                Op::Sub => Instruction::F32Sub,
+                Op::Mul => Instruction::F32Mul,
+                Op::Div => Instruction::F32Div,
+                Op::Sqrt => Instruction::F32Sqrt,
+                Op::Abs => Instruction::F32Abs,
+                Op::Min => Instruction::F32Min,
+                Op::Max => Instruction::F32Max,
+                Op::Floor => Instruction::F32Floor,
+                Op::Ceil => Instruction::F32Ceil,
+                Op::Ge => Instruction::F32Ge,
+                Op::Load => Instruction::F32Load(mem_arg(2)),
+                Op::Store => Instruction::F32Store(mem_arg(2)),
+            },
+            #[cfg(feature="wasm_simd")]
+            Lanes::F64x2 => match op {
+                Op::Add => Instruction::F64x2Add,
+                Op::Sub => Instruction::F64x2Sub,
+                Op::Mul => Instruction::F64x2Mul,
+                Op::Div => Instruction::F64x2Div,
+                Op::Sqrt => Instruction::F64x2Sqrt,
+                Op::Abs => Instruction::F64x2Abs,
+                Op::Min => Instruction::F64x2Min,
+                Op::Max => Instruction::F64x2Max,
+                Op::Floor => Instruction::F64x2Floor,
+                Op::Ceil => Instruction::F64x2Ceil,
+                Op::Ge => Instruction::F64x2Ge,
+                Op::Load => Instruction::V128Load(mem_arg(3)),
+                Op::Store => Instruction::V128Store(mem_arg(3)),
+            },
+            #[cfg(feature="wasm_simd")]
+            Lanes::F32x4 => match op {
+                Op::Add => Instruction::F32x4Add,
+                Op::Sub => Instruction::F32x4Sub,
+                Op::Mul => Instruction::F32x4Mul,
+                Op::Div => Instruction::F32x4Div,
+                Op::Sqrt => Instruction::F32x4Sqrt,
+                Op::Abs => Instruction::F32x4Abs,
+                Op::Min => Instruction::F32x4Min,
+                Op::Max => Instruction::F32x4Max,
+                Op::Floor => Instruction::F32x4Floor,
+                Op::Ceil => Instruction::F32x4Ceil,
+                Op::Ge => Instruction::F32x4Ge,
+                Op::Load => Instruction::V128Load(mem_arg(2)),
+                Op::Store => Instruction::V128Store(mem_arg(2)),
+            }
+        }
+    }
+    fn function(&self, params: Vec<ValType>, results: Vec<ValType>, body: Vec<Instruction<'static>>) -> Definition {
+        let mut f = Function::new_with_locals_types(self.locals.iter().cloned());
+        for instr in &body {
+            f.instruction(instr);
+        }
+        Definition { params, results, body: f }
+    }
 
-    fn to_module(self) -> Module {
-        builder::module()
-            .function()
-                .signature()
-                    .with_params(vec![ValueType::F64; self.inputs.len()])
-                    .with_return_type(Some(ValueType::F64))
-                    .build()
-                .body()
-                    .with_locals(vec![Local::new(self.locals, ValueType::F64)])
-                    .with_instructions(Instructions::new(self.instructions))
-                    .build()
-                .build()
-            .export()
-                .field("f")
-                .internal().func(0)
-                .build()
-            .build()
+    /// compile `nodes`, reading `vars` from the locals `sources`.
+    /// returns the instructions leaving the value of each node on the stack
+    fn generate(&mut self, nodes: &[NodeRc], vars: &[&str], sources: &[u32]) -> Result<Vec<Vec<Instruction<'static>>>, Error> {
+        self.sources = vars.iter().zip(sources.iter()).map(|(&name, &idx)| (name.to_owned(), idx)).collect();
+        Compiler::compile(self, nodes, vars)
     }
 
+    /// `fn(inputs…) -> f64`
+    fn scalar_function(node: &NodeRc, vars: &[&str]) -> Result<Definition, Error> {
+        let mut w = Wasm::new(vars.len() as u32, Lanes::Scalar);
+        let sources: Vec<u32> = (0 .. vars.len() as u32).collect();
+        let mut out = w.generate(&[node.clone()], vars, &sources)?;
+        let mut body = w.instructions.split_off(0);
+        body.append(&mut out[0]);
+        body.push(Instruction::End);
+
+        Ok(w.function(vec![ValType::F64; vars.len()], vec![ValType::F64], body))
+    }
+
+    /// `fn(n: i32, inputs: i32…, outputs: i32…)`
+    ///
+    /// The pointer arguments are byte offsets of arrays of length `n` in linear memory,
+    /// of `f32` with `Lanes::F32` and `Lanes::F32x4` and of `f64` otherwise.
+    fn map_function(nodes: &[NodeRc], vars: &[&str], lanes: Lanes) -> Result<Definition, Error> {
+        let num_params = 1 + vars.len() + nodes.len();
+        let mut w = Wasm::new(num_params as u32, lanes);
+        let i = w.alloc(ValType::I32);
+
+        let mut body = vec![];
+        if lanes != lanes.scalar() {
+            body.extend(w.map_loop(nodes, vars, i, lanes)?);
+        }
+        // the remaining elements, or all of them
+        body.extend(w.map_loop(nodes, vars, i, lanes.scalar())?);
+        body.push(Instruction::End);
+
+        Ok(w.function(vec![ValType::I32; num_params], vec![], body))
+    }
+
+    fn map_loop(&mut self, nodes: &[NodeRc], vars: &[&str], i: u32, lanes: Lanes) -> Result<Vec<Instruction<'static>>, Error> {
+        self.lanes = lanes;
+        self.instructions.clear();
+
+        let sources: Vec<u32> = vars.iter().map(|_| self.alloc(lanes.value_type())).collect();
+        let outputs = self.generate(nodes, vars, &sources)?;
+
+        // byte address of element i in the array at param `ptr`
+        let address = |ptr: u32| vec![
+            Instruction::LocalGet(ptr),
+            Instruction::LocalGet(i),
+            Instruction::I32Const(lanes.shift()),
+            Instruction::I32Shl,
+            Instruction::I32Add
+        ];
+
+        let mut body = vec![
+            Instruction::Block(BlockType::Empty),
+            Instruction::Loop(BlockType::Empty),
+            // break if i + lanes > n
+            Instruction::LocalGet(i),
+            Instruction::I32Const(lanes.count()),
+            Instruction::I32Add,
+            Instruction::LocalGet(0),
+            Instruction::I32GtU,
+            Instruction::BrIf(1),
+        ];
+        for (k, &local) in sources.iter().enumerate() {
+            body.extend(address(1 + k as u32));
+            body.push(self.op(Op::Load));
+            body.push(Instruction::LocalSet(local));
+        }
+        body.append(&mut self.instructions);
+        for (k, mut out) in outputs.into_iter().enumerate() {
+            body.extend(address(1 + (vars.len() + k) as u32));
+            body.append(&mut out);
+            body.push(self.op(Op::Store));
+        }
+        body.extend_from_slice(&[
+            Instruction::LocalGet(i),
+            Instruction::I32Const(lanes.count()),
+            Instruction::I32Add,
+            Instruction::LocalSet(i),
+            Instruction::Br(0),
+            Instruction::End,
+            Instruction::End
+        ]);
+        Ok(body)
+    }
+
+    /// encode a module with the given functions and exports, and a linear memory `memory` if `memory` is set
+    fn encode(definitions: &[Definition], exports: &[String], memory: bool) -> Vec<u8> {
+        let mut types = TypeSection::new();
+        let mut functions = FunctionSection::new();
+        let mut code = CodeSection::new();
+        for (idx, def) in definitions.iter().enumerate() {
+            types.ty().function(def.params.iter().cloned(), def.results.iter().cloned());
+            functions.function(idx as u32);
+            code.function(&def.body);
+        }
+
+        let mut memories = MemorySection::new();
+        let mut export = ExportSection::new();
+        if memory {
+            memories.memory(MemoryType { minimum: 1, maximum: None, memory64: false, shared: false, page_size_log2: None });
+            export.export("memory", ExportKind::Memory, 0);
+        }
+        for (idx, name) in exports.iter().enumerate() {
+            export.export(name, ExportKind::Func, idx as u32);
+        }
+
+        let mut module = Module::new();
+        module.section(&types);
+        module.section(&functions);
+        if memory {
+            module.section(&memories);
+        }
+        module.section(&export);
+        module.section(&code);
+        module.finish()
+    }
+
+    /// Build a module with one export `f(inputs…) -> f64`.
     pub fn compile(node: &NodeRc, inputs: &[&str]) -> Result<Vec<u8>, Error> {
-        let mut w =  Wasm {
-            instructions: vec![],
-            inputs: inputs.into(),
-            locals: 0
-        };
-        let mut instr = Compiler::run(&mut w, node)?;
-        w.instructions.append(&mut instr);
-        w.instructions.push(Instruction::End);
-        dbg!(&w.instructions);
-        Ok(w.to_module().to_bytes().expect("can't build module"))
+        let f = Wasm::scalar_function(node, inputs)?;
+        Ok(Wasm::encode(&[f], &["f".to_owned()], false))
+    }
+
+    /// Build a module exporting a linear memory `memory` and for each `(name, node)`:
+    ///
+    /// - `name(inputs…) -> f64` unless `node` is a tuple
+    /// - `name_map(n, inputs…, outputs…)`, which reads `n` values of each input from the arrays at the given
+    ///   byte offsets and writes one array per output (one per tuple element).
+    ///
+    /// With `Lanes::F64x2` or `Lanes::F32x4`, `name_map` processes two or four values at once, and the remainder one by one.
+    pub fn module(functions: &[(&str, NodeRc)], inputs: &[&str], lanes: Lanes) -> Result<Vec<u8>, Error> {
+        let mut definitions = vec![];
+        let mut exports = vec![];
+        for &(name, ref node) in functions {
+            let parts = match **node {
+                Node::Tuple(ref parts) => parts.clone(),
+                _ => {
+                    definitions.push(Wasm::scalar_function(node, inputs)?);
+                    exports.push(name.to_owned());
+                    vec![node.clone()]
+                }
+            };
+            definitions.push(Wasm::map_function(&parts, inputs, lanes)?);
+            exports.push(format!("{}_map", name));
+        }
+        Ok(Wasm::encode(&definitions, &exports, true))
     }
 }
-impl<'a> Vm for Wasm<'a> {
-    type Var = Vec<Instruction>;
+
+impl Vm for Wasm {
+    type Var = Vec<Instruction<'static>>;
     type Storage = LocalVar;
-    
+
     fn make_const(&mut self, c: f64) -> Self::Var {
-        vec![Instruction::F64Const(c.to_bits())]
+        match self.lanes {
+            Lanes::Scalar => vec![Instruction::F64Const(c)],
+            Lanes::F32 => vec![Instruction::F32Const(c as f32)],
+            #[cfg(feature="wasm_simd")]
+            Lanes::F64x2 => vec![Instruction::F64Const(c), Instruction::F64x2Splat],
+            #[cfg(feature="wasm_simd")]
+            Lanes::F32x4 => vec![Instruction::F32Const(c as f32), Instruction::F32x4Splat]
+        }
     }
     fn make_source(&mut self, name: &str) -> Self::Var {
-        let idx = self.inputs.iter().position(|&n| n == name).expect("input is not defined");
-        vec![Instruction::GetLocal(idx as u32)]
+        let idx = self.sources.iter().find(|s| s.0 == name).expect("input is not defined").1;
+        vec![Instruction::LocalGet(idx)]
     }
-    fn sub(&mut self, a: Self::Var, mut b: Self::Var) -> Self::Var {
-        let mut instr = a;
-        instr.append(&mut b);
-        instr.push(Instruction::F64Sub);
-        instr
+    fn sub(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        let op = self.op(Op::Sub);
+        self.binary(a, b, op)
     }
     fn make_sum(&mut self, parts: Vec<Self::Var>) -> Self::Var {
-        self.fold(parts, Instruction::F64Add)
+        let op = self.op(Op::Add);
+        self.fold(parts, op)
     }
     fn make_product(&mut self, parts: Vec<Self::Var>) -> Self::Var {
-        self.fold(parts, Instruction::F64Mul)
-    }
-    fn store(&mut self, var: &mut Self::Var, uses: usize) -> Self::Storage {
-        let idx = self.locals;
-        self.locals += 1;
-        self.instructions.extend_from_slice(&**var);
-        self.instructions.push(Instruction::SetLocal(idx));
-        
-        *var = vec![Instruction::GetLocal(idx)];
-        
+        let op = self.op(Op::Mul);
+        self.fold(parts, op)
+    }
+    fn store(&mut self, var: &mut Self::Var, _uses: usize) -> Self::Storage {
+        // the value is computed once, before the expression that uses it
+        let idx = self.alloc(self.lanes.value_type());
+        self.instructions.append(var);
+        self.instructions.push(Instruction::LocalSet(idx));
+
+        *var = vec![Instruction::LocalGet(idx)];
+
         LocalVar(idx)
     }
     fn load(&mut self, storage: &Self::Storage) -> Self::Var {
         let LocalVar(idx) = *storage;
-        vec![Instruction::GetLocal(idx)]
+        vec![Instruction::LocalGet(idx)]
     }
     fn round(&mut self, mut x: Self::Var, mode: Round) -> Self::Var {
-        let instr = match mode {
-            Round::Up => Instruction::F64Ceil,
-            Round::Down => Instruction::F64Floor
-        };
-        x.push(instr);
+        x.push(self.op(match mode {
+            Round::Up => Op::Ceil,
+            Round::Down => Op::Floor
+        }));
         x
     }
-    fn step_at(&mut self, mut at: Self::Var, mut x: Self::Var) -> Self::Var {
-        let mut instr = vec![Instruction::F64Const(0.0f64.to_bits()), Instruction::F64Const(1.0f64.to_bits())];
-        instr.append(&mut at);
-        instr.append(&mut x);
-        instr.extend_from_slice(&[Instruction::F64Ge, Instruction::Select]);
-        instr
+    fn step_at(&mut self, at: Self::Var, x: Self::Var) -> Self::Var {
+        let ge = self.op(Op::Ge);
+        let mut cmp = self.binary(x, at, ge);
+        if self.lanes == self.lanes.scalar() {
+            // select(1, 0, x >= at)
+            let mut instr = self.make_const(1.0);
+            instr.append(&mut self.make_const(0.0));
+            instr.append(&mut cmp);
+            instr.push(Instruction::Select);
+            instr
+        } else {
+            // the comparison sets all bits of the lanes where x >= at
+            cmp.append(&mut self.make_const(1.0));
+            cmp.push(Instruction::V128And);
+            cmp
+        }
     }
-    fn div(&mut self, a: Self::Var, mut b: Self::Var) -> Self::Var {
-        let mut instr = a;
-        instr.append(&mut b);
-        instr.push(Instruction::F64Div);
-        instr
+    fn div(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        let op = self.op(Op::Div);
+        self.binary(a, b, op)
+    }
+    fn sqrt(&mut self, mut x: Self::Var) -> Self::Var {
+        x.push(self.op(Op::Sqrt));
+        x
+    }
+    fn abs(&mut self, mut x: Self::Var) -> Self::Var {
+        x.push(self.op(Op::Abs));
+        x
+    }
+    fn min(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        let op = self.op(Op::Min);
+        self.binary(a, b, op)
+    }
+    fn max(&mut self, a: Self::Var, b: Self::Var) -> Self::Var {
+        let op = self.op(Op::Max);
+        self.binary(a, b, op)
     }
 }
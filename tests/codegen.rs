@@ -0,0 +1,32 @@
+#![cfg(feature="codegen")]
+extern crate bullet;
+use bullet::builder::Builder;
+use bullet::diff::diff;
+use bullet::vm::syn::module;
+
+#[test]
+fn codegen_module() {
+    let b = Builder::new();
+    let f = b.parse("x^2 y + sin(x)").unwrap();
+    let df = diff(&b, &f, "x").unwrap();
+    let g = b.parse("(x + y, x - y)").unwrap();
+
+    let code = module(&[
+        ("f", f, &["x", "y"]),
+        ("df_dx", df, &["x", "y"]),
+        ("g", g, &["x", "y"])
+    ]).unwrap().to_string();
+    println!("{}", code);
+
+    assert!(code.contains("use math_traits :: Real"));
+    assert!(code.contains("pub fn f <"));
+    assert!(code.contains("pub fn df_dx <"));
+    assert!(code.contains("pub fn g <"));
+}
+
+#[test]
+fn codegen_undefined_argument() {
+    let b = Builder::new();
+    let f = b.parse("x + z").unwrap();
+    assert!(module(&[("f", f, &["x"])]).is_err());
+}
@@ -0,0 +1,76 @@
+//! helpers shared by the integration tests
+#![allow(dead_code)]
+
+use bullet::builder::Builder;
+use bullet::eval::EvalContext;
+use bullet::node::NodeRc;
+use bullet::poly::Poly;
+use bullet::vm::{Vm, Round};
+use std::fmt::Display;
+
+/// evaluates directly and counts the operations
+#[derive(Default)]
+pub struct Count {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub muls: usize,
+    pub invs: usize
+}
+impl Vm for Count {
+    type Var = f64;
+    type Storage = f64;
+
+    fn make_const(&mut self, c: f64) -> f64 { c }
+    fn make_source(&mut self, name: &str) -> f64 {
+        match name {
+            "x" => self.x,
+            "y" => self.y,
+            "z" => self.z,
+            _ => panic!("unknown input {}", name)
+        }
+    }
+    fn store(&mut self, var: &mut f64, _uses: usize) -> f64 { *var }
+    fn load(&mut self, storage: &f64) -> f64 { *storage }
+    fn round(&mut self, a: f64, mode: Round) -> f64 {
+        match mode {
+            Round::Down => a.floor(),
+            Round::Up => a.ceil()
+        }
+    }
+    fn add(&mut self, a: f64, b: f64) -> f64 { a + b }
+    fn mul(&mut self, a: f64, b: f64) -> f64 {
+        self.muls += 1;
+        a * b
+    }
+    fn div(&mut self, a: f64, b: f64) -> f64 { a / b }
+    fn inv(&mut self, a: f64) -> f64 {
+        self.invs += 1;
+        1.0 / a
+    }
+    fn sqrt(&mut self, a: f64) -> f64 { a.sqrt() }
+    fn sin(&mut self, a: f64) -> f64 { a.sin() }
+    fn cos(&mut self, a: f64) -> f64 { a.cos() }
+    fn step_at(&mut self, at: f64, x: f64) -> f64 {
+        if x >= at { 1.0 } else { 0.0 }
+    }
+}
+
+pub fn poly(b: &Builder, expr: &str) -> Poly {
+    Poly::from_node(b.parse(expr).unwrap())
+}
+
+/// `a` and `b` agree up to rounding
+pub fn assert_close(a: f64, b: f64, what: impl Display) {
+    assert!((a - b).abs() < 1e-9 * (1.0 + a.abs()), "{}: {} != {}", what, a, b);
+}
+
+/// `n` and `m` evaluate to the same value at a few points in x and y
+pub fn assert_equivalent(expr: &str, n: &NodeRc, m: &NodeRc) {
+    let mut ctx = EvalContext::new();
+    for &(x, y) in &[(0.5, 2.0), (-1.5, 0.25), (3.0, -2.0)] {
+        ctx.set("x", x);
+        ctx.set("y", y);
+        assert_close(ctx.eval(n).unwrap(), ctx.eval(m).unwrap(), expr);
+    }
+}
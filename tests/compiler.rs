@@ -0,0 +1,118 @@
+extern crate bullet;
+mod common;
+use bullet::builder::Builder;
+use bullet::compiler::Compiler;
+use bullet::eval::EvalContext;
+use bullet::vm::{Vm, Round};
+use crate::common::{Count, assert_close};
+
+fn run(exprs: &[&str]) -> Count {
+    let b = Builder::new();
+    let nodes: Vec<_> = exprs.iter().map(|e| b.parse(e).unwrap()).collect();
+    let mut vm = Count { x: 1.5, y: -0.75, .. Count::default() };
+    let values = Compiler::compile(&mut vm, &nodes, &["x", "y"]).unwrap();
+
+    let mut ctx = EvalContext::new();
+    ctx.set("x", vm.x);
+    ctx.set("y", vm.y);
+    for (n, v) in nodes.iter().zip(values) {
+        assert_close(ctx.eval(n).unwrap(), v, n);
+    }
+    vm
+}
+
+#[test]
+fn shared_powers() {
+    // x², x³ = x² x, x⁵ = x³ x²
+    assert_eq!(run(&["x^2 + x^3 + x^5"]).muls, 3);
+    // across outputs: x³ = x² x, x⁵ = x³ x²
+    assert_eq!(run(&["x^3", "x^5"]).muls, 4);
+    // x⁴ = x² x²
+    assert_eq!(run(&["x^2", "x^4", "x^8"]).muls, 3);
+}
+
+#[test]
+fn shared_monomials() {
+    // x² y is computed once
+    assert_eq!(run(&["x^2 y + 1", "3 x^2 y"]).muls, 2 + 1);
+    // so is the inverse
+    assert_eq!(run(&["1 / x^2 + y", "2 / x^2"]).invs, 1);
+}
+
+#[test]
+fn default_lowerings() {
+    let b = Builder::new();
+    let exprs = [
+        "tan(x)", "asin(y)", "acos(y)", "atan(x)", "atan2(y, x)", "atan2(x, y)",
+        "sinh(y)", "cosh(x)", "tanh(y)", "asinh(y)", "acosh(x)", "atanh(y)", "exp(y)", "log(x)",
+        "abs(y)", "sign(y)", "floor(y)", "ceil(x)", "min(x, y)", "max(x, y)", "erf(x)", "erf(y)",
+        "cbrt(y)", "x^(1/3)", "y^(2/3)", "y^(-5/3)", "x^(5/6)",
+        "π x"
+    ];
+    let mut ctx = EvalContext::new();
+    for &(x, y) in &[(1.5, -0.75), (3.25, 0.5)] {
+        ctx.set("x", x);
+        ctx.set("y", y);
+        for e in exprs.iter() {
+            let n = b.parse(e).unwrap();
+            let mut vm = Count { x, y, .. Count::default() };
+            let v = Compiler::compile(&mut vm, &[n.clone()], &["x", "y"]).unwrap()[0];
+            let expected = ctx.eval(&n).unwrap();
+            // erf uses a rational approximation
+            let eps = if e.starts_with("erf") { 1e-6 } else { 1e-9 };
+            assert!((v - expected).abs() < eps * (1.0 + expected.abs()), "{}: {} != {}", e, v, expected);
+        }
+    }
+}
+
+#[test]
+fn default_atan() {
+    let b = Builder::new();
+    let mut ctx = EvalContext::new();
+    // close to the negative x-axis and far out on the x-axis
+    for &(x, y) in &[(-1.0, 1e-9), (-1.0, -1e-9), (-0.1, -3.0), (-2.0, 0.5), (1000.0, 10.0)] {
+        ctx.set("x", x);
+        ctx.set("y", y);
+        for e in &["atan2(y, x)", "atan(x)", "atan(y)"] {
+            let n = b.parse(e).unwrap();
+            let mut vm = Count { x, y, .. Count::default() };
+            let v = Compiler::compile(&mut vm, &[n.clone()], &["x", "y"]).unwrap()[0];
+            assert_close(ctx.eval(&n).unwrap(), v, e);
+        }
+    }
+}
+
+/// only the required operations, so the default square root is used
+struct NoSqrt {
+    x: f64
+}
+impl Vm for NoSqrt {
+    type Var = f64;
+    type Storage = f64;
+
+    fn make_const(&mut self, c: f64) -> f64 { c }
+    fn make_source(&mut self, _name: &str) -> f64 { self.x }
+    fn store(&mut self, var: &mut f64, _uses: usize) -> f64 { *var }
+    fn load(&mut self, storage: &f64) -> f64 { *storage }
+    fn round(&mut self, a: f64, _mode: Round) -> f64 { a }
+    fn add(&mut self, a: f64, b: f64) -> f64 { a + b }
+    fn mul(&mut self, a: f64, b: f64) -> f64 { a * b }
+    fn div(&mut self, a: f64, b: f64) -> f64 { a / b }
+    fn step_at(&mut self, at: f64, x: f64) -> f64 {
+        if x >= at { 1.0 } else { 0.0 }
+    }
+}
+
+#[test]
+fn default_sqrt() {
+    let b = Builder::new();
+    let mut ctx = EvalContext::new();
+    for &x in &[0.0, 1e-3, 0.5, 2.0, 1e3] {
+        ctx.set("x", x);
+        for e in &["sqrt(x)", "x^(3/2)", "abs(x - 1)"] {
+            let n = b.parse(e).unwrap();
+            let v = Compiler::compile(&mut NoSqrt { x }, &[n.clone()], &["x"]).unwrap()[0];
+            assert_close(ctx.eval(&n).unwrap(), v, e);
+        }
+    }
+}
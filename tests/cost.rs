@@ -0,0 +1,52 @@
+extern crate bullet;
+mod common;
+use bullet::builder::Builder;
+use bullet::cost::{cost, Cost};
+use crate::common::assert_equivalent;
+
+#[test]
+fn cost_counts() {
+    let b = Builder::new();
+    let n = b.parse("x^2 + x^3 + x^5").unwrap();
+    assert_eq!(cost(&[n]), Cost { add: 2, mul: 3, div: 0, transcendental: 0 });
+
+    let n = b.parse("sin(x) / y + 3 sin(x)").unwrap();
+    assert_eq!(cost(&[n]), Cost { add: 1, mul: 2, div: 1, transcendental: 1 });
+
+    // shared between outputs
+    let f = b.parse("x^2 y").unwrap();
+    let g = b.parse("2 x^2 y").unwrap();
+    assert_eq!(cost(&[f, g]).mul, 3);
+}
+
+fn check(expr: &str) {
+    let b = Builder::new();
+    let n = b.parse(expr).unwrap();
+    let m = b.optimize_for_eval(n.clone()).unwrap();
+    println!("{} -> {} ({} -> {})", n, m, cost(&[n.clone()]), cost(&[m.clone()]));
+    assert!(cost(&[m.clone()]).total() <= cost(&[n.clone()]).total());
+    assert_equivalent(expr, &n, &m);
+}
+
+#[test]
+fn optimize_for_eval() {
+    let b = Builder::new();
+
+    // Horner
+    let n = b.parse("x^4 + 2 x^3 + 3 x^2 + 4 x + 5").unwrap();
+    let m = b.optimize_for_eval(n.clone()).unwrap();
+    assert!(cost(&[m]).total() < cost(&[n]).total());
+
+    // factored
+    let n = b.parse("x^3 y + x^3 + x^3 y^2").unwrap();
+    let m = b.optimize_for_eval(n.clone()).unwrap();
+    assert!(cost(&[m]).mul < cost(&[n]).mul);
+
+    // addition chain: x² x³ x⁶ x¹² x¹⁵ instead of pow_n
+    let n = b.parse("x^15").unwrap();
+    assert_eq!(cost(&[b.optimize_for_eval(n).unwrap()]).mul, 5);
+
+    for expr in &["x^4 + 2 x^3 + 3 x^2 + 4 x + 5", "x^3 y + x^3 + x^3 y^2", "x^15 + y", "sin(x^2 + 2 x + 1) * y", "(x^9, y^6 + y^3)"] {
+        check(expr);
+    }
+}
@@ -19,3 +19,13 @@ fn expr() {
         }
     }
 }
+
+#[test]
+fn term_order() {
+    let b = Builder::new();
+    let a = b.parse("1 + y + x y + x^2").unwrap();
+    let c = b.parse("x^2 + 1 + y + y x").unwrap();
+    assert_eq!(a, c);
+    assert_eq!(a.to_string(), "x² + x y + y + 1");
+    assert_eq!(b.parse("z^2 + y z + x z + y^2 + x^2").unwrap().to_string(), "x² + y² + x z + y z + z²");
+}
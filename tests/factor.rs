@@ -0,0 +1,71 @@
+extern crate bullet;
+mod common;
+use bullet::builder::Builder;
+use bullet::eval::EvalContext;
+use bullet::factor::factor;
+use bullet::numbers::Rational;
+use crate::common::{poly, assert_equivalent};
+
+/// factor `expr` and compare with `unit` and the expected factors
+fn check(expr: &str, unit: Rational, expected: &[(&str, u32)]) {
+    let b = Builder::new();
+    let f = factor(&poly(&b, expr)).unwrap();
+    let mut expected: Vec<_> = expected.iter().map(|&(e, k)| (poly(&b, e), k)).collect();
+    expected.sort();
+    assert_eq!(f.unit, unit, "unit of {}", expr);
+    assert_eq!(f.factors, expected, "factors of {}", expr);
+}
+
+#[test]
+fn univariate() {
+    let one = Rational::from(1);
+    check("x^2 - 1", one.clone(), &[("x - 1", 1), ("x + 1", 1)]);
+    check("2 x^3 - 2 x", 2.into(), &[("x", 1), ("x - 1", 1), ("x + 1", 1)]);
+    check("(x + 1)^3 (x - 2)^2", one.clone(), &[("x + 1", 3), ("x - 2", 2)]);
+    check("x^2 / 4 - 1 / 9", Rational::new(1.into(), 36.into()), &[("3 x - 2", 1), ("3 x + 2", 1)]);
+    check("6 x^2 + 5 x + 1", one.clone(), &[("2 x + 1", 1), ("3 x + 1", 1)]);
+
+    // splits into more factors modulo every prime than over the rationals
+    check("x^4 + 1", one.clone(), &[("x^4 + 1", 1)]);
+    check("x^4 + 4", one.clone(), &[("x^2 - 2 x + 2", 1), ("x^2 + 2 x + 2", 1)]);
+    check("x^6 - 1", one.clone(), &[("x - 1", 1), ("x + 1", 1), ("x^2 + x + 1", 1), ("x^2 - x + 1", 1)]);
+    check("-3", Rational::from(-3), &[]);
+}
+
+#[test]
+fn multivariate() {
+    let one = Rational::from(1);
+    check("x^2 - y^2", one.clone(), &[("x - y", 1), ("x + y", 1)]);
+    check("x^2 y + x y^2 + x y", one.clone(), &[("x", 1), ("y", 1), ("x + y + 1", 1)]);
+    check("x^3 - y^3", one.clone(), &[("x - y", 1), ("x^2 + x y + y^2", 1)]);
+    check("(x y + 1) (x + y)^2", one.clone(), &[("x y + 1", 1), ("x + y", 2)]);
+    check("(x + z) (y - z) (x y z + 2)", one.clone(), &[("x + z", 1), ("y - z", 1), ("x y z + 2", 1)]);
+    check("-2 sin(x)^2 + 2", Rational::from(-2), &[("sin(x) - 1", 1), ("sin(x) + 1", 1)]);
+}
+
+#[test]
+fn builder_factor() {
+    let b = Builder::new();
+    for expr in &["x^2 - 1", "2 x^3 - 2 x", "x^4 + 4", "x^3 y - x y", "sin(x^2 + 2 x + 1)"] {
+        let n = b.parse(expr).unwrap();
+        let f = b.factor(n.clone()).unwrap();
+        println!("{} -> {}", n, f);
+        assert_equivalent(expr, &n, &f);
+    }
+    let t = b.parse("(x^2 - 1, x^2 y - y)").unwrap();
+    let parts = ["x^2 - 1", "x^2 y - y"].iter().map(|e| b.factor(b.parse(e).unwrap()));
+    assert_eq!(b.factor(t).unwrap(), b.tuple(parts).unwrap());
+
+    // irreducible polynomials stay as they are
+    let n = b.parse("x^2 + 1").unwrap();
+    assert_eq!(b.factor(n.clone()).unwrap(), n);
+    assert_eq!(b.factor(b.parse("x^2 - 1").unwrap()).unwrap().to_string(), "(x − 1) (x + 1)");
+
+    let mut ctx = EvalContext::new();
+    assert_eq!(ctx.run("factor x^2 + 2 x + 1").unwrap().unwrap(), "(x + 1)²");
+    assert_eq!(ctx.run("factor (x^2 - 1)").unwrap().unwrap(), "(x − 1) (x + 1)");
+    // expressions containing the name
+    assert_eq!(ctx.run("factor + 1").unwrap().unwrap(), b.parse("factor + 1").unwrap().to_string());
+    let f = b.add(b.factor(b.parse("x^2 - 1").unwrap()).unwrap(), b.int(1)).unwrap();
+    assert_eq!(ctx.run("factor(x^2 - 1) + 1").unwrap().unwrap(), f.to_string());
+}
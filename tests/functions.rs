@@ -0,0 +1,65 @@
+extern crate bullet;
+use bullet::builder::Builder;
+use bullet::diff::diff;
+use bullet::eval::EvalContext;
+
+const FUNCTIONS: &[&str] = &[
+    "tan(x)", "asin(x)", "acos(x)", "atan(x)", "atan2(x, 2)", "atan2(1, x)",
+    "sinh(x)", "cosh(x)", "tanh(x)", "asinh(x)", "acosh(x + 2)", "atanh(x)",
+    "abs(x)", "min(x, x^2)", "max(x, 1 - x)", "erf(x)"
+];
+
+#[test]
+fn eval() {
+    let b = Builder::new();
+    let mut ctx = EvalContext::new();
+    let mut check = |e: &str, x: f64, expected: f64| {
+        ctx.set("x", x);
+        let v = ctx.eval(&b.parse(e).unwrap()).unwrap();
+        assert!((v - expected).abs() < 1e-12, "{}({}): {} != {}", e, x, v, expected);
+    };
+    check("tan(x)", 0.5, 0.5f64.tan());
+    check("atan2(x, -1)", 1.0, 1f64.atan2(-1.0));
+    check("acosh(x)", 2.0, 2f64.acosh());
+    check("sign(x)", -3.0, -1.0);
+    check("sign(x)", 0.0, 0.0);
+    check("floor(x) + ceil(x)", 2.5, 5.0);
+    check("min(x, 1) + max(x, 1)", 0.25, 1.25);
+    check("erf(x)", 0.5, 0.5204998778130465);
+    check("erf(x)", -1.0, -0.8427007929497149);
+    check("erf(x)", 3.5, 0.9999992569016276);
+}
+
+#[test]
+fn derivatives() {
+    let b = Builder::new();
+    let mut ctx = EvalContext::new();
+    let h = 1e-6;
+    for e in FUNCTIONS {
+        let f = b.parse(e).unwrap();
+        let df = diff(&b, &f, "x").unwrap();
+        for &x in &[-0.3, 0.2, 0.7] {
+            let mut at = |x| {
+                ctx.set("x", x);
+                ctx.eval(&f).unwrap()
+            };
+            let numeric = (at(x + h) - at(x - h)) / (2.0 * h);
+            ctx.set("x", x);
+            let v = ctx.eval(&df).unwrap();
+            assert!((v - numeric).abs() < 1e-6 * (1.0 + v.abs()), "d/dx {} at {}: {} != {}", e, x, v, numeric);
+        }
+    }
+    assert_eq!(diff(&b, &b.parse("tan(x)").unwrap(), "x").unwrap(), b.parse("1 / cos(x)^2").unwrap());
+    assert_eq!(diff(&b, &b.parse("floor(x)").unwrap(), "x").unwrap(), b.int(0));
+    // with π as a constant, not as acos(-1)
+    assert_eq!(diff(&b, &b.parse("erf(x)").unwrap(), "x").unwrap(), b.parse("2 exp(-x^2) / π^(1/2)").unwrap());
+    assert_eq!(ctx.eval(&b.pi()).unwrap(), std::f64::consts::PI);
+}
+
+#[test]
+fn display() {
+    let b = Builder::new();
+    assert_eq!(b.parse("atan2(y, x)").unwrap().to_string(), "atan2(y, x)");
+    assert_eq!(b.parse("min(x, 2 y)").unwrap().to_string(), "min(x, 2 y)");
+    assert_eq!(b.parse("erf(x)").unwrap().to_string(), "erf(x)");
+}
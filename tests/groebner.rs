@@ -0,0 +1,57 @@
+extern crate bullet;
+use bullet::builder::Builder;
+use bullet::groebner::Order;
+use bullet::eval::EvalContext;
+use bullet::node::Node;
+
+#[test]
+fn basis() {
+    let b = Builder::new();
+    let g = |e: &str, order| b.groebner(b.parse(e).unwrap(), &["x", "y"], order).unwrap();
+
+    assert_eq!(g("(x^2 + y^2 - 1, x - y)", Order::Lex), b.parse("(x - y, y^2 - 1/2)").unwrap());
+    assert_eq!(g("(x^3 - 2 x y, x^2 y - 2 y^2 + x)", Order::GrevLex), b.parse("(x^2, x y, y^2 - x/2)").unwrap());
+    assert_eq!(g("(x y - 1, x - 1, y - 2)", Order::Lex), b.tuple(vec![Ok(b.int(1))]).unwrap());
+
+    let n = b.parse("groebner((x^3 - 2 x y, x^2 y - 2 y^2 + x), (x, y), grevlex)").unwrap();
+    assert_eq!(n, g("(x^3 - 2 x y, x^2 y - 2 y^2 + x)", Order::GrevLex));
+    assert!(b.parse("groebner((x, y), (x, y), foo)").is_err());
+    assert!(b.groebner(b.parse("(x - sin(y))").unwrap(), &["x", "y"], Order::Lex).is_err());
+}
+
+#[test]
+fn system() {
+    let b = Builder::new();
+    let n = b.parse("solve((x + y - 3, x - y - 1), (x, y))").unwrap();
+    assert_eq!(n, b.tuple(vec![b.parse("(2, 1)")]).unwrap());
+
+    let n = b.solve_system(b.parse("(x^2 + y^2 + z^2 - 3, x - y, y - z)").unwrap(), &["x", "y", "z"]).unwrap();
+    assert_eq!(n, b.tuple(vec![b.parse("(-1, -1, -1)"), b.parse("(1, 1, 1)")]).unwrap());
+
+    // no solutions
+    let n = b.solve_system(b.parse("(x y - 1, x)").unwrap(), &["x", "y"]).unwrap();
+    assert_eq!(n, b.tuple(vec![]).unwrap());
+
+    // infinitely many
+    assert!(b.solve_system(b.parse("(x - y)").unwrap(), &["x", "y"]).is_err());
+}
+
+#[test]
+fn irrational() {
+    let b = Builder::new();
+    let ctx = EvalContext::new();
+    let n = b.solve_system(b.parse("(x^2 + y^2 - 1, x - y)").unwrap(), &["x", "y"]).unwrap();
+    let solutions = match *n {
+        Node::Tuple(ref s) => s.clone(),
+        _ => panic!("{}", n)
+    };
+    assert_eq!(solutions.len(), 2);
+    for (s, &sign) in solutions.iter().zip([-1.0f64, 1.0].iter()) {
+        match **s {
+            Node::Tuple(ref xy) => for v in xy {
+                assert!((ctx.eval(v).unwrap() - sign * 0.5f64.sqrt()).abs() < 1e-12, "{}", s);
+            },
+            _ => panic!("{}", s)
+        }
+    }
+}
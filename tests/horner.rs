@@ -0,0 +1,69 @@
+extern crate bullet;
+mod common;
+use bullet::builder::Builder;
+use bullet::compiler::Compiler;
+use bullet::node::NodeRc;
+use crate::common::{Count, assert_close};
+
+/// the value and number of multiplications at (x, y, z)
+fn eval(node: &NodeRc, (x, y, z): (f64, f64, f64)) -> (f64, usize) {
+    let mut vm = Count { x, y, z, .. Count::default() };
+    let value = Compiler::compile(&mut vm, &[node.clone()], &["x", "y", "z"]).unwrap()[0];
+    (value, vm.muls)
+}
+
+/// compare the rewritten forms against the naïve lowering and return the multiplications
+/// of (naïve, horner, estrin)
+fn check(expr: &str) -> (usize, usize, usize) {
+    let b = Builder::new();
+    let naive = b.parse(expr).unwrap();
+    let horner = b.horner(naive.clone()).unwrap();
+    let estrin = b.estrin(naive.clone()).unwrap();
+    println!("{}\n  horner: {}\n  estrin: {}", naive, horner, estrin);
+
+    let mut muls = (0, 0, 0);
+    for &point in &[(0.5, 2.0, 1.0), (-1.5, 0.25, -0.5), (3.0, -2.0, 0.125), (0.0, 1.0, 7.0)] {
+        let (a, n) = eval(&naive, point);
+        let (h, nh) = eval(&horner, point);
+        let (e, ne) = eval(&estrin, point);
+        assert_close(a, h, format!("horner {}", expr));
+        assert_close(a, e, format!("estrin {}", expr));
+        muls = (n, nh, ne);
+    }
+    muls
+}
+
+#[test]
+fn univariate() {
+    let (naive, horner, estrin) = check("x^7 + 2 x^6 - x^5 + 3 x^4 + x^3 - 4 x^2 + 5 x + 6");
+    assert_eq!(horner, 6);
+    assert!(horner < naive);
+    assert!(estrin < naive);
+
+    // sparse
+    let (naive, horner, _) = check("x^9 + 3 x^4 + 1");
+    assert!(horner <= naive);
+}
+
+#[test]
+fn multivariate() {
+    for expr in &[
+        "x^3 y + 2 x^2 y^2 + x y + y^3 + 1",
+        "x^2 y z + x y^2 z + x y z^2 + 4 x y z + z^3 - 2",
+        "(x + y + z)^4",
+        "x^2 / y + x / y + 1 / y",
+        "sin(x^3 + 3 x^2 + 3 x + 1) + y^2 + y"
+    ] {
+        let (naive, horner, _) = check(expr);
+        assert!(horner <= naive, "{}: {} > {}", expr, horner, naive);
+    }
+}
+
+#[test]
+fn unchanged() {
+    let b = Builder::new();
+    for expr in &["x + y", "x^2 y + 3", "sin(x)"] {
+        let n = b.parse(expr).unwrap();
+        assert_eq!(b.horner(n.clone()).unwrap(), n);
+    }
+}
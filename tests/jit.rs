@@ -0,0 +1,80 @@
+#![cfg(all(feature="jit", target_feature="avx"))]
+extern crate bullet;
+
+use bullet::builder::Builder;
+use bullet::eval::EvalContext;
+use bullet::rt::simd_jit::Kernel;
+use std::sync::Arc;
+use std::thread;
+
+fn close(a: f32, b: f64) -> bool {
+    (a as f64 - b).abs() < 1e-3 * (1.0 + b.abs())
+}
+
+#[test]
+fn jit_kernel() {
+    let b = Builder::new();
+    let f = b.parse("x^2 + 3 y").unwrap();
+    let g = b.parse("x / y").unwrap();
+    let kernel: Kernel<(f32, f32), (f32, f32)> = Kernel::compile(&[f.clone(), g.clone()], &["x", "y"]).unwrap();
+
+    assert_eq!(kernel.call((2.0, 1.0)).0, 7.0);
+
+    // not a multiple of 8
+    let xs: Vec<(f32, f32)> = (0 .. 13).map(|i| (i as f32 * 0.25, 1.0 + i as f32)).collect();
+    let ys = kernel.map(&xs);
+    assert_eq!(ys.len(), xs.len());
+
+    let mut ctx = EvalContext::new();
+    for (&(x, y), &(a, b)) in xs.iter().zip(ys.iter()) {
+        ctx.set("x", x as f64);
+        ctx.set("y", y as f64);
+        assert!(close(a, ctx.eval(&f).unwrap()));
+        assert!(close(b, ctx.eval(&g).unwrap()));
+    }
+}
+
+#[test]
+fn jit_kernel_types() {
+    let b = Builder::new();
+    let f = b.parse("x y").unwrap();
+    assert!(Kernel::<f32, f32>::compile(&[f.clone()], &["x", "y"]).is_err());
+    assert!(Kernel::<(f32, f32), f32>::compile(&[f], &["x", "y"]).is_ok());
+}
+
+#[test]
+fn jit_threads() {
+    let b = Builder::new();
+    let f = b.parse("sin(x) * x").unwrap();
+    let kernel: Arc<Kernel<f32, f32>> = Arc::new(Kernel::compile(&[f], &["x"]).unwrap());
+
+    let handles: Vec<_> = (0 .. 4).map(|t| {
+        let kernel = kernel.clone();
+        thread::spawn(move || {
+            let xs: Vec<f32> = (0 .. 100).map(|i| (t * 100 + i) as f32 * 0.01).collect();
+            let ys = kernel.map(&xs);
+            xs.iter().zip(ys).all(|(&x, y)| close(y, (x.sin() * x) as f64))
+        })
+    }).collect();
+    for h in handles {
+        assert!(h.join().unwrap());
+    }
+}
+
+#[test]
+fn jit_compare() {
+    // sign and the odd roots compare in the generated code through step_at
+    let b = Builder::new();
+    let mut ctx = EvalContext::new();
+    for e in &["sign(x - y)", "sign(y - x)", "cbrt(x - y)", "max(x, y)"] {
+        let f = b.parse(e).unwrap();
+        let kernel: Kernel<(f32, f32), f32> = Kernel::compile(&[f.clone()], &["x", "y"]).unwrap();
+        let xs: Vec<(f32, f32)> = (0 .. 13).map(|i| (i as f32 * 0.5 - 3.0, 0.25)).collect();
+        for (&(x, y), &z) in xs.iter().zip(kernel.map(&xs).iter()) {
+            ctx.set("x", x as f64);
+            ctx.set("y", y as f64);
+            let expected = ctx.eval(&f).unwrap();
+            assert!(close(z, expected), "{} at x = {}: {} != {}", e, x, z, expected);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+extern crate bullet;
+use bullet::builder::Builder;
+use bullet::eval::EvalContext;
+use bullet::vm::opencl::{kernel, Precision};
+use std::collections::HashMap;
+
+/// evaluates the C subset the OpenCL backend emits
+struct Interp<'a> {
+    vars: &'a HashMap<String, f64>,
+    s: &'a [u8],
+    pos: usize
+}
+impl<'a> Interp<'a> {
+    fn eval(vars: &'a HashMap<String, f64>, s: &'a str) -> f64 {
+        let mut i = Interp { vars, s: s.as_bytes(), pos: 0 };
+        let v = i.sum();
+        assert_eq!(i.pos, i.s.len(), "trailing input in {}", s);
+        v
+    }
+    fn peek(&mut self) -> Option<u8> {
+        while self.s.get(self.pos) == Some(&b' ') {
+            self.pos += 1;
+        }
+        self.s.get(self.pos).cloned()
+    }
+    fn expect(&mut self, c: u8) {
+        assert_eq!(self.peek(), Some(c));
+        self.pos += 1;
+    }
+    fn sum(&mut self) -> f64 {
+        let mut v = self.product();
+        loop {
+            match self.peek() {
+                Some(b'+') => { self.pos += 1; v += self.product(); },
+                Some(b'-') => { self.pos += 1; v -= self.product(); },
+                _ => return v
+            }
+        }
+    }
+    fn product(&mut self) -> f64 {
+        let mut v = self.atom();
+        loop {
+            match self.peek() {
+                Some(b'*') => { self.pos += 1; v *= self.atom(); },
+                Some(b'/') => { self.pos += 1; v /= self.atom(); },
+                _ => return v
+            }
+        }
+    }
+    fn atom(&mut self) -> f64 {
+        match self.peek().expect("unexpected end") {
+            b'(' => {
+                self.pos += 1;
+                let v = self.sum();
+                self.expect(b')');
+                v
+            },
+            b'-' => {
+                self.pos += 1;
+                -self.atom()
+            },
+            b'0' ..= b'9' => {
+                let start = self.pos;
+                while let Some(&c) = self.s.get(self.pos) {
+                    match c {
+                        b'0' ..= b'9' | b'.' | b'e' => self.pos += 1,
+                        b'-' if self.s[self.pos-1] == b'e' => self.pos += 1,
+                        _ => break
+                    }
+                }
+                let v = std::str::from_utf8(&self.s[start .. self.pos]).unwrap().parse().unwrap();
+                if self.s.get(self.pos) == Some(&b'f') {
+                    self.pos += 1;
+                }
+                v
+            },
+            _ => {
+                let start = self.pos;
+                while let Some(&c) = self.s.get(self.pos) {
+                    match c {
+                        b'a' ..= b'z' | b'A' ..= b'Z' | b'_' | b'0' ..= b'9' => self.pos += 1,
+                        _ => break
+                    }
+                }
+                let name = std::str::from_utf8(&self.s[start .. self.pos]).unwrap().to_owned();
+                match self.peek() {
+                    Some(b'(') => {
+                        self.pos += 1;
+                        let mut args = vec![self.sum()];
+                        while self.peek() == Some(b',') {
+                            self.pos += 1;
+                            args.push(self.sum());
+                        }
+                        self.expect(b')');
+                        match (name.as_str(), args.as_slice()) {
+                            ("sin", &[x]) => x.sin(),
+                            ("cos", &[x]) => x.cos(),
+                            ("exp", &[x]) => x.exp(),
+                            ("log", &[x]) => x.ln(),
+                            ("fabs", &[x]) => x.abs(),
+                            ("atan2", &[y, x]) => y.atan2(x),
+                            ("fmin", &[a, b]) => a.min(b),
+                            ("fmax", &[a, b]) => a.max(b),
+                            ("floor", &[x]) => x.floor(),
+                            ("ceil", &[x]) => x.ceil(),
+                            ("step", &[at, x]) => if x >= at { 1.0 } else { 0.0 },
+                            (f, _) => panic!("unknown function {}", f)
+                        }
+                    },
+                    Some(b'[') => {
+                        self.pos += 1;
+                        assert_eq!(self.peek(), Some(b'i'));
+                        self.pos += 1;
+                        self.expect(b']');
+                        self.vars[&name]
+                    },
+                    _ => *self.vars.get(&name).unwrap_or_else(|| panic!("{} is not defined", name))
+                }
+            }
+        }
+    }
+}
+
+/// run the kernel for one work item
+fn run(source: &str, inputs: &[f64]) -> Vec<f64> {
+    let mut vars: HashMap<String, f64> = inputs.iter().enumerate().map(|(k, &v)| (format!("in_{}", k), v)).collect();
+    let mut outputs = vec![];
+    for line in source.lines().map(|l| l.trim()) {
+        let (lhs, rhs) = match line.find(" = ") {
+            Some(p) => (&line[.. p], line[p + 3 ..].trim_end_matches(';')),
+            None => continue
+        };
+        if lhs.starts_with("size_t") {
+            continue;
+        }
+        let value = Interp::eval(&vars, rhs);
+        if lhs.starts_with("out_") {
+            outputs.push(value);
+        } else {
+            let name = lhs.split_whitespace().nth(1).unwrap();
+            assert!(!vars.contains_key(name), "{} assigned twice", name);
+            vars.insert(name.to_owned(), value);
+        }
+    }
+    outputs
+}
+
+#[test]
+fn opencl_snapshot() {
+    let b = Builder::new();
+    let nodes = [b.parse("x y").unwrap(), b.parse("sin(x) / 2").unwrap()];
+    assert_eq!(kernel("f", &nodes, &["x", "y"], Precision::Single).unwrap(), "\
+__kernel void f(__global const float* in_0, __global const float* in_1, __global float* out_0, __global float* out_1, const ulong n) {
+    size_t i = get_global_id(0);
+    if (i >= n) return;
+    float storage_0 = in_0[i];
+    out_0[i] = (storage_0 * in_1[i]);
+    out_1[i] = (0.5f * sin(storage_0));
+}
+");
+    assert!(kernel("f", &nodes, &["x", "y"], Precision::Double).unwrap()
+        .starts_with("#pragma OPENCL EXTENSION cl_khr_fp64 : enable\n\n__kernel void f(__global const double* in_0"));
+}
+
+#[test]
+fn opencl_structure() {
+    let b = Builder::new();
+    let n = b.parse("sin(x^4)^2 + cos(3*x-5) + 1 / y^2").unwrap();
+    let source = kernel("g", &[n], &["x", "y"], Precision::Single).unwrap();
+
+    let count = |c| source.chars().filter(|&d| d == c).count();
+    assert_eq!(count('('), count(')'));
+    assert_eq!(count('{'), count('}'));
+    assert_eq!(source.matches("out_0[i] =").count(), 1);
+    for line in source.lines().filter(|l| l.starts_with("    ")) {
+        assert!(line.ends_with(';'), "{}", line);
+    }
+}
+
+#[test]
+fn opencl_eval() {
+    let b = Builder::new();
+    let exprs = ["x y + 3", "sin(x) * y + x^3 / (y + 2)", "cos(x)^2 + sin(x)^2", "x^5 - 2 / x^2",
+        "exp(x) / y + atan2(y, x)", "max(x, y) - abs(y) log(x^2)"];
+    let nodes: Vec<_> = exprs.iter().map(|e| b.parse(e).unwrap()).collect();
+    let source = kernel("h", &nodes, &["x", "y"], Precision::Double).unwrap();
+
+    let mut ctx = EvalContext::new();
+    for &(x, y) in &[(0.5, 1.5), (-2.0, 3.0), (1.25, -0.75)] {
+        ctx.set("x", x);
+        ctx.set("y", y);
+        let outputs = run(&source, &[x, y]);
+        assert_eq!(outputs.len(), nodes.len());
+        for (n, out) in nodes.iter().zip(outputs) {
+            let expected = ctx.eval(n).unwrap();
+            assert!((out - expected).abs() < 1e-9 * (1.0 + expected.abs()), "{}: {} != {}", n, out, expected);
+        }
+    }
+}
+
+#[test]
+fn opencl_names() {
+    // inputs named like the work item and the length of the buffers
+    let b = Builder::new();
+    let n = b.parse("i - 2 n").unwrap();
+    let source = kernel("k", &[n.clone()], &["i", "n"], Precision::Double).unwrap();
+    assert!(source.contains("(__global const double* in_0, __global const double* in_1, __global double* out_0, const ulong n)"));
+
+    let mut ctx = EvalContext::new();
+    ctx.set("i", 5.0);
+    ctx.set("n", 0.25);
+    assert_eq!(run(&source, &[5.0, 0.25]), vec![ctx.eval(&n).unwrap()]);
+}
@@ -0,0 +1,97 @@
+extern crate bullet;
+mod common;
+use bullet::builder::Builder;
+use bullet::poly::Poly;
+use crate::common::poly;
+
+#[test]
+fn div_rem() {
+    let b = Builder::new();
+    let (q, r) = poly(&b, "x^3 + 2 x + 1").div_rem(&poly(&b, "x - 1")).unwrap();
+    assert_eq!(q, poly(&b, "x^2 + x + 3"));
+    assert_eq!(r, poly(&b, "4"));
+
+    // multivariate
+    let (q, r) = poly(&b, "x^2 y + x y^2 + y^2").div_rem(&poly(&b, "x y - 1")).unwrap();
+    assert_eq!(q, poly(&b, "x + y"));
+    assert_eq!(r, poly(&b, "y^2 + x + y"));
+    assert_eq!(q * poly(&b, "x y - 1") + r, poly(&b, "x^2 y + x y^2 + y^2"));
+
+    assert!(poly(&b, "x").div_rem(&Poly::zero()).is_err());
+    assert!(poly(&b, "1/x").div_rem(&poly(&b, "x")).is_err());
+}
+
+#[test]
+fn gcd() {
+    let b = Builder::new();
+    let g = |x: &str, y: &str| poly(&b, x).gcd(&poly(&b, y)).unwrap();
+
+    assert_eq!(g("x^2 - 1", "x^2 + 2 x + 1"), poly(&b, "x + 1"));
+    assert_eq!(g("(x + y) (x - y)", "(x + y)^2"), poly(&b, "x + y"));
+    assert_eq!(g("2 x y + 2 y", "4 x^2 y^2 - 4 y^2"), poly(&b, "x y + y"));
+    assert_eq!(g("(x z + 1) (y - z)^2", "(x z + 1) (y + z)"), poly(&b, "x z + 1"));
+    assert_eq!(g("x^2 + 1", "x + 1"), poly(&b, "1"));
+    assert_eq!(g("6", "4"), poly(&b, "1"));
+    assert_eq!(g("0", "3 x - 3"), poly(&b, "x - 1"));
+}
+
+#[test]
+fn cancel() {
+    let b = Builder::new();
+    let eq = |x: &str, y: &str| assert_eq!(b.parse(x).unwrap(), b.parse(y).unwrap(), "{} != {}", x, y);
+
+    eq("(x^2 - 1) / (x - 1)", "x + 1");
+    eq("(x^2 y - y) / (x y + y)", "x - 1");
+    eq("(x + 1) / (2 x + 2)", "1/2");
+    eq("x / (x^2 + x)", "1 / (x + 1)");
+    eq("(sin(x)^2 - 1) / (sin(x) + 1)", "sin(x) - 1");
+    assert!(b.parse("(x + 1) / 0").is_err());
+}
+
+#[test]
+fn expand() {
+    let b = Builder::new();
+    let n = b.factor(b.parse("x^2 + 2 x + 1").unwrap()).unwrap();
+    assert_eq!(b.expand(n).unwrap(), b.parse("x^2 + 2 x + 1").unwrap());
+    assert_eq!(b.expand(b.parse("(x + 1)^5").unwrap()).unwrap(), b.parse("x^5 + 5 x^4 + 10 x^3 + 10 x^2 + 5 x + 1").unwrap());
+    assert_eq!(b.expand(b.parse("sin((x + y)^4)").unwrap()).unwrap(), b.parse("sin((x^2 + 2 x y + y^2)^2)").unwrap());
+}
+
+#[test]
+fn coefficients() {
+    let b = Builder::new();
+    let n = b.parse("x^2 y + 3 x^2 + x y^2 - 2 y + 1").unwrap();
+    let c = |e: &str| b.parse(e).unwrap();
+
+    assert_eq!(b.coeff(n.clone(), "x", 2).unwrap(), c("y + 3"));
+    assert_eq!(b.coeff(n.clone(), "x", 1).unwrap(), c("y^2"));
+    assert_eq!(b.coeff(n.clone(), "x", 0).unwrap(), c("1 - 2 y"));
+    assert_eq!(b.coeff(n.clone(), "x", 3).unwrap(), c("0"));
+    assert_eq!(b.coeff(c("(x + 1)^4"), "x", 2).unwrap(), c("6"));
+
+    assert_eq!(b.degree(n.clone(), "x").unwrap(), c("2"));
+    assert_eq!(b.degree(n.clone(), "z").unwrap(), c("0"));
+    assert_eq!(b.degree(c("(x y + 1)^7"), "y").unwrap(), c("7"));
+
+    assert_eq!(b.leading_term(n.clone()).unwrap(), c("x^2 y"));
+    assert_eq!(b.leading_term(c("0")).unwrap(), c("0"));
+
+    let collected = b.collect(n.clone(), "x").unwrap();
+    assert_eq!(b.expand(collected.clone()).unwrap(), n);
+    assert_eq!(b.coeff(collected, "x", 2).unwrap(), c("y + 3"));
+
+    // as functions in expressions
+    assert_eq!(c("coeff(x^2 y + 3 x^2, x, 2)"), c("y + 3"));
+    assert_eq!(c("degree((x + 1)^5, x) + 1"), c("6"));
+    assert_eq!(c("leading_term(expand((x + y)^2))"), c("x^2"));
+    assert_eq!(c("collect(x y + x, x)"), b.collect(c("x y + x"), "x").unwrap());
+    assert_eq!(c("factor(x^2 - 1)"), b.factor(c("x^2 - 1")).unwrap());
+    assert!(b.parse("coeff(x^2, x)").is_err());
+
+    // the names are not reserved
+    assert_eq!(c("degree + collect"), b.add(b.var("degree"), b.var("collect")).unwrap());
+    // and only called when followed by a parenthesis
+    assert_eq!(c("expand x"), b.mul(b.var("expand"), b.var("x")).unwrap());
+    assert_eq!(c("factor y"), b.mul(b.var("factor"), b.var("y")).unwrap());
+    assert_eq!(c("expand (x + 1)"), b.mul(b.var("expand"), c("x + 1")).unwrap());
+}
@@ -0,0 +1,50 @@
+extern crate bullet;
+use bullet::builder::Builder;
+use bullet::vm::ptx::kernel;
+
+#[test]
+fn ptx_multiple_outputs() {
+    let b = Builder::new();
+    let nodes = [b.parse("2 x").unwrap(), b.parse("x y").unwrap()];
+    let ptx = kernel("f", &nodes, &["x", "y"]).unwrap();
+    println!("{}", ptx);
+
+    assert!(ptx.contains(".entry f(.param.u64 src, .param.u64 dst, .param.u32 n)"));
+    assert!(ptx.contains(".reg.f32            _r<5>;"));
+    assert!(ptx.contains("mul.wide.u32        off_in,     i, 8;"));
+    assert!(ptx.contains("mul.wide.u32        off_out,    i, 8;"));
+    assert!(ptx.contains("ld.cs.f32           _r0, [data_in+0];"));
+    assert!(ptx.contains("ld.cs.f32           _r1, [data_in+4];"));
+    assert!(ptx.contains("mov.f32             _r2, 0F40000000;"));
+    assert!(ptx.contains("mul.f32             _r3, _r2, _r0;"));
+    assert!(ptx.contains("mul.f32             _r4, _r0, _r1;"));
+    assert!(ptx.contains("st.cs.f32           [data_out+0], _r3;"));
+    assert!(ptx.contains("st.cs.f32           [data_out+4], _r4;"));
+
+    // threads past the end return before loading anything
+    let guard = ptx.find("setp.ge.u32         done,       i, len;").unwrap();
+    let exit = ptx.find("@done               ret;").unwrap();
+    assert!(guard < exit && exit < ptx.find("ld.cs.f32").unwrap());
+}
+
+#[test]
+fn ptx_registers_are_defined_before_use() {
+    let b = Builder::new();
+    let n = b.parse("sin(x^4)^2 + cos(3*x-5)").unwrap();
+    let ptx = kernel("main", &[n], &["x"]).unwrap();
+
+    let mut defined = vec![];
+    for line in ptx.lines().map(|l| l.trim()).filter(|l| l.starts_with("ld.cs") || l.contains(".f32 ") && l.contains("_r")) {
+        let mut args = line.split_whitespace().skip(1).map(|a| a.trim_matches(|c| c == ',' || c == ';'));
+        let first = args.next().unwrap();
+        if first.starts_with('[') {
+            // store: all arguments are read
+            assert!(args.all(|a| defined.contains(&a.to_string())), "{}", line);
+            continue;
+        }
+        for a in args.filter(|a| a.starts_with("_r")) {
+            assert!(defined.contains(&a.to_string()), "{} used before definition", a);
+        }
+        defined.push(first.to_string());
+    }
+}
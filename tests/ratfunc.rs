@@ -0,0 +1,53 @@
+extern crate bullet;
+mod common;
+use bullet::builder::Builder;
+use bullet::display::{Tokens, Mode};
+use crate::common::assert_equivalent;
+
+#[test]
+fn together() {
+    let b = Builder::new();
+    let n = b.parse("(x + 1) / (x - 1)").unwrap();
+    assert_eq!(n.to_string(), "x / (x − 1) + 1 / (x − 1)");
+    assert_eq!(b.together(n).unwrap().to_string(), "(x + 1) / (x − 1)");
+
+    let n = b.parse("1 / (x + 1) + 1 / (x - 1)").unwrap();
+    assert_eq!(b.together(n.clone()).unwrap().to_string(), "2 x / ((x − 1) (x + 1))");
+    assert_eq!(b.cancel(n).unwrap().to_string(), "2 x / (x² − 1)");
+
+    // already combined
+    let n = b.parse("x / (y + 1)").unwrap();
+    assert_eq!(b.together(n.clone()).unwrap(), n);
+}
+
+#[test]
+fn cancel() {
+    let b = Builder::new();
+    let c = |e: &str| b.cancel(b.parse(e).unwrap()).unwrap();
+
+    assert_eq!(c("1 / (x + 1) + x / (x + 1)"), b.int(1));
+    assert_eq!(c("1 / (x - y) - 1 / (y - x)"), c("2 / (x - y)"));
+    assert_eq!(c("x / (x^2 - x) + 1 / (x - 1)^2"), c("x / (x - 1)^2"));
+    assert_eq!(c("1 / (sin(x) - 1) - 1 / (sin(x) + 1)"), c("2 / (sin(x)^2 - 1)"));
+}
+
+#[test]
+fn apart() {
+    let b = Builder::new();
+    for expr in &["(x^2 + 1) / (x^3 - x)", "x^3 / (x^2 - 1)", "1 / ((x - 1)^2 (x + 2))", "(x + 3) / (x^4 - 1)"] {
+        let n = b.parse(expr).unwrap();
+        let p = b.apart(n.clone(), "x").unwrap();
+        println!("{} -> {}", n, p);
+        assert_eq!(b.cancel(p.clone()).unwrap(), b.cancel(n.clone()).unwrap(), "{}", expr);
+        assert_equivalent(expr, &n, &p);
+    }
+    let n = b.parse("2 / (x^2 - 1)").unwrap();
+    assert_eq!(b.apart(n, "x").unwrap().to_string(), "− 1 / (x + 1) + 1 / (x − 1)");
+}
+
+#[test]
+fn latex() {
+    let b = Builder::new();
+    let n = b.parse("x / y").unwrap();
+    assert_eq!(Tokens::node(&n, &Mode::LaTeX).to_string(), "\\frac{x}{y}");
+}
@@ -0,0 +1,40 @@
+extern crate bullet;
+use bullet::builder::Builder;
+use bullet::diff::diff;
+use bullet::eval::EvalContext;
+
+#[test]
+fn simplify() {
+    let b = Builder::new();
+    let p = |e: &str| b.parse(e).unwrap();
+
+    assert_eq!(p("sqrt(4)"), b.int(2));
+    assert_eq!(p("sqrt(8)"), p("2 sqrt(2)"));
+    assert_eq!(p("sqrt(8)").to_string(), "2 √2");
+    assert_eq!(p("sqrt(1/2)"), p("sqrt(2) / 2"));
+    assert_eq!(p("cbrt(-8)"), b.int(-2));
+    assert_eq!(p("sqrt(x)^2"), p("x"));
+    assert_eq!(p("x^(1/2)").to_string(), "√x");
+    assert_eq!(p("cbrt(x)").to_string(), "∛x");
+
+    // integer parts of every size are folded into the coefficient, unless it gets huge
+    assert_eq!(p("2^10"), b.int(1024));
+    assert_eq!(p("(2/3)^(-5)"), p("243 / 32"));
+    assert_ne!(p("2^1000"), p("2^999 2"));
+}
+
+#[test]
+fn derivative() {
+    let b = Builder::new();
+    let d = diff(&b, &b.parse("sqrt(x)").unwrap(), "x").unwrap();
+    assert_eq!(d, b.parse("1 / (2 sqrt(x))").unwrap());
+}
+
+#[test]
+fn eval() {
+    let b = Builder::new();
+    let mut ctx = EvalContext::new();
+    ctx.set("x", 2.0);
+    let v = ctx.eval(&b.parse("sqrt(x) + cbrt(-x)").unwrap()).unwrap();
+    assert!((v - (2f64.sqrt() - 2f64.cbrt())).abs() < 1e-12);
+}
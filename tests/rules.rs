@@ -0,0 +1,47 @@
+extern crate bullet;
+use bullet::builder::Builder;
+use bullet::rules::{Rule, Rules};
+
+#[test]
+fn rewrite() {
+    let builder = Builder::new();
+    let mut rules = Rules::new();
+    for r in &["sin(?a)^2 + cos(?a)^2 => 1", "exp(?a) exp(?b) => exp(?a + ?b)", "sin(2 ?a) => 2 sin(?a) cos(?a)"] {
+        rules.push(Rule::parse(&builder, r).unwrap());
+    }
+    let pairs = [
+        ("2 sin(x)^2 + 2 cos(x)^2 + y", "y + 2"),
+        ("cos(x)^2 + sin(x)^2", "1"),
+        ("3 x exp(u) exp(v)", "3 x exp(u + v)"),
+        ("sin(4 x)", "4 sin(x) cos(x) cos(2 x)"),
+        ("sin(x)", "sin(x)"),
+        ("sin(x) + cos(y)", "sin(x) + cos(y)"),
+    ];
+    for &(a, b) in &pairs {
+        let node = builder.parse(a).unwrap();
+        assert_eq!(builder.rewrite(node, &rules).unwrap(), builder.parse(b).unwrap());
+    }
+}
+
+#[test]
+fn invalid() {
+    let builder = Builder::new();
+    assert!(Rule::parse(&builder, "sin(?a) => ?b").is_err());
+    assert!(Rule::parse(&builder, "?a => 0").is_err());
+    assert!(Rules::parse(&builder, "sin(?a) => \n").is_err());
+}
+
+#[test]
+fn fixpoint() {
+    let builder = Builder::new();
+    let rules = Rules::parse(&builder, "exp(?a) => exp(?a + 1)").unwrap();
+    assert!(builder.rewrite(builder.parse("exp(x)").unwrap(), &rules).is_err());
+}
+
+#[test]
+fn load() {
+    let builder = Builder::new();
+    let rules = Rules::load(&builder, concat!(env!("CARGO_MANIFEST_DIR"), "/tests/trig.rules")).unwrap();
+    let node = builder.parse("cosh(t)^2 - sinh(t)^2 + exp(a) exp(-a)").unwrap();
+    assert_eq!(builder.rewrite(node, &rules).unwrap(), builder.int(2));
+}
@@ -0,0 +1,98 @@
+extern crate bullet;
+use bullet::builder::Builder;
+use bullet::vm::shader::{compute, fragment, ShaderConfig, Binding, Lang};
+
+#[test]
+fn glsl_compute() {
+    let b = Builder::new();
+    let n = b.parse("(x y, 2 t)").unwrap();
+    let config = ShaderConfig::new(Lang::Glsl)
+        .input("x", Binding::Storage(1))
+        .input("y", Binding::Storage(2))
+        .input("t", Binding::Uniform(0));
+
+    assert_eq!(compute(&config, &n).unwrap(), "\
+#version 430
+layout(local_size_x = 64) in;
+layout(std430, binding = 1) readonly buffer Input_x { float x[]; };
+layout(std430, binding = 2) readonly buffer Input_y { float y[]; };
+layout(location = 0) uniform float t;
+layout(std430, binding = 0) writeonly buffer Output { vec2 result[]; };
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index >= result.length()) {
+        return;
+    }
+    result[index] = vec2((x[index] * y[index]), (2.0 * t));
+}
+");
+}
+
+#[test]
+fn wgsl_compute() {
+    let b = Builder::new();
+    let n = b.parse("(sin(x), cos(t))").unwrap();
+    let config = ShaderConfig::new(Lang::Wgsl)
+        .input("x", Binding::Invocation(0))
+        .input("t", Binding::Uniform(1));
+
+    assert_eq!(compute(&config, &n).unwrap(), "\
+@group(0) @binding(1) var<uniform> t: f32;
+@group(0) @binding(0) var<storage, read_write> result: array<vec2<f32>>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= arrayLength(&result)) {
+        return;
+    }
+    result[index] = vec2<f32>(sin(f32(id.x)), cos(t));
+}
+");
+}
+
+#[test]
+fn glsl_fragment() {
+    let b = Builder::new();
+    let n = b.parse("x^2").unwrap();
+    let config = ShaderConfig::new(Lang::Glsl)
+        .input("x", Binding::Attribute(0));
+
+    assert_eq!(fragment(&config, &n).unwrap(), "\
+#version 330
+in float x;
+out vec4 final_col;
+
+void main() {
+    float storage_0 = x;
+    final_col = vec4(vec3((storage_0 * storage_0)), 1.0);
+}
+");
+}
+
+#[test]
+fn wgsl_fragment() {
+    let b = Builder::new();
+    let n = b.parse("(x, y, 1)").unwrap();
+    let config = ShaderConfig::new(Lang::Wgsl)
+        .input("x", Binding::Attribute(0))
+        .input("y", Binding::Attribute(1));
+
+    assert_eq!(fragment(&config, &n).unwrap(), "\
+@fragment
+fn main(@location(0) x: f32, @location(1) y: f32) -> @location(0) vec4<f32> {
+    return vec4<f32>(x, y, 1.0, 1.0);
+}
+");
+}
+
+#[test]
+fn shader_errors() {
+    let b = Builder::new();
+    let config = ShaderConfig::new(Lang::Glsl)
+        .input("x", Binding::Attribute(0));
+    assert!(compute(&config, &b.parse("x").unwrap()).is_err());
+    assert!(fragment(&config, &b.parse("x + y").unwrap()).is_err());
+    assert!(fragment(&config, &b.parse("(x, x, x, x, x)").unwrap()).is_err());
+}
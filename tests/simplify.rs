@@ -1,5 +1,7 @@
 extern crate bullet;
 use bullet::builder::Builder;
+use bullet::simplify::Assumptions;
+use bullet::eval::EvalContext;
 
 #[test]
 fn text_simplify() {
@@ -16,3 +18,69 @@ fn text_simplify() {
         assert_eq!(builder.parse(a).unwrap(), builder.parse(b).unwrap());
     }
 }
+
+#[test]
+fn elementary_functions() {
+    let pairs = [
+        ("sin(x)^2 + cos(x)^2", "1"),
+        ("2 sin(y)^2 + 2 cos(y)^2 + x", "x + 2"),
+        ("sin(x)^4 + 2 sin(x)^2 cos(x)^2 + cos(x)^4", "1"),
+        ("cosh(x)^2 - sinh(x)^2", "1"),
+        ("exp(a) exp(b)", "exp(a + b)"),
+        ("exp(x)^2 / exp(y)", "exp(2 x - y)"),
+        ("exp(log(x))", "x"),
+        ("log(exp(x + 1))", "x + 1"),
+        ("exp(2 log(x))", "x^2"),
+        ("log(x^3)", "3 log(x)"),
+        ("log(sqrt(x))", "log(x) / 2"),
+        ("sin(-x)", "-sin(x)"),
+        ("cos(-x)", "cos(x)"),
+        ("tan(y - x) + tan(x - y)", "0"),
+        ("sin(asin(x))", "x"),
+        ("sin(0) + cos(0) + log(1)", "1"),
+        ("abs(-3) + floor(5/2) + ceil(-1/2)", "5"),
+    ];
+    let builder = Builder::new();
+    for &(a, b) in &pairs {
+        let simplified = builder.simplify(builder.parse(a).unwrap()).unwrap();
+        assert_eq!(simplified, builder.parse(b).unwrap(), "{} = {}", a, simplified);
+    }
+}
+
+#[test]
+fn assumptions() {
+    let builder = Builder::new();
+    let p = |e: &str| builder.parse(e).unwrap();
+
+    // only valid for x > 0
+    for e in &["log(x^2)", "log(x y)", "asin(sin(x))"] {
+        assert_eq!(builder.simplify(p(e)).unwrap(), p(e));
+    }
+    let assume = Assumptions::new().positive("x").positive("y");
+    let s = |e: &str| builder.simplify_assuming(p(e), &assume).unwrap();
+    assert_eq!(s("log(x^2)"), p("2 log(x)"));
+    assert_eq!(s("log(2 x y)"), p("log(2) + log(x) + log(y)"));
+    assert_eq!(s("log(x exp(y))"), p("log(x) + y"));
+    // x - y may be negative
+    assert_eq!(s("log((x - y)^2)"), p("log((x - y)^2)"));
+}
+
+#[test]
+fn angle_addition() {
+    let builder = Builder::new();
+    let p = |e: &str| builder.parse(e).unwrap();
+    assert_eq!(builder.expand_trig(p("sin(x + y)")).unwrap(), p("sin(x) cos(y) + cos(x) sin(y)"));
+    assert_eq!(builder.expand_trig(p("cos(x - y)")).unwrap(), p("cos(x) cos(y) + sin(x) sin(y)"));
+
+    let n = p("cosh(a + b + c) + sin(2 a - b)");
+    let e = builder.expand_trig(n.clone()).unwrap();
+    let mut ctx = EvalContext::new();
+    for &(a, b, c) in &[(0.3, -1.2, 0.7), (2.0, 0.5, -0.25)] {
+        ctx.set("a", a);
+        ctx.set("b", b);
+        ctx.set("c", c);
+        let (x, y) = (ctx.eval(&n).unwrap(), ctx.eval(&e).unwrap());
+        assert!((x - y).abs() < 1e-12 * (1.0 + x.abs()), "{} != {}", x, y);
+    }
+    assert_eq!(builder.simplify(builder.expand_trig(p("cos(x + y)^2 + sin(x + y)^2")).unwrap()).unwrap(), p("1"));
+}
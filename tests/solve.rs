@@ -0,0 +1,81 @@
+extern crate bullet;
+use bullet::builder::Builder;
+use bullet::eval::EvalContext;
+use bullet::node::{Node, NodeRc};
+
+/// the numerical values of the solutions
+fn values(n: &NodeRc) -> Vec<f64> {
+    let ctx = EvalContext::new();
+    match **n {
+        Node::Tuple(ref parts) => parts.iter().map(|p| ctx.eval(p).unwrap()).collect(),
+        _ => panic!("{} is not a tuple", n)
+    }
+}
+
+/// solve `expr` for x and compare with the expected roots
+fn check(expr: &str, expected: &[f64]) {
+    let b = Builder::new();
+    let roots = b.solve(b.parse(expr).unwrap(), "x").unwrap();
+    println!("{} -> {}", expr, roots);
+    let values = values(&roots);
+    assert_eq!(values.len(), expected.len(), "{}: {:?}", expr, values);
+    for (a, e) in values.iter().zip(expected.iter()) {
+        assert!((a - e).abs() < 1e-9, "{}: {} != {}", expr, a, e);
+    }
+}
+
+#[test]
+fn rational() {
+    let b = Builder::new();
+    let s = |e: &str| b.solve(b.parse(e).unwrap(), "x").unwrap();
+    assert_eq!(s("6 x^3 - 11 x^2 + 6 x - 1"), b.parse("(1/3, 1/2, 1)").unwrap());
+    assert_eq!(s("(x - 1)^2 (x + 2) x"), b.parse("(-2, 0, 1)").unwrap());
+    assert_eq!(s("x^2 + 1"), b.tuple(vec![]).unwrap());
+    assert_eq!(b.parse("solve(x^2 - 1, x)").unwrap(), b.parse("(-1, 1)").unwrap());
+
+    assert!(b.solve(b.parse("x y - 1").unwrap(), "x").is_err());
+    assert!(b.solve(b.parse("0").unwrap(), "x").is_err());
+}
+
+#[test]
+fn radicals() {
+    let r2 = 2f64.sqrt();
+    let r3 = 3f64.sqrt();
+    check("x^2 - 2", &[-r2, r2]);
+    check("2 x^2 + 2 x - 1", &[(-1. - r3) / 2., (-1. + r3) / 2.]);
+    check("(x^2 - 3) (x - 5)", &[-r3, r3, 5.]);
+    check("x^3 - 2", &[2f64.cbrt()]);
+    check("x^3 + x + 1", &[-0.6823278038280193]);
+    check("x^4 - 10 x^2 + 1", &[-r2 - r3, r2 - r3, r3 - r2, r2 + r3]);
+
+    // (x² + √2 x - 2 - √2) (x² - √2 x - 2 + √2), found with Ferrari's method
+    let (a, b) = ((10. + 4. * r2).sqrt(), (10. - 4. * r2).sqrt());
+    check("x^4 - 6 x^2 + 4 x + 2", &[(-r2 - a) / 2., (r2 - b) / 2., (-r2 + a) / 2., (r2 + b) / 2.]);
+}
+
+#[test]
+fn numeric() {
+    let c = |k: f64| 2. * (k * std::f64::consts::PI / 9.).cos();
+    // three real roots can not be written with real radicals
+    check("x^3 - 3 x + 1", &[c(8.), c(4.), c(2.)]);
+
+    let b = Builder::new();
+    let roots = values(&b.solve(b.parse("x^5 - 4 x + 2").unwrap(), "x").unwrap());
+    assert_eq!(roots.len(), 3);
+    for (x, &(lo, hi)) in roots.iter().zip([(-2., -1.), (0., 1.), (1., 2.)].iter()) {
+        assert!(lo < *x && *x < hi);
+        assert!((x.powi(5) - 4. * x + 2.).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn apply() {
+    let mut b = Builder::new();
+    let f = b.parse("x^2 + 1").unwrap();
+    b.define("f", &["x"], f);
+    let roots = b.solve(b.parse("x^2 - 2").unwrap(), "x").unwrap();
+    let f = b.named("f");
+    let values = values(&b.apply(f, roots).unwrap());
+    assert_eq!(values.len(), 2);
+    assert!(values.iter().all(|v| (v - 3.).abs() < 1e-9));
+}
@@ -0,0 +1,125 @@
+#![cfg(feature="wasm_run")]
+extern crate bullet;
+extern crate wasmtime;
+
+use bullet::builder::Builder;
+use bullet::eval::EvalContext;
+use bullet::vm::wasm::{Wasm, Lanes};
+use wasmtime::{Engine, Module, Store, Instance, Val};
+
+fn instantiate(bytes: &[u8]) -> (Store<()>, Instance) {
+    let engine = Engine::default();
+    let module = Module::new(&engine, bytes).expect("invalid module");
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[]).expect("failed to instantiate");
+    (store, instance)
+}
+
+fn call(store: &mut Store<()>, instance: &Instance, name: &str, args: &[f64]) -> f64 {
+    let f = instance.get_func(&mut *store, name).expect("no such export");
+    let args: Vec<_> = args.iter().map(|&x| Val::F64(x.to_bits())).collect();
+    let mut result = [Val::F64(0)];
+    f.call(&mut *store, &args, &mut result).expect("failed to call");
+    result[0].unwrap_f64()
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-3 * (1.0 + b.abs())
+}
+
+#[test]
+fn wasm_scalar() {
+    let b = Builder::new();
+    let n = b.parse("x^2 y + 3 x").unwrap();
+    let (mut store, instance) = instantiate(&Wasm::compile(&n, &["x", "y"]).unwrap());
+    assert_eq!(call(&mut store, &instance, "f", &[2.0, 5.0]), 26.0);
+    assert_eq!(call(&mut store, &instance, "f", &[-1.0, 0.5]), -2.5);
+}
+
+#[test]
+fn wasm_multiple_exports() {
+    let b = Builder::new();
+    let f = b.parse("sin(x) + y").unwrap();
+    let g = b.parse("x / y").unwrap();
+    let module = Wasm::module(&[("f", f.clone()), ("g", g.clone())], &["x", "y"], Lanes::Scalar).unwrap();
+    let (mut store, instance) = instantiate(&module);
+
+    let mut ctx = EvalContext::new();
+    for &(x, y) in &[(0.5, 2.0), (3.0, -1.0)] {
+        ctx.set("x", x);
+        ctx.set("y", y);
+        assert!(close(call(&mut store, &instance, "f", &[x, y]), ctx.eval(&f).unwrap()));
+        assert!(close(call(&mut store, &instance, "g", &[x, y]), ctx.eval(&g).unwrap()));
+    }
+}
+
+/// write `data` as `f32` if `single`, otherwise as `f64`
+fn write(store: &mut Store<()>, instance: &Instance, offset: usize, data: &[f64], single: bool) {
+    let memory = instance.get_memory(&mut *store, "memory").unwrap();
+    let bytes: Vec<u8> = data.iter().flat_map(|&x| match single {
+        true => (x as f32).to_bits().to_le_bytes().to_vec(),
+        false => x.to_bits().to_le_bytes().to_vec()
+    }).collect();
+    memory.write(&mut *store, offset, &bytes).unwrap();
+}
+fn read(store: &mut Store<()>, instance: &Instance, offset: usize, n: usize, single: bool) -> Vec<f64> {
+    let memory = instance.get_memory(&mut *store, "memory").unwrap();
+    let size = if single { 4 } else { 8 };
+    let mut bytes = vec![0; n * size];
+    memory.read(&*store, offset, &mut bytes).unwrap();
+    bytes.chunks(size).map(|c| match single {
+        true => {
+            let mut b = [0; 4];
+            b.copy_from_slice(c);
+            f32::from_bits(u32::from_le_bytes(b)) as f64
+        }
+        false => {
+            let mut b = [0; 8];
+            b.copy_from_slice(c);
+            f64::from_bits(u64::from_le_bytes(b))
+        }
+    }).collect()
+}
+
+fn map(lanes: Lanes, single: bool) {
+    let b = Builder::new();
+    let f = b.parse("(x y, x - 2 y, cos(x), floor(y / 3))").unwrap();
+    let module = Wasm::module(&[("f", f)], &["x", "y"], lanes).unwrap();
+    let (mut store, instance) = instantiate(&module);
+
+    // odd length, so the SIMD path also needs the scalar remainder
+    let n = 7;
+    let x: Vec<f64> = (0 .. n).map(|i| i as f64 * 0.5).collect();
+    let y: Vec<f64> = (0 .. n).map(|i| 1.0 - i as f64).collect();
+    let (px, py, o0, o1, o2, o3) = (0, 1024, 2048, 3072, 4096, 5120);
+    write(&mut store, &instance, px, &x, single);
+    write(&mut store, &instance, py, &y, single);
+
+    let args: Vec<_> = [n as i32, px as i32, py as i32, o0 as i32, o1 as i32, o2 as i32, o3 as i32].iter()
+        .map(|&i| Val::I32(i)).collect();
+    let f_map = instance.get_func(&mut store, "f_map").unwrap();
+    f_map.call(&mut store, &args, &mut []).unwrap();
+
+    let r: Vec<_> = [o0, o1, o2, o3].iter().map(|&o| read(&mut store, &instance, o, n, single)).collect();
+    for i in 0 .. n {
+        assert_eq!(r[0][i], x[i] * y[i]);
+        assert_eq!(r[1][i], x[i] - 2.0 * y[i]);
+        assert!(close(r[2][i], x[i].cos()), "cos({}) = {} != {}", x[i], r[2][i], x[i].cos());
+        assert_eq!(r[3][i], (y[i] / 3.0).floor());
+    }
+    // the tuple has no scalar export
+    assert!(instance.get_func(&mut store, "f").is_none());
+}
+
+#[test]
+fn wasm_map() {
+    map(Lanes::Scalar, false);
+    map(Lanes::F32, true);
+}
+
+#[cfg(feature="wasm_simd")]
+#[test]
+fn wasm_simd_map() {
+    map(Lanes::F64x2, false);
+    map(Lanes::F32x4, true);
+}